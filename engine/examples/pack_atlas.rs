@@ -0,0 +1,41 @@
+//! Packs PNG images into an atlas, writing `<name>.png` and `<name>.json`.
+//! Frames are named after the files they came from.
+//!
+//! cargo run --example pack_atlas -- <name> <width> <images>...
+use std::path::Path;
+
+use engine::assets::{decode_png, encode_png};
+use engine::renderer::pack;
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 3 {
+        return Err("usage: pack_atlas <name> <width> <images>...".to_string());
+    }
+    let name = &args[0];
+    let width: u32 = args[1]
+        .parse()
+        .map_err(|_| format!("{} isn't a width", args[1]))?;
+
+    let mut sprites = Vec::new();
+    for path in args[2..].iter().map(Path::new) {
+        let frame_name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| format!("can't name a frame after {}", path.display()))?;
+        let bytes =
+            std::fs::read(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let image = decode_png(&bytes).map_err(|error| format!("{}: {}", path.display(), error))?;
+        sprites.push((frame_name.to_string(), image));
+    }
+
+    let texture_name = Path::new(name)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(name);
+    let (image, atlas) = pack(texture_name, &sprites, width)?;
+    std::fs::write(format!("{}.png", name), encode_png(&image)?)
+        .map_err(|error| error.to_string())?;
+    std::fs::write(format!("{}.json", name), atlas.to_json()).map_err(|error| error.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,339 @@
+//! Flips through the frames of a sprite.
+//!
+//! A `Clip` is a sequence of textures, each shown for a while. An `Animator`
+//! component holds the clips of a `GameObject`, plays one of them and switches
+//! between them by its transitions. Like `physics::step`, the engine calls
+//! `step` after every update of a `World` that exposes its `Entities`, rends
+//! draw `Animator::get_texture`.
+//!
+//! Times are in milliseconds.
+use halfbrown::HashMap;
+
+use crate::entity::Entities;
+use crate::renderer::Texture;
+
+/// What a clip does once it gets to its last frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Playback {
+    /// Starts over from the first frame.
+    Loop,
+    /// Stays on the last frame, the clip is then finished.
+    Once,
+    /// Plays backwards to the first frame and forwards again.
+    PingPong,
+}
+
+#[derive(Clone, Debug)]
+struct Keyframe {
+    texture: Texture,
+    duration: f64,
+    event: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Clip {
+    keyframes: Vec<Keyframe>,
+    playback: Playback,
+}
+
+impl Clip {
+    pub fn new(playback: Playback) -> Clip {
+        Clip {
+            keyframes: Vec::new(),
+            playback,
+        }
+    }
+
+    /// Adds a frame shown for `duration` milliseconds.
+    pub fn with_frame(mut self, texture: Texture, duration: f64) -> Clip {
+        assert!(duration > 0.0, "frames must last a while");
+        self.keyframes.push(Keyframe {
+            texture,
+            duration,
+            event: None,
+        });
+        self
+    }
+
+    /// Reports an `AnimationEvent` called `name` whenever the last frame added
+    /// starts showing.
+    pub fn with_event(mut self, name: &str) -> Clip {
+        self.keyframes
+            .last_mut()
+            .expect("events belong to a frame")
+            .event = Some(name.to_string());
+        self
+    }
+
+    pub fn get_playback(&self) -> Playback {
+        self.playback
+    }
+
+    pub fn get_frame_count(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    /// Number of steps before the frames repeat, ping-pong clips don't show
+    /// their first and last frame twice in a row.
+    fn get_cycle_len(&self) -> usize {
+        let len = self.keyframes.len();
+        match self.playback {
+            Playback::PingPong if len > 1 => 2 * len - 2,
+            _ => len,
+        }
+    }
+
+    /// The frame shown at `position` in the cycle.
+    fn get_keyframe(&self, position: usize) -> &Keyframe {
+        let len = self.keyframes.len();
+        if position < len {
+            &self.keyframes[position]
+        } else {
+            &self.keyframes[2 * len - 2 - position]
+        }
+    }
+}
+
+/// When an `Animator` switches from one clip to another.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    /// The clip played once and is on its last frame.
+    Finished,
+    /// The flag is set, see `Animator::set_flag`.
+    Flag(String),
+    /// The flag isn't set.
+    NotFlag(String),
+}
+
+#[derive(Clone, Debug)]
+struct Transition {
+    from: String,
+    to: String,
+    condition: Condition,
+}
+
+/// Reported when a frame with an event starts showing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnimationEvent {
+    pub clip: String,
+    pub name: String,
+}
+
+/// Plays the clips of a `GameObject`, a small state machine with a clip per
+/// state.
+#[derive(Clone, Debug)]
+pub struct Animator {
+    clips: HashMap<String, Clip>,
+    transitions: Vec<Transition>,
+    flags: HashMap<String, bool>,
+
+    clip: String,
+    position: usize,
+    elapsed: f64,
+    /// Whether the current frame was just entered and its event not reported.
+    entered: bool,
+    finished: bool,
+    events: Vec<AnimationEvent>,
+}
+
+impl Animator {
+    /// Plays `clip`, which is called `name`.
+    pub fn new(name: &str, clip: Clip) -> Animator {
+        assert!(clip.get_frame_count() > 0, "clips need frames");
+        let mut clips = HashMap::new();
+        clips.insert(name.to_string(), clip);
+        Animator {
+            clips,
+            transitions: Vec::new(),
+            flags: HashMap::new(),
+
+            clip: name.to_string(),
+            position: 0,
+            elapsed: 0.0,
+            entered: true,
+            finished: false,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn with_clip(mut self, name: &str, clip: Clip) -> Animator {
+        assert!(clip.get_frame_count() > 0, "clips need frames");
+        self.clips.insert(name.to_string(), clip);
+        self
+    }
+
+    /// Switches from the clip `from` to `to` when `condition` holds. The first
+    /// transition added wins if several do.
+    pub fn with_transition(mut self, from: &str, to: &str, condition: Condition) -> Animator {
+        self.transitions.push(Transition {
+            from: from.to_string(),
+            to: to.to_string(),
+            condition,
+        });
+        self
+    }
+
+    pub fn set_flag(&mut self, flag: &str, value: bool) {
+        self.flags.insert(flag.to_string(), value);
+    }
+
+    pub fn get_flag(&self, flag: &str) -> bool {
+        self.flags.get(flag).copied().unwrap_or(false)
+    }
+
+    /// Switches to the clip called `name` from its start, unless it's already
+    /// playing.
+    pub fn play(&mut self, name: &str) -> Result<(), String> {
+        if self.clip == name {
+            return Ok(());
+        }
+        self.restart(name)
+    }
+
+    /// Plays the clip called `name` from its start.
+    pub fn restart(&mut self, name: &str) -> Result<(), String> {
+        if !self.clips.contains_key(name) {
+            return Err(format!("no clip called {}", name));
+        }
+        self.clip = name.to_string();
+        self.position = 0;
+        self.elapsed = 0.0;
+        self.entered = true;
+        self.finished = false;
+        Ok(())
+    }
+
+    pub fn get_clip_name(&self) -> &str {
+        &self.clip
+    }
+
+    /// Whether a clip played once has reached its last frame.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// The texture of the frame showing.
+    pub fn get_texture(&self) -> &Texture {
+        &self.get_keyframe().texture
+    }
+
+    /// Events of the frames that started showing during the last step.
+    pub fn get_events(&self) -> &Vec<AnimationEvent> {
+        &self.events
+    }
+
+    fn get_clip(&self) -> &Clip {
+        &self.clips[&self.clip]
+    }
+
+    fn get_keyframe(&self) -> &Keyframe {
+        self.get_clip().get_keyframe(self.position)
+    }
+
+    fn enter(&mut self) {
+        self.entered = false;
+        if let Some(event) = &self.get_keyframe().event {
+            let event = AnimationEvent {
+                clip: self.clip.clone(),
+                name: event.clone(),
+            };
+            self.events.push(event);
+        }
+    }
+
+    /// Moves on by `dt` milliseconds, going through every frame on the way.
+    fn advance(&mut self, dt: f64) {
+        self.events.clear();
+        if self.entered {
+            self.enter();
+        }
+        self.elapsed += dt;
+        loop {
+            let duration = self.get_keyframe().duration;
+            if self.elapsed < duration || self.finished {
+                break;
+            }
+            let clip = self.get_clip();
+            let cycle_len = clip.get_cycle_len();
+            if clip.get_playback() == Playback::Once && self.position + 1 == cycle_len {
+                self.finished = true;
+                break;
+            }
+            self.elapsed -= duration;
+            self.position = (self.position + 1) % cycle_len;
+            self.enter();
+        }
+
+        let transition = self.transitions.iter().find(|transition| {
+            transition.from == self.clip
+                && match &transition.condition {
+                    Condition::Finished => self.finished,
+                    Condition::Flag(flag) => self.get_flag(flag),
+                    Condition::NotFlag(flag) => !self.get_flag(flag),
+                }
+        });
+        if let Some(transition) = transition {
+            let to = transition.to.clone();
+            self.restart(&to).unwrap();
+        }
+    }
+}
+
+/// Moves every `Animator` forward by `dt` milliseconds.
+pub fn step(entities: &mut Entities, dt: f64) {
+    // Animating doesn't move anything, so this skips marking entities as moved
+    for (_, game_object) in entities.query_in_place_mut::<Animator>() {
+        if let Some(animator) = game_object.get_component_mut::<Animator>() {
+            animator.advance(dt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::TextureMap;
+
+    fn clip(playback: Playback, frames: usize) -> Clip {
+        let sheet = TextureMap::new(4, 1, "sheet".to_string());
+        (0..frames).fold(Clip::new(playback), |clip, column| {
+            clip.with_frame(sheet.get_texture(column as i32, 0), 100.0)
+                .with_event(&column.to_string())
+        })
+    }
+
+    fn event_names(animator: &Animator) -> Vec<&str> {
+        animator
+            .get_events()
+            .iter()
+            .map(|event| event.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn plays_clips_and_switches_between_them() {
+        let mut animator = Animator::new("walk", clip(Playback::PingPong, 3))
+            .with_clip("chop", clip(Playback::Once, 2))
+            .with_clip("idle", clip(Playback::Loop, 1))
+            .with_transition("chop", "idle", Condition::Finished)
+            .with_transition("walk", "chop", Condition::Flag("chopping".to_string()));
+
+        animator.advance(50.0);
+        assert_eq!(event_names(&animator), ["0"]);
+        // Every frame passed on the way is reported
+        animator.advance(300.0);
+        assert_eq!(event_names(&animator), ["1", "2", "1"]);
+        animator.advance(100.0);
+        assert_eq!(event_names(&animator), ["0"]);
+
+        animator.set_flag("chopping", true);
+        animator.advance(0.0);
+        assert_eq!(animator.get_clip_name(), "chop");
+        animator.advance(150.0);
+        assert_eq!(event_names(&animator), ["0", "1"]);
+        assert!(!animator.is_finished());
+        animator.advance(100.0);
+        assert_eq!(animator.get_clip_name(), "idle");
+        assert!(animator.play("run").is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! Loads the images textures are made of, from PNG bytes or URLs.
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use halfbrown::HashMap;
+
+use crate::renderer::Image;
+
+/// Name of the texture drawn in place of textures that aren't loaded.
+pub const PLACEHOLDER: &str = "placeholder";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    Failed(String),
+}
+
+/// Loads that finished in the background, waiting for `Assets::take_loaded`.
+type Inbox = Rc<RefCell<Vec<(String, Result<Image, String>)>>>;
+
+/// Images by texture name, with how far along loading each one is.
+///
+/// The `Renderer` owns one and uploads whatever finished loading to its
+/// backend at every flush. Until then, and if loading fails, textures loaded
+/// here are drawn with the placeholder instead. The WebGL backend also draws
+/// the placeholder for textures that were never loaded here.
+pub struct Assets {
+    states: HashMap<String, LoadState>,
+    loaded: Vec<(String, Image)>,
+    inbox: Inbox,
+}
+
+impl Default for Assets {
+    fn default() -> Assets {
+        Assets::new()
+    }
+}
+
+impl Assets {
+    /// No images yet, with a transparent placeholder.
+    pub fn new() -> Assets {
+        let mut assets = Assets {
+            states: HashMap::new(),
+            loaded: Vec::new(),
+            inbox: Rc::new(RefCell::new(Vec::new())),
+        };
+        assets.set_placeholder(Image::filled(1, 1, [0, 0, 0, 0]));
+        assets
+    }
+
+    /// Replaces the image drawn for textures that are loading or failed to load.
+    pub fn set_placeholder(&mut self, image: Image) {
+        self.loaded.push((PLACEHOLDER.to_string(), image));
+    }
+
+    /// Decodes a PNG image for `name`, replacing any image it had.
+    pub fn load_bytes(&mut self, name: &str, bytes: &[u8]) -> Result<(), String> {
+        self.finish(name, decode_png(bytes))
+    }
+
+    /// Starts downloading a PNG image for `name`, it's drawn with the
+    /// placeholder until it has loaded. Only works in the browser, elsewhere the
+    /// load fails.
+    pub fn load_url(&mut self, name: &str, url: &str) {
+        self.states.insert(name.to_string(), LoadState::Loading);
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            let inbox = self.inbox.clone();
+            let (name, url) = (name.to_string(), url.to_string());
+            wasm_bindgen_futures::spawn_local(async move {
+                let result = match fetch(&url).await {
+                    Ok(bytes) => decode_png(&bytes),
+                    Err(error) => Err(error),
+                };
+                inbox.borrow_mut().push((name, result));
+            });
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        self.inbox.borrow_mut().push((
+            name.to_string(),
+            Err(format!("can't fetch {} outside the browser", url)),
+        ));
+    }
+
+    /// `None` if nothing was loaded for `name`.
+    pub fn get_state(&self, name: &str) -> Option<&LoadState> {
+        self.states.get(name)
+    }
+
+    /// Whether any downloads haven't finished yet.
+    pub fn is_loading(&self) -> bool {
+        self.states
+            .values()
+            .any(|state| *state == LoadState::Loading)
+    }
+
+    /// The texture to draw for `name`, which is the placeholder while an image
+    /// for it is loading or if it failed to load.
+    pub fn resolve<'a>(&self, name: &'a str) -> &'a str {
+        match self.states.get(name) {
+            Some(LoadState::Loading) | Some(LoadState::Failed(_)) => PLACEHOLDER,
+            _ => name,
+        }
+    }
+
+    /// Returns the images that finished loading since the last call, picking up
+    /// downloads that completed in the meantime.
+    pub fn take_loaded(&mut self) -> Vec<(String, Image)> {
+        let finished = std::mem::take(&mut *self.inbox.borrow_mut());
+        for (name, result) in finished {
+            if let Err(error) = self.finish(&name, result) {
+                log::error!("Failed to load {}: {}", name, error);
+            }
+        }
+        std::mem::take(&mut self.loaded)
+    }
+
+    fn finish(&mut self, name: &str, result: Result<Image, String>) -> Result<(), String> {
+        match result {
+            Ok(image) => {
+                self.states.insert(name.to_string(), LoadState::Loaded);
+                self.loaded.push((name.to_string(), image));
+                Ok(())
+            }
+            Err(error) => {
+                self.states
+                    .insert(name.to_string(), LoadState::Failed(error.clone()));
+                Err(error)
+            }
+        }
+    }
+}
+
+/// Decodes a PNG image of any colour type into 8 bit RGBA.
+pub fn decode_png(bytes: &[u8]) -> Result<Image, String> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|error| error.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|error| error.to_string())?;
+
+    let pixels = &buffer[..info.buffer_size()];
+    let pixels: Vec<u8> = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|g| [*g, *g, *g, 255]).collect(),
+        png::ColorType::Indexed => return Err("palette wasn't expanded".to_string()),
+    };
+    Ok(Image::new(
+        info.width as usize,
+        info.height as usize,
+        pixels,
+    ))
+}
+
+/// Encodes an image as an 8 bit RGBA PNG.
+pub fn encode_png(image: &Image) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&image.pixels))
+        .map_err(|error| error.to_string())?;
+    Ok(bytes)
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+async fn fetch(url: &str) -> Result<Vec<u8>, String> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let window = web_sys::window().ok_or("no window to fetch from")?;
+    let response = JsFuture::from(window.fetch_with_str(url))
+        .await
+        .map_err(|error| format!("can't fetch {}: {:?}", url, error))?
+        .dyn_into::<web_sys::Response>()
+        .map_err(|_| format!("fetching {} didn't give a response", url))?;
+    if !response.ok() {
+        return Err(format!("{} responded with {}", url, response.status()));
+    }
+    let buffer = response
+        .array_buffer()
+        .map_err(|error| format!("can't read {}: {:?}", url, error))?;
+    let buffer = JsFuture::from(buffer)
+        .await
+        .map_err(|error| format!("can't read {}: {:?}", url, error))?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_with_color(
+        width: u32,
+        height: u32,
+        color_type: png::ColorType,
+        pixels: &[u8],
+    ) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(pixels)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn decodes_images_and_tracks_failures() {
+        let mut assets = Assets::new();
+        let rgb = encode_with_color(2, 1, png::ColorType::Rgb, &[255, 0, 0, 0, 0, 255]);
+        assert_eq!(assets.load_bytes("flag", &rgb), Ok(()));
+        assert!(assets.load_bytes("broken", &[1, 2, 3]).is_err());
+        assets.load_url("remote", "images/remote.png");
+
+        assert_eq!(assets.get_state("flag"), Some(&LoadState::Loaded));
+        assert!(matches!(
+            assets.get_state("broken"),
+            Some(LoadState::Failed(_))
+        ));
+        assert_eq!(assets.get_state("remote"), Some(&LoadState::Loading));
+        assert!(assets.is_loading());
+        assert_eq!(assets.resolve("remote"), PLACEHOLDER);
+        assert_eq!(assets.resolve("flag"), "flag");
+        assert_eq!(assets.resolve("elsewhere"), "elsewhere");
+
+        let loaded = assets.take_loaded();
+        let names: Vec<&str> = loaded.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, [PLACEHOLDER, "flag"]);
+        assert_eq!(
+            loaded[1].1,
+            Image::new(2, 1, vec![255, 0, 0, 255, 0, 0, 255, 255])
+        );
+        // Outside the browser the download fails
+        assert!(matches!(
+            assets.get_state("remote"),
+            Some(LoadState::Failed(_))
+        ));
+        assert!(assets.take_loaded().is_empty());
+    }
+}
@@ -0,0 +1,510 @@
+//! Collision shapes and contacts between them.
+//!
+//! Every shape is a core, a point, a segment or a box, grown by a radius. Two
+//! shapes touch when their cores are closer than the sum of their radii, which
+//! keeps the maths down to finding the closest points between the cores.
+//!
+//! `Entities::update_contacts` turns overlaps into `CollisionEvent`s, which the
+//! engine does after every update of a `World` that exposes its `Entities`.
+use crate::entity::Entity;
+
+/// Layers a `Collider` is in when none are given.
+pub const DEFAULT_LAYERS: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    Circle {
+        radius: f32,
+    },
+    /// A box aligned with the world axes.
+    Aabb {
+        half_extents: na::Vector2<f32>,
+    },
+    /// A segment from `-half_segment` to `half_segment` around the position,
+    /// grown by `radius`.
+    Capsule {
+        half_segment: na::Vector2<f32>,
+        radius: f32,
+    },
+}
+
+/// The shape of a `GameObject` for collisions, centred on its position.
+///
+/// A collider is in `layers` and collides with colliders in any of the layers
+/// in its `mask`, see `blocked_by`. Triggers never block anything, they only
+/// report overlaps.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Collider {
+    shape: Shape,
+    layers: u32,
+    mask: u32,
+    trigger: bool,
+}
+
+/// How far a collider sank into another one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+    /// Unit vector pointing out of the other collider.
+    pub normal: na::Vector2<f32>,
+    /// Distance to move along `normal` to stop touching.
+    pub depth: f32,
+}
+
+enum Core {
+    Segment(na::Point2<f32>, na::Point2<f32>),
+    Box(na::Point2<f32>, na::Vector2<f32>),
+}
+
+impl Collider {
+    /// A circle of `range` in the default layers.
+    pub fn new(range: f32) -> Collider {
+        Collider::circle(range)
+    }
+
+    pub fn circle(radius: f32) -> Collider {
+        Collider::with_shape(Shape::Circle { radius })
+    }
+
+    pub fn aabb(half_extents: na::Vector2<f32>) -> Collider {
+        Collider::with_shape(Shape::Aabb { half_extents })
+    }
+
+    pub fn capsule(half_segment: na::Vector2<f32>, radius: f32) -> Collider {
+        Collider::with_shape(Shape::Capsule {
+            half_segment,
+            radius,
+        })
+    }
+
+    pub fn with_shape(shape: Shape) -> Collider {
+        Collider {
+            shape,
+            layers: DEFAULT_LAYERS,
+            mask: !0,
+            trigger: false,
+        }
+    }
+
+    /// Turns the collider into a trigger.
+    pub fn as_trigger(mut self) -> Collider {
+        self.trigger = true;
+        self
+    }
+
+    pub fn is_trigger(&self) -> bool {
+        self.trigger
+    }
+
+    /// Puts the collider in the given layers, a bit per layer.
+    pub fn with_layers(mut self, layers: u32) -> Collider {
+        self.layers = layers;
+        self
+    }
+
+    /// Makes the collider collide only with colliders in the given layers.
+    pub fn with_mask(mut self, mask: u32) -> Collider {
+        self.mask = mask;
+        self
+    }
+
+    pub fn get_shape(&self) -> Shape {
+        self.shape
+    }
+
+    pub fn get_layers(&self) -> u32 {
+        self.layers
+    }
+
+    pub fn get_mask(&self) -> u32 {
+        self.mask
+    }
+
+    /// Radius of a circle around the position that contains the whole shape.
+    pub fn get_range(&self) -> f32 {
+        match self.shape {
+            Shape::Circle { radius } => radius,
+            Shape::Aabb { half_extents } => half_extents.norm(),
+            Shape::Capsule {
+                half_segment,
+                radius,
+            } => half_segment.norm() + radius,
+        }
+    }
+
+    /// Returns true if this collider should be pushed out of `other`.
+    pub fn blocked_by(&self, other: &Collider) -> bool {
+        !self.trigger && !other.trigger && self.mask & other.layers != 0
+    }
+
+    /// Returns true if either collider's mask includes the other's layers, so
+    /// overlaps between them are reported.
+    pub fn interacts_with(&self, other: &Collider) -> bool {
+        self.mask & other.layers != 0 || other.mask & self.layers != 0
+    }
+
+    fn core(&self, pos: na::Point2<f32>) -> (Core, f32) {
+        match self.shape {
+            Shape::Circle { radius } => (Core::Segment(pos, pos), radius),
+            Shape::Aabb { half_extents } => (Core::Box(pos, half_extents), 0.0),
+            Shape::Capsule {
+                half_segment,
+                radius,
+            } => (
+                Core::Segment(pos - half_segment, pos + half_segment),
+                radius,
+            ),
+        }
+    }
+
+    /// Finds how this collider at `pos` overlaps `other` at `other_pos`,
+    /// regardless of layers. Shapes that only touch don't overlap.
+    pub fn contact(
+        &self,
+        pos: na::Point2<f32>,
+        other: &Collider,
+        other_pos: na::Point2<f32>,
+    ) -> Option<Contact> {
+        let (core, radius) = self.core(pos);
+        let (other_core, other_radius) = other.core(other_pos);
+        let radius = radius + other_radius;
+        let fallback = pos - other_pos;
+        match (core, other_core) {
+            (Core::Segment(a, b), Core::Segment(c, d)) => {
+                let (p, q) = closest_between_segments(a, b, c, d);
+                round_contact(p - q, radius, fallback)
+            }
+            (Core::Segment(a, b), Core::Box(center, half_extents)) => {
+                segment_box_contact(a, b, radius, center, half_extents)
+            }
+            (Core::Box(center, half_extents), Core::Segment(a, b)) => {
+                segment_box_contact(a, b, radius, center, half_extents).map(|contact| Contact {
+                    normal: -contact.normal,
+                    ..contact
+                })
+            }
+            (Core::Box(center, half_extents), Core::Box(other_center, other_half_extents)) => {
+                box_contact(center - other_center, half_extents + other_half_extents)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// The colliders started overlapping this update.
+    Enter,
+    /// The colliders kept overlapping.
+    Stay,
+    /// The colliders stopped overlapping, or one of them was despawned.
+    Exit,
+}
+
+/// Two colliders overlapping, reported once per update.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CollisionEvent {
+    /// The entity with the lower handle.
+    pub a: Entity,
+    pub b: Entity,
+    pub phase: Phase,
+    /// True if either collider is a trigger.
+    pub trigger: bool,
+}
+
+impl CollisionEvent {
+    /// Returns the entity `entity` collided with, if it's part of the event.
+    pub fn other(&self, entity: Entity) -> Option<Entity> {
+        if self.a == entity {
+            Some(self.b)
+        } else if self.b == entity {
+            Some(self.a)
+        } else {
+            None
+        }
+    }
+}
+
+impl Contact {
+    /// Moves `pos` out of the other collider and removes the part of `speed`
+    /// going into it, so what's left slides along the surface.
+    pub fn resolve(&self, pos: &mut na::Point2<f32>, speed: &mut na::Vector2<f32>) {
+        *pos += self.normal * self.depth;
+        let into = speed.dot(&self.normal);
+        if into < 0.0 {
+            *speed -= self.normal * into;
+        }
+    }
+}
+
+fn closest_on_segment(
+    a: na::Point2<f32>,
+    b: na::Point2<f32>,
+    p: na::Point2<f32>,
+) -> na::Point2<f32> {
+    let ab = b - a;
+    let length_squared = ab.norm_squared();
+    if length_squared == 0.0 {
+        return a;
+    }
+    let t = ((p - a).dot(&ab) / length_squared).clamp(0.0, 1.0);
+    a + ab * t
+}
+
+fn cross(a: na::Vector2<f32>, b: na::Vector2<f32>) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/// Closest points on segments `ab` and `cd`, the first on `ab`.
+fn closest_between_segments(
+    a: na::Point2<f32>,
+    b: na::Point2<f32>,
+    c: na::Point2<f32>,
+    d: na::Point2<f32>,
+) -> (na::Point2<f32>, na::Point2<f32>) {
+    let ab = b - a;
+    let cd = d - c;
+    let denominator = cross(ab, cd);
+    if denominator != 0.0 {
+        let t = cross(c - a, cd) / denominator;
+        let u = cross(c - a, ab) / denominator;
+        if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+            let crossing = a + ab * t;
+            return (crossing, crossing);
+        }
+    }
+    let candidates = [
+        (a, closest_on_segment(c, d, a)),
+        (b, closest_on_segment(c, d, b)),
+        (closest_on_segment(a, b, c), c),
+        (closest_on_segment(a, b, d), d),
+    ];
+    let mut closest = candidates[0];
+    for candidate in candidates.iter().skip(1) {
+        if na::distance_squared(&candidate.0, &candidate.1)
+            < na::distance_squared(&closest.0, &closest.1)
+        {
+            closest = *candidate;
+        }
+    }
+    closest
+}
+
+/// Contact between round cores whose closest points are `offset` apart.
+/// `fallback` gives the direction when the cores cross each other.
+fn round_contact(
+    offset: na::Vector2<f32>,
+    radius: f32,
+    fallback: na::Vector2<f32>,
+) -> Option<Contact> {
+    let distance = offset.norm();
+    if distance >= radius {
+        return None;
+    }
+    let normal = if distance > f32::EPSILON {
+        offset / distance
+    } else if fallback.norm() > f32::EPSILON {
+        fallback.normalize()
+    } else {
+        na::Vector2::new(0.0, 1.0)
+    };
+    Some(Contact {
+        normal,
+        depth: radius - distance,
+    })
+}
+
+fn clamp_to_box(
+    p: na::Point2<f32>,
+    center: na::Point2<f32>,
+    half_extents: na::Vector2<f32>,
+) -> na::Point2<f32> {
+    na::Point2::new(
+        p.x.max(center.x - half_extents.x)
+            .min(center.x + half_extents.x),
+        p.y.max(center.y - half_extents.y)
+            .min(center.y + half_extents.y),
+    )
+}
+
+/// Contact of segment `ab` grown by `radius` against a box, the normal points
+/// out of the box.
+fn segment_box_contact(
+    a: na::Point2<f32>,
+    b: na::Point2<f32>,
+    radius: f32,
+    center: na::Point2<f32>,
+    half_extents: na::Vector2<f32>,
+) -> Option<Contact> {
+    // The closest points are at an end of the segment or a corner of the box
+    let corners = [
+        center + na::Vector2::new(-half_extents.x, -half_extents.y),
+        center + na::Vector2::new(half_extents.x, -half_extents.y),
+        center + na::Vector2::new(half_extents.x, half_extents.y),
+        center + na::Vector2::new(-half_extents.x, half_extents.y),
+    ];
+    let mut offset: Option<na::Vector2<f32>> = None;
+    let mut crossing = false;
+    for (i, corner) in corners.iter().enumerate() {
+        let next = corners[(i + 1) % corners.len()];
+        let (p, q) = closest_between_segments(a, b, *corner, next);
+        if p == q {
+            crossing = true;
+        }
+    }
+    let inside = |p: na::Point2<f32>| clamp_to_box(p, center, half_extents) == p;
+    if !crossing && !inside(a) {
+        for p in [a, b].iter().copied().chain(
+            corners
+                .iter()
+                .map(|corner| closest_on_segment(a, b, *corner)),
+        ) {
+            let candidate = p - clamp_to_box(p, center, half_extents);
+            if offset.is_none_or(|offset| candidate.norm() < offset.norm()) {
+                offset = Some(candidate);
+            }
+        }
+    }
+    match offset {
+        Some(offset) if offset.norm() > f32::EPSILON => {
+            round_contact(offset, radius, na::Vector2::zeros())
+        }
+        _ => {
+            // Deep inside, push out along the axis that needs the least movement
+            let p = closest_on_segment(a, b, center);
+            box_contact(p - center, half_extents + na::Vector2::new(radius, radius))
+        }
+    }
+}
+
+/// Contact of a point `offset` from the centre of a box with `half_extents`.
+fn box_contact(offset: na::Vector2<f32>, half_extents: na::Vector2<f32>) -> Option<Contact> {
+    let overlap_x = half_extents.x - offset.x.abs();
+    let overlap_y = half_extents.y - offset.y.abs();
+    if overlap_x <= 0.0 || overlap_y <= 0.0 {
+        return None;
+    }
+    if overlap_x < overlap_y {
+        Some(Contact {
+            normal: na::Vector2::new(offset.x.signum(), 0.0),
+            depth: overlap_x,
+        })
+    } else {
+        Some(Contact {
+            normal: na::Vector2::new(0.0, offset.y.signum()),
+            depth: overlap_y,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_contact(contact: Option<Contact>, normal: (f32, f32), depth: f32) {
+        let contact = contact.expect("no contact");
+        assert!(
+            (contact.normal - na::Vector2::new(normal.0, normal.1)).norm() < 1e-4,
+            "normal {:?}",
+            contact.normal
+        );
+        assert!(
+            (contact.depth - depth).abs() < 1e-4,
+            "depth {}",
+            contact.depth
+        );
+    }
+
+    #[test]
+    fn finds_contacts_between_shapes() {
+        let origin = na::Point2::new(0.0, 0.0);
+        let circle = Collider::circle(10.0);
+        let wall = Collider::aabb(na::Vector2::new(5.0, 50.0));
+        let capsule = Collider::capsule(na::Vector2::new(0.0, 20.0), 4.0);
+
+        assert_contact(
+            circle.contact(na::Point2::new(15.0, 0.0), &circle, origin),
+            (1.0, 0.0),
+            5.0,
+        );
+        assert_eq!(
+            circle.contact(na::Point2::new(20.0, 0.0), &circle, origin),
+            None
+        );
+        assert_contact(
+            circle.contact(na::Point2::new(12.0, 10.0), &wall, origin),
+            (1.0, 0.0),
+            3.0,
+        );
+        assert_contact(
+            wall.contact(origin, &circle, na::Point2::new(12.0, 10.0)),
+            (-1.0, 0.0),
+            3.0,
+        );
+        // Past the corner, the circle is pushed away from the corner
+        assert_contact(
+            circle.contact(na::Point2::new(8.0, 54.0), &wall, origin),
+            (0.6, 0.8),
+            5.0,
+        );
+        assert_contact(
+            circle.contact(na::Point2::new(2.0, -1.0), &wall, origin),
+            (1.0, 0.0),
+            13.0,
+        );
+        assert_contact(
+            wall.contact(na::Point2::new(2.0, 95.0), &wall, origin),
+            (0.0, 1.0),
+            5.0,
+        );
+        assert_contact(
+            capsule.contact(na::Point2::new(10.0, 20.0), &circle, origin),
+            (1.0, 0.0),
+            4.0,
+        );
+        assert_contact(
+            capsule.contact(
+                na::Point2::new(0.0, 30.0),
+                &capsule,
+                na::Point2::new(0.0, -10.0),
+            ),
+            (0.0, 1.0),
+            8.0,
+        );
+    }
+
+    #[test]
+    fn slides_along_surfaces() {
+        let mut pos = na::Point2::new(12.0, 10.0);
+        let mut speed = na::Vector2::new(-3.0, 2.0);
+        let contact = Collider::circle(10.0)
+            .contact(
+                pos,
+                &Collider::aabb(na::Vector2::new(5.0, 50.0)),
+                na::Point2::new(0.0, 0.0),
+            )
+            .unwrap();
+        contact.resolve(&mut pos, &mut speed);
+        assert_eq!(pos, na::Point2::new(15.0, 10.0));
+        assert_eq!(speed, na::Vector2::new(0.0, 2.0));
+
+        // Moving away already, the speed is left alone
+        let mut speed = na::Vector2::new(3.0, 2.0);
+        contact.resolve(&mut pos, &mut speed);
+        assert_eq!(speed, na::Vector2::new(3.0, 2.0));
+    }
+
+    #[test]
+    fn masks_pick_what_blocks() {
+        const SOLID: u32 = 1;
+        const ITEM: u32 = 2;
+        let tree = Collider::new(17.0).with_layers(SOLID);
+        let log = Collider::new(16.0).with_layers(ITEM);
+        let player = Collider::new(16.0).with_layers(0).with_mask(SOLID);
+        assert!(player.blocked_by(&tree));
+        assert!(!player.blocked_by(&log));
+        assert!(player.with_mask(SOLID | ITEM).blocked_by(&log));
+        assert!(!tree.blocked_by(&player));
+        assert!(tree.interacts_with(&player));
+        assert!(!log.interacts_with(&player));
+        assert!(!player.blocked_by(&tree.as_trigger()));
+    }
+}
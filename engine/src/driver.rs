@@ -0,0 +1,98 @@
+//! Drivers decide when an `Engine` runs a frame and with which timestamp.
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::key::replay::Playback;
+use crate::Engine;
+
+/// Runs the engine on `requestAnimationFrame`, sizing the viewport after the canvas.
+pub struct BrowserDriver {
+    canvas: web_sys::HtmlCanvasElement,
+}
+
+impl BrowserDriver {
+    pub fn new(canvas: web_sys::HtmlCanvasElement) -> BrowserDriver {
+        BrowserDriver { canvas }
+    }
+
+    /// Hands the engine over to the browser, it will keep running for the lifetime of the page.
+    pub fn run(self, mut engine: Engine) {
+        let canvas = self.canvas;
+
+        let f = Rc::new(RefCell::new(None));
+        let g = f.clone();
+
+        *g.borrow_mut() = Some(Closure::wrap(Box::new(move |timestamp: f64| {
+            let viewport = na::Vector2::new(canvas.width() as f32, canvas.height() as f32);
+            // log::debug!("{:?}: {}", viewport, timestamp);
+            engine.frame(timestamp, viewport);
+
+            request_animation_frame(f.borrow().as_ref().unwrap());
+        }) as Box<dyn FnMut(f64)>));
+
+        request_animation_frame(g.borrow().as_ref().unwrap());
+    }
+}
+
+fn window() -> web_sys::Window {
+    web_sys::window().expect("no global `window` exists")
+}
+
+fn request_animation_frame(f: &Closure<dyn FnMut(f64)>) {
+    window()
+        .request_animation_frame(f.as_ref().unchecked_ref())
+        .expect("should register `requestAnimationFrame` OK");
+}
+
+/// Runs the engine without a browser, with timestamps supplied by the caller.
+///
+/// Useful for simulating thousands of frames in tests, pair it with a
+/// `renderer::SoftwareBackend` and `KeyManager::headless`.
+pub struct NativeDriver {
+    timestamp: f64,
+    frame_time: f64,
+    viewport: na::Vector2<f32>,
+}
+
+impl NativeDriver {
+    /// `frame_time` is the number of milliseconds `step` advances the clock by.
+    pub fn new(frame_time: f64, viewport: na::Vector2<f32>) -> NativeDriver {
+        NativeDriver {
+            timestamp: 0.0,
+            frame_time,
+            viewport,
+        }
+    }
+
+    pub fn get_timestamp(&self) -> f64 {
+        self.timestamp
+    }
+
+    /// Runs a single frame at the given timestamp.
+    pub fn step_to(&mut self, engine: &mut Engine, timestamp: f64) {
+        self.timestamp = timestamp;
+        engine.frame(timestamp, self.viewport);
+    }
+
+    /// Advances the clock by one frame time and runs a frame.
+    pub fn step(&mut self, engine: &mut Engine) {
+        self.step_to(engine, self.timestamp + self.frame_time);
+    }
+
+    /// Runs the given number of frames back to back.
+    pub fn run(&mut self, engine: &mut Engine, frames: usize) {
+        for _ in 0..frames {
+            self.step(engine);
+        }
+    }
+
+    /// Runs every frame of a recording, with the recorded key states and timestamps.
+    pub fn replay(&mut self, engine: &mut Engine, mut playback: Playback) {
+        while let Some(timestamp) = playback.next_frame(engine.get_key_manager_mut()) {
+            self.step_to(engine, timestamp);
+        }
+    }
+}
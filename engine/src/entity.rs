@@ -0,0 +1,1107 @@
+//! Entities and their typed components.
+//!
+//! Every `GameObject` carries a set of components, at most one of each type.
+//! Components are plain structs, anything `'static` will do:
+//!
+//! ```ignore
+//! struct Heat(f32);
+//!
+//! let mut entities = Entities::new();
+//! let mut fire = GameObject::new(na::Point2::new(0.0, 0.0));
+//! fire.add_component(Heat(1.0));
+//! let fire = entities.spawn(fire);
+//!
+//! for (entity, game_object, heat) in entities.query::<Heat>() {
+//!     log::debug!("{:?} at {} has heat {}", entity, game_object.pos, heat.0);
+//! }
+//! ```
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, BTreeSet};
+
+use halfbrown::HashMap;
+
+use crate::collision::{CollisionEvent, Phase};
+use crate::spatial::{SpatialHash, DEFAULT_CELL_SIZE};
+use crate::transform::Transform;
+use crate::GameObject;
+
+/// Identifies a `GameObject` spawned into `Entities`.
+///
+/// Indices are reused after a despawn, but with a new generation, so a handle
+/// to a despawned entity never finds the one that took its place.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    pub fn get_index(self) -> u32 {
+        self.index
+    }
+
+    pub fn get_generation(self) -> u32 {
+        self.generation
+    }
+}
+
+/// Hands out entity handles, reusing the indices of despawned entities.
+#[derive(Default)]
+struct Allocator {
+    generations: Vec<u32>,
+    free: Vec<u32>,
+}
+
+impl Allocator {
+    fn allocate(&mut self) -> Entity {
+        match self.free.pop() {
+            Some(index) => Entity {
+                index,
+                generation: self.generations[index as usize],
+            },
+            None => {
+                self.generations.push(0);
+                Entity {
+                    index: self.generations.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    fn free(&mut self, entity: Entity) {
+        self.generations[entity.index as usize] += 1;
+        self.free.push(entity.index);
+    }
+}
+
+/// Spawns and despawns queued with `Commands`, applied by `apply_commands`.
+#[derive(Default)]
+struct Queue {
+    spawns: Vec<(Entity, GameObject)>,
+    despawns: Vec<Entity>,
+}
+
+/// Queues spawns and despawns while `Entities` is busy, e.g. in `for_each_mut`.
+///
+/// Spawned entities get their handle right away, but only show up in
+/// `Entities` once the queue is applied. The engine applies it after every
+/// update.
+pub struct Commands<'a> {
+    allocator: &'a mut Allocator,
+    queue: &'a mut Queue,
+}
+
+impl<'a> Commands<'a> {
+    pub fn spawn(&mut self, game_object: GameObject) -> Entity {
+        let entity = self.allocator.allocate();
+        self.queue.spawns.push((entity, game_object));
+        entity
+    }
+
+    pub fn despawn(&mut self, entity: Entity) {
+        self.queue.despawns.push(entity);
+    }
+}
+
+/// The components of a single `GameObject`, keyed by their type.
+#[derive(Default)]
+pub struct Components {
+    components: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl Components {
+    pub fn new() -> Components {
+        Components::default()
+    }
+
+    /// Adds a component, returning the one of the same type it replaced.
+    pub fn insert<C: 'static>(&mut self, component: C) -> Option<C> {
+        self.components
+            .insert(TypeId::of::<C>(), Box::new(component))
+            .and_then(|previous| previous.downcast().ok())
+            .map(|previous| *previous)
+    }
+
+    pub fn remove<C: 'static>(&mut self) -> Option<C> {
+        self.components
+            .remove(&TypeId::of::<C>())
+            .and_then(|component| component.downcast().ok())
+            .map(|component| *component)
+    }
+
+    pub fn get<C: 'static>(&self) -> Option<&C> {
+        self.components
+            .get(&TypeId::of::<C>())
+            .and_then(|component| component.downcast_ref())
+    }
+
+    pub fn get_mut<C: 'static>(&mut self) -> Option<&mut C> {
+        self.components
+            .get_mut(&TypeId::of::<C>())
+            .and_then(|component| component.downcast_mut())
+    }
+
+    pub fn contains<C: 'static>(&self) -> bool {
+        self.components.contains_key(&TypeId::of::<C>())
+    }
+
+    /// The types of all components.
+    pub fn type_ids(&self) -> impl Iterator<Item = TypeId> + '_ {
+        self.components.keys().copied()
+    }
+}
+
+/// Owns the `GameObject`s of a world and hands out an `Entity` for each.
+///
+/// Iteration and queries visit entities in the order of their handles, so
+/// running the same updates always visits them in the same order.
+///
+/// Entities can be given a name to look them up by, see `set_name`.
+///
+/// Entities are indexed by the types of their components, so queries only
+/// visit the entities that have the component asked for. Components added or
+/// removed through `add_component` and `remove_component` are indexed right
+/// away. A `GameObject` handed out mutably is indexed again along with its
+/// position, until then queries check it whatever it had.
+///
+/// Entities with a collider are kept in a `SpatialHash`, see `colliders_within`.
+/// Anything handed out mutably may have moved, so it's marked and brought up
+/// to date in the hash before the next spatial query. The exceptions are
+/// `get_game_objects_mut`, which is there for the engine's own bookkeeping, and
+/// `query_in_place_mut`.
+///
+/// Entities are also kept in a second `SpatialHash` by what they draw, see
+/// `visible_within`.
+///
+/// Only entities that may have moved are checked for new overlaps by
+/// `update_contacts`, overlaps between the rest carry over as they were.
+///
+/// An entity can be the child of another one, see `set_parent`. Children are
+/// despawned with their parent.
+pub struct Entities {
+    allocator: Allocator,
+    queue: Queue,
+    names: HashMap<String, Entity>,
+    names_by_entity: BTreeMap<Entity, String>,
+    /// The `GameObject` of every entity alive by its index.
+    game_objects: Vec<Option<GameObject>>,
+    len: usize,
+    /// The entities with a component of each type.
+    by_component: HashMap<TypeId, BTreeSet<Entity>>,
+    /// The component types each entity is in `by_component` for.
+    indexed_components: BTreeMap<Entity, Vec<TypeId>>,
+    /// Entities that may have moved since `save_previous_positions`.
+    unsettled: BTreeSet<Entity>,
+    colliders: SpatialHash,
+    visuals: SpatialHash,
+    unbounded: BTreeSet<Entity>,
+    moved: BTreeSet<Entity>,
+    awake: BTreeSet<Entity>,
+    overlaps: BTreeMap<(Entity, Entity), bool>,
+    collision_events: Vec<CollisionEvent>,
+    parents: BTreeMap<Entity, (Entity, Transform)>,
+    children: BTreeMap<Entity, Vec<Entity>>,
+}
+
+impl Default for Entities {
+    fn default() -> Entities {
+        Entities::with_cell_size(DEFAULT_CELL_SIZE)
+    }
+}
+
+impl Entities {
+    pub fn new() -> Entities {
+        Entities::default()
+    }
+
+    /// Uses cells of `cell_size` world units for the spatial index.
+    pub fn with_cell_size(cell_size: f32) -> Entities {
+        Entities {
+            allocator: Allocator::default(),
+            queue: Queue::default(),
+            names: HashMap::new(),
+            names_by_entity: BTreeMap::new(),
+            game_objects: Vec::new(),
+            len: 0,
+            by_component: HashMap::new(),
+            indexed_components: BTreeMap::new(),
+            unsettled: BTreeSet::new(),
+            colliders: SpatialHash::new(cell_size),
+            visuals: SpatialHash::new(cell_size),
+            unbounded: BTreeSet::new(),
+            moved: BTreeSet::new(),
+            awake: BTreeSet::new(),
+            overlaps: BTreeMap::new(),
+            collision_events: Vec::new(),
+            parents: BTreeMap::new(),
+            children: BTreeMap::new(),
+        }
+    }
+
+    pub fn spawn(&mut self, game_object: GameObject) -> Entity {
+        let entity = self.allocator.allocate();
+        self.insert(entity, game_object);
+        entity
+    }
+
+    fn insert(&mut self, entity: Entity, game_object: GameObject) {
+        let index = entity.index as usize;
+        if self.game_objects.len() <= index {
+            self.game_objects.resize_with(index + 1, || None);
+        }
+        self.game_objects[index] = Some(game_object);
+        self.len += 1;
+        self.reindex(entity);
+    }
+
+    /// Removes an entity and its children, returning its `GameObject` if it was
+    /// still alive. An entity still queued to spawn is taken out of the queue.
+    pub fn despawn(&mut self, entity: Entity) -> Option<GameObject> {
+        for child in self.children.remove(&entity).unwrap_or_default() {
+            self.parents.remove(&child);
+            self.despawn(child);
+        }
+        self.clear_parent(entity);
+        self.colliders.remove(entity);
+        self.visuals.remove(entity);
+        self.unbounded.remove(&entity);
+        if let Some(name) = self.names_by_entity.remove(&entity) {
+            self.names.remove(&name);
+        }
+        let queued = self
+            .queue
+            .spawns
+            .iter()
+            .position(|(spawned, _)| *spawned == entity);
+        if let Some(position) = queued {
+            let (_, game_object) = self.queue.spawns.remove(position);
+            self.allocator.free(entity);
+            return Some(game_object);
+        }
+        if !self.contains(entity) {
+            return None;
+        }
+        let game_object = self.game_objects[entity.index as usize].take();
+        self.len -= 1;
+        self.index_components(entity);
+        self.allocator.free(entity);
+        game_object
+    }
+
+    /// Queues spawns and despawns, for when the entities are borrowed.
+    pub fn commands(&mut self) -> Commands<'_> {
+        Commands {
+            allocator: &mut self.allocator,
+            queue: &mut self.queue,
+        }
+    }
+
+    /// Spawns and despawns everything queued with `commands`, in order.
+    pub fn apply_commands(&mut self) {
+        for (entity, game_object) in std::mem::take(&mut self.queue.spawns) {
+            self.insert(entity, game_object);
+        }
+        for entity in std::mem::take(&mut self.queue.despawns) {
+            self.despawn(entity);
+        }
+    }
+
+    /// Calls `f` on every entity with a way to queue spawns and despawns, which
+    /// are applied once all entities have been visited.
+    pub fn for_each_mut<F>(&mut self, f: F)
+    where
+        F: FnMut(Entity, &mut GameObject, &mut Commands),
+    {
+        let entities: Vec<Entity> = self.iter().map(|(entity, _)| entity).collect();
+        self.visit_mut(entities, f);
+    }
+
+    /// Like `for_each_mut`, but only visits entities with a `C` component.
+    pub fn for_each_with_mut<C: 'static, F>(&mut self, f: F)
+    where
+        F: FnMut(Entity, &mut GameObject, &mut Commands),
+    {
+        let entities: Vec<Entity> = self.query::<C>().map(|(entity, _, _)| entity).collect();
+        self.visit_mut(entities, f);
+    }
+
+    fn visit_mut<F>(&mut self, entities: Vec<Entity>, mut f: F)
+    where
+        F: FnMut(Entity, &mut GameObject, &mut Commands),
+    {
+        let mut commands = Commands {
+            allocator: &mut self.allocator,
+            queue: &mut self.queue,
+        };
+        for entity in entities {
+            // Spawns are only queued, so everything visited is still alive
+            let game_object = self.game_objects[entity.index as usize].as_mut().unwrap();
+            self.moved.insert(entity);
+            f(entity, game_object, &mut commands);
+        }
+        self.apply_commands();
+    }
+
+    /// Names an entity, replacing any name it had. A name belongs to a single
+    /// entity at a time, giving it to another one takes it from the first.
+    pub fn set_name(&mut self, entity: Entity, name: &str) -> Result<(), String> {
+        if !self.contains(entity) {
+            return Err(format!("can't name {:?}, it isn't alive", entity));
+        }
+        if let Some(previous) = self.names_by_entity.remove(&entity) {
+            self.names.remove(&previous);
+        }
+        if let Some(previous) = self.names.insert(name.to_string(), entity) {
+            self.names_by_entity.remove(&previous);
+        }
+        self.names_by_entity.insert(entity, name.to_string());
+        Ok(())
+    }
+
+    pub fn get_name(&self, entity: Entity) -> Option<&str> {
+        self.names_by_entity.get(&entity).map(|name| name.as_str())
+    }
+
+    /// Looks up an entity by its name.
+    pub fn find(&self, name: &str) -> Option<Entity> {
+        self.names.get(name).copied()
+    }
+
+    /// Makes `child` follow `parent`, placed by `local` relative to it. The
+    /// child's world transform is worked out in `update_transforms`.
+    pub fn set_parent(
+        &mut self,
+        child: Entity,
+        parent: Entity,
+        local: Transform,
+    ) -> Result<(), String> {
+        if !self.contains(child) || !self.contains(parent) {
+            return Err(format!(
+                "can't parent {:?} to {:?}, both must be alive",
+                child, parent
+            ));
+        }
+        let mut ancestor = Some(parent);
+        while let Some(entity) = ancestor {
+            if entity == child {
+                return Err(format!("{:?} is an ancestor of {:?}", child, parent));
+            }
+            ancestor = self.get_parent(entity);
+        }
+        self.clear_parent(child);
+        self.parents.insert(child, (parent, local));
+        self.children.entry(parent).or_default().push(child);
+        Ok(())
+    }
+
+    /// Detaches an entity from its parent, leaving it where it is.
+    pub fn clear_parent(&mut self, child: Entity) {
+        if let Some((parent, _)) = self.parents.remove(&child) {
+            if let Some(siblings) = self.children.get_mut(&parent) {
+                siblings.retain(|sibling| *sibling != child);
+                if siblings.is_empty() {
+                    self.children.remove(&parent);
+                }
+            }
+        }
+    }
+
+    pub fn get_parent(&self, child: Entity) -> Option<Entity> {
+        self.parents.get(&child).map(|(parent, _)| *parent)
+    }
+
+    pub fn get_children(&self, parent: Entity) -> &[Entity] {
+        self.children.get(&parent).map_or(&[], |children| children)
+    }
+
+    pub fn get_local_transform(&self, child: Entity) -> Option<&Transform> {
+        self.parents.get(&child).map(|(_, local)| local)
+    }
+
+    pub fn get_local_transform_mut(&mut self, child: Entity) -> Option<&mut Transform> {
+        self.parents.get_mut(&child).map(|(_, local)| local)
+    }
+
+    /// Moves every child to where its parent and local transform put it,
+    /// parents before their children. The engine calls this after every update.
+    pub fn update_transforms(&mut self) {
+        let roots: Vec<Entity> = self
+            .children
+            .keys()
+            .filter(|entity| !self.parents.contains_key(entity))
+            .copied()
+            .collect();
+        let mut stack: Vec<(Entity, Transform)> = roots
+            .into_iter()
+            .filter_map(|root| {
+                self.get(root)
+                    .map(|game_object| (root, game_object.get_transform()))
+            })
+            .collect();
+        while let Some((parent, transform)) = stack.pop() {
+            for child in self.get_children(parent).to_vec() {
+                let world = transform.combine(&self.parents[&child].1);
+                self.get_mut(child).unwrap().set_transform(world);
+                stack.push((child, world));
+            }
+        }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.get(entity).is_some()
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&GameObject> {
+        if !self.is_current(entity) {
+            return None;
+        }
+        self.game_objects.get(entity.index as usize)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut GameObject> {
+        self.moved.insert(entity);
+        self.get_in_place_mut(entity)
+    }
+
+    /// Like `get_mut`, without marking the entity as moved.
+    fn get_in_place_mut(&mut self, entity: Entity) -> Option<&mut GameObject> {
+        if !self.is_current(entity) {
+            return None;
+        }
+        self.game_objects.get_mut(entity.index as usize)?.as_mut()
+    }
+
+    /// Whether `entity` is the latest handle for its index, spawned or queued.
+    fn is_current(&self, entity: Entity) -> bool {
+        self.allocator.generations.get(entity.index as usize) == Some(&entity.generation)
+    }
+
+    /// Adds a component to an entity, replacing any of the same type, and
+    /// indexes it for queries right away.
+    pub fn add_component<C: 'static>(
+        &mut self,
+        entity: Entity,
+        component: C,
+    ) -> Result<(), String> {
+        let game_object = self
+            .get_in_place_mut(entity)
+            .ok_or_else(|| format!("can't add a component to {:?}, it isn't alive", entity))?;
+        game_object.add_component(component);
+        let type_id = TypeId::of::<C>();
+        self.by_component
+            .entry(type_id)
+            .or_insert_with(BTreeSet::new)
+            .insert(entity);
+        let indexed = self.indexed_components.entry(entity).or_default();
+        if !indexed.contains(&type_id) {
+            indexed.push(type_id);
+        }
+        Ok(())
+    }
+
+    /// Removes a component from an entity and from the index.
+    pub fn remove_component<C: 'static>(&mut self, entity: Entity) -> Option<C> {
+        let component = self.get_in_place_mut(entity)?.remove_component::<C>()?;
+        let type_id = TypeId::of::<C>();
+        self.unindex_component(entity, type_id);
+        if let Some(indexed) = self.indexed_components.get_mut(&entity) {
+            indexed.retain(|indexed| *indexed != type_id);
+        }
+        Some(component)
+    }
+
+    /// Shorthand for getting a component of an entity.
+    pub fn get_component<C: 'static>(&self, entity: Entity) -> Option<&C> {
+        self.get(entity)
+            .and_then(|game_object| game_object.get_component())
+    }
+
+    /// Shorthand for getting a component of an entity mutably.
+    pub fn get_component_mut<C: 'static>(&mut self, entity: Entity) -> Option<&mut C> {
+        self.get_in_place_mut(entity)
+            .and_then(|game_object| game_object.get_component_mut())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &GameObject)> {
+        let generations = &self.allocator.generations;
+        self.game_objects
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, game_object)| {
+                let entity = Entity {
+                    index: index as u32,
+                    generation: generations[index],
+                };
+                game_object
+                    .as_ref()
+                    .map(|game_object| (entity, game_object))
+            })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut GameObject)> {
+        let entities: Vec<Entity> = self.iter().map(|(entity, _)| entity).collect();
+        self.moved.extend(entities.iter().copied());
+        pick_mut(&mut self.game_objects, entities)
+    }
+
+    /// Every `GameObject`, for implementing `World::get_game_objects`.
+    pub fn get_game_objects(&self) -> Vec<&GameObject> {
+        self.game_objects.iter().flatten().collect()
+    }
+
+    /// Every `GameObject`, for implementing `World::get_game_objects_mut`.
+    ///
+    /// Unlike the other mutable accessors this doesn't mark anything as moved,
+    /// call `moved` for colliders repositioned through it.
+    pub fn get_game_objects_mut(&mut self) -> Vec<&mut GameObject> {
+        self.game_objects.iter_mut().flatten().collect()
+    }
+
+    /// Despawns every entity for which `keep` returns false.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(Entity, &mut GameObject) -> bool,
+    {
+        let despawned: Vec<Entity> = self
+            .iter_mut()
+            .filter_map(|(entity, game_object)| {
+                if keep(entity, game_object) {
+                    None
+                } else {
+                    Some(entity)
+                }
+            })
+            .collect();
+        for entity in despawned {
+            self.despawn(entity);
+        }
+    }
+
+    /// Entities that may have a `C` component: the ones indexed with one and
+    /// the ones that may have changed since, in order.
+    fn candidates<C: 'static>(&self) -> impl Iterator<Item = Entity> + '_ {
+        static NONE: BTreeSet<Entity> = BTreeSet::new();
+        self.by_component
+            .get(&TypeId::of::<C>())
+            .unwrap_or(&NONE)
+            .union(&self.moved)
+            .copied()
+    }
+
+    /// Every entity with a `C` component.
+    pub fn query<C: 'static>(&self) -> impl Iterator<Item = (Entity, &GameObject, &C)> {
+        self.candidates::<C>().filter_map(move |entity| {
+            let game_object = self.get(entity)?;
+            game_object
+                .get_component::<C>()
+                .map(|component| (entity, game_object, component))
+        })
+    }
+
+    /// Every entity with both an `A` and a `B` component.
+    pub fn query2<A: 'static, B: 'static>(
+        &self,
+    ) -> impl Iterator<Item = (Entity, &GameObject, &A, &B)> {
+        self.query::<A>().filter_map(|(entity, game_object, a)| {
+            game_object
+                .get_component::<B>()
+                .map(|b| (entity, game_object, a, b))
+        })
+    }
+
+    /// Every entity with a `C` component, with mutable access to the whole
+    /// `GameObject`.
+    pub fn query_mut<C: 'static>(&mut self) -> impl Iterator<Item = (Entity, &mut GameObject)> {
+        let entities: Vec<Entity> = self.query::<C>().map(|(entity, _, _)| entity).collect();
+        self.moved.extend(entities.iter().copied());
+        pick_mut(&mut self.game_objects, entities)
+    }
+
+    /// Like `query_mut`, but doesn't mark the entities as moved, for changes
+    /// that leave their position, collider, rends and components as they were.
+    pub fn query_in_place_mut<C: 'static>(
+        &mut self,
+    ) -> impl Iterator<Item = (Entity, &mut GameObject)> {
+        let entities: Vec<Entity> = self.query::<C>().map(|(entity, _, _)| entity).collect();
+        pick_mut(&mut self.game_objects, entities)
+    }
+
+    /// Sets the previous position of every entity that may have moved since
+    /// the last call to where it is now. The engine calls this before every
+    /// update, to interpolate between updates when rendering.
+    pub fn save_previous_positions(&mut self) {
+        let mut entities = std::mem::take(&mut self.unsettled);
+        entities.extend(self.moved.iter().copied());
+        for entity in entities {
+            if let Some(game_object) = self.get_in_place_mut(entity) {
+                game_object.prev_pos = game_object.pos;
+            }
+        }
+    }
+
+    /// Marks an entity as moved, so the spatial index picks up its new position.
+    pub fn moved(&mut self, entity: Entity) {
+        self.moved.insert(entity);
+    }
+
+    fn update_spatial(&mut self) {
+        for entity in std::mem::take(&mut self.moved) {
+            self.reindex(entity);
+        }
+    }
+
+    /// Brings the spatial and component indices up to date for one entity.
+    fn reindex(&mut self, entity: Entity) {
+        self.awake.insert(entity);
+        self.unsettled.insert(entity);
+        self.index_components(entity);
+        let alive = self.is_current(entity);
+        let game_object = match self.game_objects.get(entity.index as usize) {
+            Some(Some(game_object)) if alive => game_object,
+            _ => {
+                self.colliders.remove(entity);
+                self.visuals.remove(entity);
+                self.unbounded.remove(&entity);
+                return;
+            }
+        };
+        match game_object.get_collider() {
+            Some(collider) => {
+                self.colliders
+                    .insert(entity, game_object.pos, collider.get_range());
+            }
+            None => {
+                self.colliders.remove(entity);
+            }
+        }
+        match game_object.get_render_range() {
+            Some(range) => {
+                self.visuals.insert(entity, game_object.pos, range);
+                self.unbounded.remove(&entity);
+            }
+            None => {
+                self.visuals.remove(entity);
+                self.unbounded.insert(entity);
+            }
+        }
+    }
+
+    /// Indexes an entity by the components it has now, or takes it out of the
+    /// index if it isn't alive.
+    fn index_components(&mut self, entity: Entity) {
+        let type_ids: Vec<TypeId> = self.get(entity).map_or_else(Vec::new, |game_object| {
+            game_object.components.type_ids().collect()
+        });
+        for type_id in self.indexed_components.remove(&entity).unwrap_or_default() {
+            if !type_ids.contains(&type_id) {
+                self.unindex_component(entity, type_id);
+            }
+        }
+        for type_id in type_ids.iter() {
+            self.by_component
+                .entry(*type_id)
+                .or_insert_with(BTreeSet::new)
+                .insert(entity);
+        }
+        if !type_ids.is_empty() {
+            self.indexed_components.insert(entity, type_ids);
+        }
+    }
+
+    fn unindex_component(&mut self, entity: Entity, type_id: TypeId) {
+        if let Some(entities) = self.by_component.get_mut(&type_id) {
+            entities.remove(&entity);
+            if entities.is_empty() {
+                self.by_component.remove(&type_id);
+            }
+        }
+    }
+
+    /// Finds which colliders overlap and records the changes since the last call
+    /// as `CollisionEvent`s. The engine calls this after every update.
+    pub fn update_contacts(&mut self) {
+        self.update_spatial();
+        let awake = std::mem::take(&mut self.awake);
+        let mut overlaps: BTreeMap<(Entity, Entity), bool> = self
+            .overlaps
+            .iter()
+            .filter(|((a, b), _)| {
+                !awake.contains(a) && !awake.contains(b) && self.contains(*a) && self.contains(*b)
+            })
+            .map(|(pair, trigger)| (*pair, *trigger))
+            .collect();
+        for entity in awake {
+            let game_object = match self.get(entity) {
+                Some(game_object) => game_object,
+                None => continue,
+            };
+            let collider = match game_object.get_collider() {
+                Some(collider) => collider,
+                None => continue,
+            };
+            for other in self.colliders.query(game_object.pos, collider.get_range()) {
+                let other_object = self.get(other).unwrap();
+                let other_collider = other_object.get_collider().as_ref().unwrap();
+                if other == entity
+                    || !collider.interacts_with(other_collider)
+                    || collider
+                        .contact(game_object.pos, other_collider, other_object.pos)
+                        .is_none()
+                {
+                    continue;
+                }
+                overlaps.insert(
+                    (entity.min(other), entity.max(other)),
+                    collider.is_trigger() || other_collider.is_trigger(),
+                );
+            }
+        }
+
+        self.collision_events.clear();
+        for ((a, b), trigger) in overlaps.iter() {
+            let phase = if self.overlaps.contains_key(&(*a, *b)) {
+                Phase::Stay
+            } else {
+                Phase::Enter
+            };
+            self.collision_events.push(CollisionEvent {
+                a: *a,
+                b: *b,
+                phase,
+                trigger: *trigger,
+            });
+        }
+        for ((a, b), trigger) in self.overlaps.iter() {
+            if !overlaps.contains_key(&(*a, *b)) {
+                self.collision_events.push(CollisionEvent {
+                    a: *a,
+                    b: *b,
+                    phase: Phase::Exit,
+                    trigger: *trigger,
+                });
+            }
+        }
+        self.overlaps = overlaps;
+    }
+
+    /// The events found by the last `update_contacts`.
+    pub fn get_collision_events(&self) -> &[CollisionEvent] {
+        &self.collision_events
+    }
+
+    /// Every entity whose collider overlaps the circle of `radius` around `pos`,
+    /// in the order they were spawned.
+    pub fn colliders_within(&mut self, pos: na::Point2<f32>, radius: f32) -> Vec<Entity> {
+        self.update_spatial();
+        self.colliders.query(pos, radius)
+    }
+
+    /// Entities with something drawn inside the rectangle from `min` to `max`,
+    /// see `GameObject::get_render_range`. Entities without a render range
+    /// aren't included, they're in `get_unbounded`.
+    pub fn visible_within(&mut self, min: na::Point2<f32>, max: na::Point2<f32>) -> Vec<Entity> {
+        self.update_spatial();
+        self.visuals.query_rect(min, max)
+    }
+
+    /// Entities that may draw anywhere, so can't be culled.
+    pub fn get_unbounded(&mut self) -> Vec<Entity> {
+        self.update_spatial();
+        self.unbounded.iter().copied().collect()
+    }
+}
+
+/// Hands out the objects of `entities` all at once. They have to be alive and
+/// in order, which makes the borrows of their slots disjoint.
+fn pick_mut(
+    game_objects: &mut [Option<GameObject>],
+    entities: Vec<Entity>,
+) -> impl Iterator<Item = (Entity, &mut GameObject)> {
+    let mut rest = game_objects;
+    let mut start = 0;
+    entities.into_iter().map(move |entity| {
+        let index = entity.index as usize;
+        let (slots, tail) = std::mem::take(&mut rest).split_at_mut(index + 1 - start);
+        rest = tail;
+        start = index + 1;
+        (entity, slots[slots.len() - 1].as_mut().unwrap())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Collider;
+
+    #[derive(Debug, PartialEq)]
+    struct Heat(f32);
+
+    #[derive(Debug, PartialEq)]
+    struct Fuel(u8);
+
+    #[test]
+    fn queries_component_combinations() {
+        let mut entities = Entities::new();
+        let mut fire = GameObject::new(na::Point2::new(1.0, 0.0));
+        fire.add_component(Heat(1.0));
+        fire.add_component(Fuel(3));
+        let fire = entities.spawn(fire);
+        let mut ember = GameObject::new(na::Point2::new(2.0, 0.0));
+        ember.add_component(Heat(0.2));
+        let ember = entities.spawn(ember);
+        let rock = entities.spawn(GameObject::new(na::Point2::new(3.0, 0.0)));
+
+        let hot: Vec<Entity> = entities
+            .query::<Heat>()
+            .map(|(entity, _, _)| entity)
+            .collect();
+        assert_eq!(hot, [fire, ember]);
+        let burning: Vec<(Entity, &Fuel)> = entities
+            .query2::<Heat, Fuel>()
+            .map(|(entity, _, _, fuel)| (entity, fuel))
+            .collect();
+        assert_eq!(burning, [(fire, &Fuel(3))]);
+
+        for (_, game_object) in entities.query_mut::<Heat>() {
+            game_object.get_component_mut::<Heat>().unwrap().0 *= 0.5;
+        }
+        assert_eq!(entities.get_component::<Heat>(fire), Some(&Heat(0.5)));
+        assert_eq!(
+            entities.get_mut(ember).unwrap().remove_component::<Heat>(),
+            Some(Heat(0.1))
+        );
+        assert_eq!(entities.query::<Heat>().count(), 1);
+
+        entities.retain(|_, game_object| game_object.pos.x < 2.5);
+        assert!(!entities.contains(rock));
+        assert!(entities.despawn(ember).is_some());
+        assert!(entities.despawn(ember).is_none());
+        let new = entities.spawn(GameObject::new(na::Point2::new(0.0, 0.0)));
+        assert_ne!(new, rock);
+        assert_eq!(entities.len(), 2);
+    }
+
+    #[test]
+    fn indexes_entities_by_component() {
+        let mut entities = Entities::new();
+        let fire = entities.spawn(GameObject::new(na::Point2::new(1.0, 0.0)));
+        let rock = entities.spawn(GameObject::new(na::Point2::new(3.0, 0.0)));
+        entities.add_component(fire, Heat(1.0)).unwrap();
+        entities.update_contacts();
+        assert_eq!(entities.candidates::<Heat>().collect::<Vec<_>>(), [fire]);
+        assert!(entities.candidates::<Fuel>().next().is_none());
+
+        // Changed behind the index's back, found since it may have moved
+        entities.get_mut(rock).unwrap().add_component(Heat(0.1));
+        let hot: Vec<Entity> = entities
+            .query_in_place_mut::<Heat>()
+            .map(|(entity, _)| entity)
+            .collect();
+        assert_eq!(hot, [fire, rock]);
+        entities.update_contacts();
+        assert_eq!(entities.by_component[&TypeId::of::<Heat>()].len(), 2);
+
+        assert_eq!(entities.remove_component::<Heat>(fire), Some(Heat(1.0)));
+        assert_eq!(entities.remove_component::<Heat>(fire), None);
+        assert_eq!(entities.candidates::<Heat>().collect::<Vec<_>>(), [rock]);
+        entities.despawn(rock);
+        assert!(entities.by_component.is_empty());
+        assert!(entities.add_component(rock, Fuel(1)).is_err());
+
+        entities.get_mut(fire).unwrap().pos.x = 2.0;
+        entities.save_previous_positions();
+        entities.update_contacts();
+        entities.save_previous_positions();
+        assert_eq!(entities.get(fire).unwrap().prev_pos.x, 2.0);
+        assert!(entities.unsettled.is_empty());
+    }
+
+    #[test]
+    fn reuses_indices_with_new_generations() {
+        let mut entities = Entities::new();
+        let tree = entities.spawn(GameObject::new(na::Point2::new(0.0, 0.0)));
+        entities.set_name(tree, "tree").unwrap();
+        assert_eq!(entities.find("tree"), Some(tree));
+        entities.despawn(tree);
+        assert_eq!(entities.find("tree"), None);
+
+        let stump = entities.spawn(GameObject::new(na::Point2::new(0.0, 0.0)));
+        assert_eq!(stump.get_index(), tree.get_index());
+        assert_ne!(stump, tree);
+        assert!(entities.get(tree).is_none());
+        assert!(entities.set_name(tree, "tree").is_err());
+
+        entities.set_name(stump, "stump").unwrap();
+        let log = entities.spawn(GameObject::new(na::Point2::new(1.0, 0.0)));
+        entities.set_name(log, "stump").unwrap();
+        assert_eq!(entities.find("stump"), Some(log));
+        assert_eq!(entities.get_name(stump), None);
+    }
+
+    #[test]
+    fn queues_spawns_and_despawns_while_iterating() {
+        let mut entities = Entities::new();
+        for x in 0..3 {
+            entities.spawn(GameObject::new(na::Point2::new(x as f32, 0.0)));
+        }
+        let mut logs = Vec::new();
+        entities.for_each_mut(|entity, game_object, commands| {
+            if game_object.pos.x >= 1.0 {
+                commands.despawn(entity);
+                logs.push(commands.spawn(GameObject::new(
+                    game_object.pos + na::Vector2::new(0.0, 1.0),
+                )));
+            }
+        });
+        assert_eq!(entities.len(), 3);
+        assert_eq!(
+            entities.get(logs[1]).unwrap().pos,
+            na::Point2::new(2.0, 1.0)
+        );
+
+        let queued = entities
+            .commands()
+            .spawn(GameObject::new(na::Point2::new(5.0, 5.0)));
+        assert!(!entities.contains(queued));
+        entities.apply_commands();
+        assert!(entities.contains(queued));
+    }
+
+    #[test]
+    fn cancels_spawns_despawned_before_they_are_applied() {
+        let mut entities = Entities::new();
+        let mut fire = GameObject::new(na::Point2::new(0.0, 0.0));
+        fire.add_component(Heat(1.0));
+        let fire = entities.spawn(fire);
+        let mut sparks = Vec::new();
+        entities.for_each_with_mut::<Heat, _>(|_, game_object, commands| {
+            let spark = commands.spawn(GameObject::new(game_object.pos));
+            commands.despawn(spark);
+            sparks.push(spark);
+            sparks.push(commands.spawn(GameObject::new(game_object.pos)));
+        });
+        assert!(!entities.contains(sparks[0]));
+        assert!(entities.contains(sparks[1]));
+
+        let smoke = entities
+            .commands()
+            .spawn(GameObject::new(na::Point2::new(0.0, 1.0)));
+        assert!(entities.despawn(smoke).is_some());
+        entities.apply_commands();
+        assert!(!entities.contains(smoke));
+        assert_eq!(entities.len(), 2);
+        assert!(entities.contains(fire));
+    }
+
+    #[test]
+    fn keeps_colliders_indexed() {
+        let mut entities = Entities::with_cell_size(10.0);
+        let mut tree = GameObject::new(na::Point2::new(0.0, 0.0));
+        tree.add_collider(Collider::new(5.0));
+        let tree = entities.spawn(tree);
+        let mut log = GameObject::new(na::Point2::new(40.0, 0.0));
+        log.add_collider(Collider::new(2.0));
+        let log = entities.spawn(log);
+        entities.spawn(GameObject::new(na::Point2::new(0.0, 0.0)));
+
+        assert_eq!(
+            entities.colliders_within(na::Point2::new(3.0, 0.0), 1.0),
+            [tree]
+        );
+        entities.get_mut(log).unwrap().pos = na::Point2::new(4.0, 0.0);
+        assert_eq!(
+            entities.colliders_within(na::Point2::new(3.0, 0.0), 1.0),
+            [tree, log]
+        );
+        for (_, game_object) in entities.iter_mut() {
+            game_object.pos.y += 100.0;
+        }
+        assert!(entities
+            .colliders_within(na::Point2::new(3.0, 0.0), 1.0)
+            .is_empty());
+        entities.despawn(tree);
+        assert_eq!(
+            entities.colliders_within(na::Point2::new(3.0, 100.0), 1.0),
+            [log]
+        );
+    }
+
+    #[test]
+    fn moves_children_with_their_parents() {
+        let mut entities = Entities::new();
+        let player = entities.spawn(GameObject::new(na::Point2::new(10.0, 0.0)));
+        let axe = entities.spawn(GameObject::new(na::Point2::new(0.0, 0.0)));
+        let blade = entities.spawn(GameObject::new(na::Point2::new(0.0, 0.0)));
+        entities
+            .set_parent(axe, player, Transform::new(na::Vector2::new(2.0, 0.0)))
+            .unwrap();
+        entities
+            .set_parent(blade, axe, Transform::new(na::Vector2::new(0.0, 1.0)))
+            .unwrap();
+        assert!(entities
+            .set_parent(player, blade, Transform::identity())
+            .is_err());
+
+        entities.get_mut(player).unwrap().rotation = std::f32::consts::PI;
+        entities.update_transforms();
+        let blade_pos = entities.get(blade).unwrap().pos;
+        assert!((blade_pos - na::Point2::new(8.0, -1.0)).norm() < 1e-5);
+        assert_eq!(entities.get_children(player), [axe]);
+
+        entities.despawn(player);
+        assert!(!entities.contains(axe));
+        assert!(!entities.contains(blade));
+        assert!(entities.get_children(player).is_empty());
+    }
+
+    #[test]
+    fn reports_contacts_entering_staying_and_exiting() {
+        let mut entities = Entities::new();
+        let mut player = GameObject::new(na::Point2::new(0.0, 0.0));
+        player.add_collider(Collider::new(5.0));
+        let player = entities.spawn(player);
+        let mut fire = GameObject::new(na::Point2::new(8.0, 0.0));
+        fire.add_collider(Collider::new(5.0).as_trigger());
+        let fire = entities.spawn(fire);
+        let mut tree = GameObject::new(na::Point2::new(-12.0, 0.0));
+        tree.add_collider(Collider::new(5.0).with_layers(2).with_mask(2));
+        let tree = entities.spawn(tree);
+
+        let phases = |entities: &Entities| -> Vec<(Entity, Entity, Phase, bool)> {
+            entities
+                .get_collision_events()
+                .iter()
+                .map(|event| (event.a, event.b, event.phase, event.trigger))
+                .collect()
+        };
+        entities.update_contacts();
+        assert_eq!(phases(&entities), [(player, fire, Phase::Enter, true)]);
+        entities.update_contacts();
+        assert_eq!(phases(&entities), [(player, fire, Phase::Stay, true)]);
+
+        entities.get_mut(player).unwrap().pos.x = -4.0;
+        entities.update_contacts();
+        assert_eq!(
+            phases(&entities),
+            [
+                (player, tree, Phase::Enter, false),
+                (player, fire, Phase::Exit, true)
+            ]
+        );
+        assert_eq!(entities.get_collision_events()[0].other(tree), Some(player));
+        entities.despawn(tree);
+        entities.update_contacts();
+        assert_eq!(phases(&entities), [(player, tree, Phase::Exit, false)]);
+        entities.update_contacts();
+        assert!(entities.get_collision_events().is_empty());
+    }
+}
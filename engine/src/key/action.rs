@@ -0,0 +1,451 @@
+//! Named actions on top of the `KeyManager`, so game code doesn't need to know
+//! which keys the player prefers.
+//!
+//! An `ActionMap` binds any number of inputs to an action name. Bindings can be
+//! changed at runtime and saved to / loaded from a plain text profile:
+//!
+//! ```text
+//! # one action per line
+//! move_up = code:KeyW key:38 axis:1-
+//! chop = key:32 button:0
+//! ```
+//!
+//! `code` bindings name the physical key, so they stay in place whatever the
+//! keyboard layout.
+//!
+//! Gamepad bindings react to every connected gamepad. An `axis` binding names the
+//! axis and the direction it has to be pushed in, in Gamepad API orientation. It
+//! goes down and up as the axis crosses `AXIS_PRESS_THRESHOLD`.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::gamepad::{AXIS_MAX, BUTTON_MAX, GAMEPAD_MAX};
+use super::{Code, KeyManager, KEY_CODE_MAX};
+
+/// How far an axis has to be pushed for an axis binding to count as pressed.
+const AXIS_PRESS_THRESHOLD: f32 = 0.5;
+
+/// An input an action can be bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    /// A KeyCode, see the `key_codes` module.
+    Key(usize),
+    /// A physical key.
+    Code(Code),
+    /// A gamepad button, see the `buttons` module.
+    Button(usize),
+    /// One direction of a gamepad axis.
+    Axis { axis: usize, positive: bool },
+}
+
+impl Binding {
+    /// How far the input is pushed, from 0 to 1.
+    fn value(&self, key_manager: &KeyManager) -> f32 {
+        match *self {
+            Binding::Axis { axis, positive } => {
+                Binding::axis_value(positive, |gamepad| key_manager.axis(gamepad, axis))
+            }
+            _ => {
+                if self.pressed(key_manager) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    /// How far an axis binding was pushed in the previous frame, from 0 to 1.
+    fn previous_value(&self, key_manager: &KeyManager) -> f32 {
+        match *self {
+            Binding::Axis { axis, positive } => {
+                Binding::axis_value(positive, |gamepad| key_manager.previous_axis(gamepad, axis))
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// The furthest any gamepad pushes an axis in the binding's direction.
+    fn axis_value(positive: bool, axis: impl Fn(usize) -> f32) -> f32 {
+        (0..GAMEPAD_MAX)
+            .map(|gamepad| {
+                let value = axis(gamepad);
+                if positive {
+                    value.max(0.0)
+                } else {
+                    (-value).max(0.0)
+                }
+            })
+            .fold(0.0, f32::max)
+    }
+
+    fn down(&self, key_manager: &KeyManager) -> bool {
+        match *self {
+            Binding::Key(key_code) => key_manager.key_down(key_code),
+            Binding::Code(code) => key_manager.code_down(code),
+            Binding::Button(button) => {
+                (0..GAMEPAD_MAX).any(|gamepad| key_manager.button_down(gamepad, button))
+            }
+            // Pushed past the threshold since the previous frame
+            Binding::Axis { .. } => {
+                self.value(key_manager) >= AXIS_PRESS_THRESHOLD
+                    && self.previous_value(key_manager) < AXIS_PRESS_THRESHOLD
+            }
+        }
+    }
+
+    fn pressed(&self, key_manager: &KeyManager) -> bool {
+        match *self {
+            Binding::Key(key_code) => key_manager.key_pressed(key_code),
+            Binding::Code(code) => key_manager.code_pressed(code),
+            Binding::Button(button) => {
+                (0..GAMEPAD_MAX).any(|gamepad| key_manager.button_pressed(gamepad, button))
+            }
+            Binding::Axis { .. } => self.value(key_manager) >= AXIS_PRESS_THRESHOLD,
+        }
+    }
+
+    fn up(&self, key_manager: &KeyManager) -> bool {
+        match *self {
+            Binding::Key(key_code) => key_manager.key_up(key_code),
+            Binding::Code(code) => key_manager.code_up(code),
+            Binding::Button(button) => {
+                (0..GAMEPAD_MAX).any(|gamepad| key_manager.button_up(gamepad, button))
+            }
+            Binding::Axis { .. } => {
+                self.value(key_manager) < AXIS_PRESS_THRESHOLD
+                    && self.previous_value(key_manager) >= AXIS_PRESS_THRESHOLD
+            }
+        }
+    }
+
+    fn parse(text: &str) -> Result<Binding, String> {
+        let mut parts = text.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        let value = parts
+            .next()
+            .ok_or_else(|| format!("Binding '{}' is missing a ':'", text))?;
+        match kind {
+            "key" => {
+                let key_code = value
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid KeyCode '{}'", value))?;
+                if key_code >= KEY_CODE_MAX {
+                    return Err(format!("KeyCode {} out of range", key_code));
+                }
+                Ok(Binding::Key(key_code))
+            }
+            "code" => Code::parse(value)
+                .map(Binding::Code)
+                .ok_or_else(|| format!("Unknown code '{}'", value)),
+            "button" => {
+                let button = value
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid button '{}'", value))?;
+                if button >= BUTTON_MAX {
+                    return Err(format!("Button {} out of range", button));
+                }
+                Ok(Binding::Button(button))
+            }
+            "axis" => {
+                let positive = match value.chars().last() {
+                    Some('+') => true,
+                    Some('-') => false,
+                    _ => return Err(format!("Axis '{}' is missing a '+' or '-'", value)),
+                };
+                let axis = value[..value.len() - 1]
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid axis '{}'", value))?;
+                if axis >= AXIS_MAX {
+                    return Err(format!("Axis {} out of range", axis));
+                }
+                Ok(Binding::Axis { axis, positive })
+            }
+            _ => Err(format!("Unknown binding type '{}'", kind)),
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Binding::Key(key_code) => write!(f, "key:{}", key_code),
+            Binding::Code(code) => write!(f, "code:{}", code),
+            Binding::Button(button) => write!(f, "button:{}", button),
+            Binding::Axis { axis, positive } => {
+                write!(f, "axis:{}{}", axis, if positive { '+' } else { '-' })
+            }
+        }
+    }
+}
+
+/// Maps action names to the inputs that trigger them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ActionMap {
+    actions: BTreeMap<String, Vec<Binding>>,
+}
+
+impl ActionMap {
+    pub fn new() -> ActionMap {
+        ActionMap::default()
+    }
+
+    /// Adds a binding to an action, an action can have any number of them.
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let bindings = self.actions.entry(action.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str, binding: Binding) {
+        if let Some(bindings) = self.actions.get_mut(action) {
+            bindings.retain(|bound| *bound != binding);
+        }
+    }
+
+    /// Replaces one binding of an action with another, keeping its position.
+    /// The new binding is taken away from any other action using it.
+    pub fn rebind(&mut self, action: &str, old: Binding, new: Binding) {
+        for (name, bindings) in self.actions.iter_mut() {
+            if name != action {
+                bindings.retain(|bound| *bound != new);
+            }
+        }
+        let bindings = self.actions.entry(action.to_string()).or_default();
+        bindings.retain(|bound| *bound != new);
+        match bindings.iter().position(|bound| *bound == old) {
+            Some(index) => bindings[index] = new,
+            None => bindings.push(new),
+        }
+    }
+
+    /// Replaces the bindings of every action in `other` with its own, leaving
+    /// the other actions as they were.
+    pub fn merge(&mut self, other: &ActionMap) {
+        for (action, bindings) in other.actions.iter() {
+            self.actions.insert(action.clone(), bindings.clone());
+        }
+    }
+
+    pub fn get_bindings(&self, action: &str) -> &[Binding] {
+        self.actions
+            .get(action)
+            .map(|bindings| bindings.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Returns true if any binding of the action was just pressed.
+    pub fn down(&self, action: &str, key_manager: &KeyManager) -> bool {
+        self.get_bindings(action)
+            .iter()
+            .any(|binding| binding.down(key_manager))
+    }
+
+    /// Returns true every frame any binding of the action is pressed.
+    pub fn pressed(&self, action: &str, key_manager: &KeyManager) -> bool {
+        self.get_bindings(action)
+            .iter()
+            .any(|binding| binding.pressed(key_manager))
+    }
+
+    /// Returns true if a binding of the action was just released and no other
+    /// binding is keeping it pressed.
+    pub fn up(&self, action: &str, key_manager: &KeyManager) -> bool {
+        self.get_bindings(action)
+            .iter()
+            .any(|binding| binding.up(key_manager))
+            && !self.pressed(action, key_manager)
+    }
+
+    /// How strongly the action is triggered, from 0 to 1.
+    pub fn value(&self, action: &str, key_manager: &KeyManager) -> f32 {
+        self.get_bindings(action)
+            .iter()
+            .map(|binding| binding.value(key_manager))
+            .fold(0.0, f32::max)
+    }
+
+    /// Combines two opposing actions into an axis from -1 to 1.
+    pub fn axis(&self, negative: &str, positive: &str, key_manager: &KeyManager) -> f32 {
+        self.value(positive, key_manager) - self.value(negative, key_manager)
+    }
+
+    /// Serialises the bindings into the text profile format.
+    pub fn to_profile(&self) -> String {
+        let mut profile = String::new();
+        for (action, bindings) in self.actions.iter() {
+            profile.push_str(action);
+            profile.push_str(" =");
+            for binding in bindings.iter() {
+                profile.push_str(&format!(" {}", binding));
+            }
+            profile.push('\n');
+        }
+        profile
+    }
+
+    /// Parses a text profile, see the module documentation for the format.
+    pub fn from_profile(profile: &str) -> Result<ActionMap, String> {
+        let mut action_map = ActionMap::new();
+        for (line_number, line) in profile.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let action = parts.next().unwrap_or("").trim();
+            let bindings = parts
+                .next()
+                .ok_or_else(|| format!("Line {}: expected 'action = bindings'", line_number + 1))?;
+            if action.is_empty() {
+                return Err(format!("Line {}: missing action name", line_number + 1));
+            }
+            action_map.actions.entry(action.to_string()).or_default();
+            for binding in bindings.split_whitespace() {
+                let binding = Binding::parse(binding)
+                    .map_err(|error| format!("Line {}: {}", line_number + 1, error))?;
+                action_map.bind(action, binding);
+            }
+        }
+        Ok(action_map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{buttons, key_codes, ProgrammaticInput, Stick};
+    use super::*;
+
+    #[test]
+    fn queries_actions_through_any_binding() {
+        let mut action_map = ActionMap::new();
+        action_map.bind("left", Binding::Key(key_codes::A));
+        action_map.bind("left", Binding::Key(key_codes::LEFT_ARROW));
+        action_map.bind("right", Binding::Key(key_codes::D));
+        // Never pressed, rather than out of bounds
+        action_map.bind("right", Binding::Key(KEY_CODE_MAX + 1));
+
+        let input = ProgrammaticInput::new();
+        let mut key_manager = KeyManager::new(Box::new(input.clone()));
+        input.press(key_codes::LEFT_ARROW);
+        key_manager.poll_input();
+        assert!(action_map.down("left", &key_manager));
+        assert!(!action_map.pressed("right", &key_manager));
+        assert_eq!(action_map.axis("left", "right", &key_manager), -1.0);
+
+        input.press(key_codes::D);
+        key_manager.post_tick_update_key_states();
+        key_manager.poll_input();
+        assert!(!action_map.down("left", &key_manager));
+        assert_eq!(action_map.axis("left", "right", &key_manager), 0.0);
+
+        action_map.rebind(
+            "right",
+            Binding::Key(key_codes::D),
+            Binding::Key(key_codes::LEFT_ARROW),
+        );
+        assert_eq!(
+            action_map.get_bindings("left"),
+            [Binding::Key(key_codes::A)]
+        );
+        assert_eq!(action_map.axis("left", "right", &key_manager), 1.0);
+    }
+
+    #[test]
+    fn gamepads_drive_actions_from_any_pad() {
+        let mut action_map = ActionMap::new();
+        action_map.bind("left", Binding::Key(key_codes::A));
+        action_map.bind(
+            "left",
+            Binding::Axis {
+                axis: 0,
+                positive: false,
+            },
+        );
+        action_map.bind(
+            "right",
+            Binding::Axis {
+                axis: 0,
+                positive: true,
+            },
+        );
+        action_map.bind("chop", Binding::Button(buttons::A));
+
+        let input = ProgrammaticInput::new();
+        let mut key_manager = KeyManager::new(Box::new(input.clone()));
+        input.connect_gamepad(2);
+        input.move_stick(2, Stick::Left, na::Vector2::new(-0.3, 0.0));
+        input.press_button(2, buttons::A);
+        key_manager.poll_input();
+        let axis = action_map.axis("left", "right", &key_manager);
+        assert!(axis < -0.1 && axis > -0.3);
+        assert!(!action_map.pressed("left", &key_manager));
+        assert!(action_map.down("chop", &key_manager));
+
+        // The key wins over the half tilted stick
+        key_manager.post_tick_update_key_states();
+        input.press(key_codes::A);
+        input.release_button(2, buttons::A);
+        key_manager.poll_input();
+        assert_eq!(action_map.axis("left", "right", &key_manager), -1.0);
+        assert!(action_map.up("chop", &key_manager));
+
+        // Axes go down and up as they cross the threshold
+        key_manager.post_tick_update_key_states();
+        input.move_stick(2, Stick::Left, na::Vector2::new(0.9, 0.0));
+        key_manager.poll_input();
+        assert!(action_map.down("right", &key_manager));
+        key_manager.post_tick_update_key_states();
+        key_manager.poll_input();
+        assert!(action_map.pressed("right", &key_manager));
+        assert!(!action_map.down("right", &key_manager));
+        key_manager.post_tick_update_key_states();
+        input.move_stick(2, Stick::Left, na::Vector2::new(0.2, 0.0));
+        key_manager.poll_input();
+        assert!(action_map.up("right", &key_manager));
+        assert!(!action_map.down("right", &key_manager));
+    }
+
+    #[test]
+    fn round_trips_profiles() {
+        let profile =
+            "# AZERTY\nmove_up = key:90 code:ArrowUp axis:1-\nchop = key:32 button:0\nunused =\n";
+        let action_map = ActionMap::from_profile(profile).unwrap();
+        assert_eq!(
+            action_map.get_bindings("move_up"),
+            [
+                Binding::Key(key_codes::Z),
+                Binding::Code(Code::ArrowUp),
+                Binding::Axis {
+                    axis: 1,
+                    positive: false
+                }
+            ]
+        );
+        assert_eq!(
+            ActionMap::from_profile(&action_map.to_profile()),
+            Ok(action_map.clone())
+        );
+
+        let mut defaults = ActionMap::new();
+        defaults.bind("move_up", Binding::Key(key_codes::W));
+        defaults.bind("move_down", Binding::Key(key_codes::S));
+        defaults.merge(&action_map);
+        assert_eq!(defaults.get_bindings("move_up").len(), 3);
+        assert_eq!(
+            defaults.get_bindings("move_down"),
+            [Binding::Key(key_codes::S)]
+        );
+        assert!(defaults.get_bindings("unused").is_empty());
+
+        assert!(ActionMap::from_profile("chop key:32").is_err());
+        assert!(ActionMap::from_profile("chop = key:space").is_err());
+        assert!(ActionMap::from_profile("chop = key:1000").is_err());
+        assert!(ActionMap::from_profile("chop = pedal:1").is_err());
+        assert!(ActionMap::from_profile("chop = button:17").is_err());
+        assert!(ActionMap::from_profile("chop = code:Spacebar").is_err());
+        assert!(ActionMap::from_profile("move_up = axis:1").is_err());
+        assert!(ActionMap::from_profile("move_up = axis:4+").is_err());
+    }
+}
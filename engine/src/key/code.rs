@@ -0,0 +1,171 @@
+//! Physical keys as reported by `KeyboardEvent.code` and logical keys as reported
+//! by `KeyboardEvent.key`.
+//!
+//! A `Code` names a position on the keyboard and stays the same whatever layout
+//! the player uses, `Code::KeyW` is the key labelled Z on an AZERTY keyboard. A
+//! `Key` is what the key means under the current layout, so `Key::Character('z')`
+//! is that same key on AZERTY.
+use std::fmt;
+
+/// Defines a fieldless enum whose variant names are the strings the browser uses.
+macro_rules! dom_names {
+    ($(#[$meta:meta])* pub enum $name:ident { $($(#[$variant_meta:meta])* $variant:ident,)* }) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+        }
+
+        impl $name {
+            /// Every variant, in declaration order.
+            pub const ALL: &'static [$name] = &[$($name::$variant,)*];
+
+            /// Returns the name the browser uses.
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $($name::$variant => stringify!($variant),)*
+                }
+            }
+
+            /// Parses the name the browser uses.
+            pub fn parse(name: &str) -> Option<$name> {
+                match name {
+                    $(stringify!($variant) => Some($name::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+    };
+}
+
+dom_names! {
+    /// A physical key, named after `KeyboardEvent.code`.
+    pub enum Code {
+        Unidentified,
+        KeyA, KeyB, KeyC, KeyD, KeyE, KeyF, KeyG, KeyH, KeyI, KeyJ, KeyK, KeyL, KeyM,
+        KeyN, KeyO, KeyP, KeyQ, KeyR, KeyS, KeyT, KeyU, KeyV, KeyW, KeyX, KeyY, KeyZ,
+        Digit0, Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9,
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+        ArrowUp, ArrowDown, ArrowLeft, ArrowRight,
+        Space, Enter, Escape, Backspace, Tab, CapsLock,
+        ShiftLeft, ShiftRight, ControlLeft, ControlRight, AltLeft, AltRight, MetaLeft,
+        MetaRight, ContextMenu,
+        Minus, Equal, BracketLeft, BracketRight, Backslash, Semicolon, Quote, Backquote,
+        Comma, Period, Slash, IntlBackslash,
+        Insert, Delete, Home, End, PageUp, PageDown,
+        PrintScreen, ScrollLock, Pause, NumLock,
+        Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8,
+        Numpad9, NumpadAdd, NumpadSubtract, NumpadMultiply, NumpadDivide, NumpadDecimal,
+        NumpadEnter,
+    }
+}
+
+dom_names! {
+    /// A logical key that doesn't type a character, named after `KeyboardEvent.key`.
+    pub enum NamedKey {
+        Unidentified,
+        Enter, Tab, Backspace, Escape, Delete, Insert,
+        ArrowUp, ArrowDown, ArrowLeft, ArrowRight, Home, End, PageUp, PageDown,
+        Shift, Control, Alt, AltGraph, Meta, CapsLock, NumLock, ScrollLock, ContextMenu,
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+        PrintScreen, Pause,
+        /// A dead key, waiting for the next key to build an accented character.
+        Dead,
+        /// The key went to an input method editor, see `KeyManager::text_input`.
+        Process,
+    }
+}
+
+/// A logical key, from `KeyboardEvent.key`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// A key typing a character. Letters are stored in lower case, so holding
+    /// shift doesn't turn `a` into a different key.
+    Character(char),
+    Named(NamedKey),
+}
+
+impl Key {
+    /// Converts the value of `KeyboardEvent.key`.
+    pub fn from_dom(key: &str) -> Key {
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(character), None) => Key::character(character),
+            _ => Key::Named(NamedKey::parse(key).unwrap_or(NamedKey::Unidentified)),
+        }
+    }
+
+    pub fn character(character: char) -> Key {
+        Key::Character(character.to_lowercase().next().unwrap_or(character))
+    }
+}
+
+impl Code {
+    /// The logical key this physical key has on a US QWERTY keyboard, for
+    /// simulating input without a browser.
+    pub fn us_key(self) -> Key {
+        let name = self.as_str();
+        let character = match self {
+            Code::Space => Some(' '),
+            Code::Minus | Code::NumpadSubtract => Some('-'),
+            Code::Equal => Some('='),
+            Code::BracketLeft => Some('['),
+            Code::BracketRight => Some(']'),
+            Code::Backslash | Code::IntlBackslash => Some('\\'),
+            Code::Semicolon => Some(';'),
+            Code::Quote => Some('\''),
+            Code::Backquote => Some('`'),
+            Code::Comma => Some(','),
+            Code::Period | Code::NumpadDecimal => Some('.'),
+            Code::Slash | Code::NumpadDivide => Some('/'),
+            Code::NumpadAdd => Some('+'),
+            Code::NumpadMultiply => Some('*'),
+            _ if name.starts_with("Key") || name.starts_with("Digit") => name.chars().last(),
+            _ if name.starts_with("Numpad") && name.len() == 7 => name.chars().last(),
+            _ => None,
+        };
+        if let Some(character) = character {
+            return Key::character(character);
+        }
+        let named = match self {
+            Code::ShiftLeft | Code::ShiftRight => NamedKey::Shift,
+            Code::ControlLeft | Code::ControlRight => NamedKey::Control,
+            Code::AltLeft | Code::AltRight => NamedKey::Alt,
+            Code::MetaLeft | Code::MetaRight => NamedKey::Meta,
+            Code::NumpadEnter => NamedKey::Enter,
+            _ => NamedKey::parse(name).unwrap_or(NamedKey::Unidentified),
+        };
+        Key::Named(named)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_dom_names() {
+        assert_eq!(Code::parse("KeyW"), Some(Code::KeyW));
+        assert_eq!(Code::parse("Lang1"), None);
+        for code in Code::ALL.iter() {
+            assert_eq!(Code::parse(code.as_str()), Some(*code));
+        }
+
+        assert_eq!(Key::from_dom("Z"), Key::Character('z'));
+        assert_eq!(Key::from_dom("é"), Key::Character('é'));
+        assert_eq!(Key::from_dom("Enter"), Key::Named(NamedKey::Enter));
+        assert_eq!(Key::from_dom("Hyper"), Key::Named(NamedKey::Unidentified));
+
+        assert_eq!(Code::KeyW.us_key(), Key::Character('w'));
+        assert_eq!(Code::Digit7.us_key(), Key::Character('7'));
+        assert_eq!(Code::Numpad7.us_key(), Key::Character('7'));
+        assert_eq!(Code::ShiftRight.us_key(), Key::Named(NamedKey::Shift));
+        assert_eq!(Code::F5.us_key(), Key::Named(NamedKey::F5));
+    }
+}
@@ -0,0 +1,88 @@
+//! Gamepad state, polled the same way as keys.
+//!
+//! Buttons and axes are numbered after the "standard" mapping of the browser
+//! Gamepad API, the `buttons` module lists the button numbers.
+
+use super::KeyCodeState;
+
+pub(super) const BUTTON_MAX: usize = 17;
+pub(super) const AXIS_MAX: usize = 4;
+pub(super) const GAMEPAD_MAX: usize = 4;
+
+/// Button numbers of the standard gamepad mapping.
+pub mod buttons {
+    pub const A: usize = 0;
+    pub const B: usize = 1;
+    pub const X: usize = 2;
+    pub const Y: usize = 3;
+    pub const LEFT_BUMPER: usize = 4;
+    pub const RIGHT_BUMPER: usize = 5;
+    pub const LEFT_TRIGGER: usize = 6;
+    pub const RIGHT_TRIGGER: usize = 7;
+    pub const SELECT: usize = 8;
+    pub const START: usize = 9;
+    pub const LEFT_STICK: usize = 10;
+    pub const RIGHT_STICK: usize = 11;
+    pub const DPAD_UP: usize = 12;
+    pub const DPAD_DOWN: usize = 13;
+    pub const DPAD_LEFT: usize = 14;
+    pub const DPAD_RIGHT: usize = 15;
+    pub const HOME: usize = 16;
+}
+
+/// The analog sticks of the standard mapping. The left stick is axes 0 and 1,
+/// the right stick axes 2 and 3.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stick {
+    Left,
+    Right,
+}
+
+impl Stick {
+    pub(super) fn axes(self) -> (usize, usize) {
+        match self {
+            Stick::Left => (0, 1),
+            Stick::Right => (2, 3),
+        }
+    }
+
+    pub(super) fn of_axis(axis: usize) -> Option<Stick> {
+        match axis {
+            0 | 1 => Some(Stick::Left),
+            2 | 3 => Some(Stick::Right),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct GamepadState {
+    pub(super) connected: bool,
+    pub(super) buttons: [KeyCodeState; BUTTON_MAX],
+    pub(super) axes: [f32; AXIS_MAX],
+    /// The axes as they were at the end of the previous frame.
+    pub(super) previous_axes: [f32; AXIS_MAX],
+}
+
+impl GamepadState {
+    pub(super) fn new() -> GamepadState {
+        GamepadState {
+            connected: false,
+            buttons: [KeyCodeState::None; BUTTON_MAX],
+            axes: [0.0; AXIS_MAX],
+            previous_axes: [0.0; AXIS_MAX],
+        }
+    }
+}
+
+/// Applies a radial deadzone to a stick: anything within `deadzone` of the centre
+/// reads as zero and the rest is rescaled so the edge of the deadzone is 0 and
+/// full tilt is 1.
+pub(super) fn apply_deadzone(x: f32, y: f32, deadzone: f32) -> (f32, f32) {
+    let length = (x * x + y * y).sqrt();
+    if length <= deadzone {
+        return (0.0, 0.0);
+    }
+    let scaled = f32::min(1.0, (length - deadzone) / (1.0 - deadzone));
+    (x / length * scaled, y / length * scaled)
+}
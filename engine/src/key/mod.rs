@@ -1,13 +1,22 @@
-use std::cell::RefCell;
-use std::convert::TryInto;
-use std::rc::Rc;
+pub use code::{Code, Key, NamedKey};
+pub use gamepad::{buttons, Stick};
+pub use pointer::{mouse_buttons, Touch};
+pub use source::{DomInput, InputSource, KeyEvent, ProgrammaticInput};
 
-use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
+use gamepad::{GamepadState, AXIS_MAX, BUTTON_MAX, GAMEPAD_MAX};
+use pointer::MOUSE_BUTTON_MAX;
 
+pub mod action;
+pub mod code;
+pub mod gamepad;
 pub mod key_codes;
+pub mod pointer;
+pub mod replay;
+mod source;
 
 const KEY_CODE_MAX: usize = 300;
+const CODE_MAX: usize = Code::ALL.len();
+const DEFAULT_STICK_DEADZONE: f32 = 0.15;
 
 #[repr(u8)]
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -16,69 +25,263 @@ enum KeyCodeState {
     Down = 1,
     Held = 2,
     Up = 3,
+    /// Pressed and released again before an update saw it. Updates see it
+    /// pressed like `Down`, and the one after sees it `Up`.
+    Tapped = 4,
 }
 
-/// The type managing the state of the keys. Use the `key_code` module for a list
-/// of KeyCodes to query for.
+impl KeyCodeState {
+    /// The state after a release, which keeps a press no update has seen yet.
+    fn released(self) -> KeyCodeState {
+        match self {
+            KeyCodeState::Down | KeyCodeState::Tapped => KeyCodeState::Tapped,
+            _ => KeyCodeState::Up,
+        }
+    }
+
+    /// The state the next update sees, if nothing happens in between.
+    fn advanced(self) -> KeyCodeState {
+        match self {
+            KeyCodeState::Up => KeyCodeState::None,
+            KeyCodeState::Down => KeyCodeState::Held,
+            KeyCodeState::Tapped => KeyCodeState::Up,
+            state => state,
+        }
+    }
+
+    fn is_down(self) -> bool {
+        matches!(self, KeyCodeState::Down | KeyCodeState::Tapped)
+    }
+
+    fn is_pressed(self) -> bool {
+        matches!(
+            self,
+            KeyCodeState::Down | KeyCodeState::Held | KeyCodeState::Tapped
+        )
+    }
+}
+
+/// A logical key that is pressed, remembered together with the physical key
+/// pressing it so it can be released even if the layout or modifiers changed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct LogicalKeyState {
+    code: Code,
+    key: Key,
+    state: KeyCodeState,
+}
+
+/// The type managing the state of the keys, gamepads, mouse and touches. Use the
+/// `key_code` module for a list of KeyCodes, the `buttons` module for gamepad
+/// buttons and the `mouse_buttons` module for mouse buttons to query for.
+///
+/// Keys can also be queried by `Code`, the physical key, and by `Key`, the
+/// logical key under the player's layout. Typed text is collected separately.
 pub struct KeyManager {
-    keys: Rc<RefCell<[KeyCodeState; KEY_CODE_MAX]>>,
+    keys: [KeyCodeState; KEY_CODE_MAX],
+    codes: [KeyCodeState; CODE_MAX],
+    logical_keys: Vec<LogicalKeyState>,
+    text_input: String,
+    gamepads: [GamepadState; GAMEPAD_MAX],
+    stick_deadzone: f32,
+    pointer_pos: Option<na::Point2<f32>>,
+    mouse_buttons: [KeyCodeState; MOUSE_BUTTON_MAX],
+    wheel_delta: na::Vector2<f32>,
+    touches: Vec<Touch>,
+    screen_to_world: na::Matrix3<f32>,
+    source: Box<dyn InputSource>,
+    events: Vec<KeyEvent>,
 }
 
 impl KeyManager {
-    pub(super) fn new() -> KeyManager {
-        let window = web_sys::window().expect("global window does not exists");
-        let keys = Rc::new(RefCell::new([KeyCodeState::None; KEY_CODE_MAX]));
+    /// Creates a KeyManager fed by the given source, `DomInput` in the browser
+    /// or `ProgrammaticInput` anywhere else.
+    pub fn new(source: Box<dyn InputSource>) -> KeyManager {
+        KeyManager {
+            keys: [KeyCodeState::None; KEY_CODE_MAX],
+            codes: [KeyCodeState::None; CODE_MAX],
+            logical_keys: Vec::new(),
+            text_input: String::new(),
+            gamepads: [GamepadState::new(); GAMEPAD_MAX],
+            stick_deadzone: DEFAULT_STICK_DEADZONE,
+            pointer_pos: None,
+            mouse_buttons: [KeyCodeState::None; MOUSE_BUTTON_MAX],
+            wheel_delta: na::Vector2::zeros(),
+            touches: Vec::new(),
+            screen_to_world: na::Matrix3::identity(),
+            source,
+            events: Vec::new(),
+        }
+    }
 
-        // REGISTER CALLBACKS
+    /// Creates a KeyManager that is not connected to any input, all keys stay
+    /// released. Meant for running a world outside of the browser.
+    pub fn headless() -> KeyManager {
+        KeyManager::new(Box::new(ProgrammaticInput::new()))
+    }
 
-        let keys_blur = keys.clone();
-        let onblur = Closure::wrap(Box::new(move |_e: web_sys::FocusEvent| {
-            for key_code in 0..KEY_CODE_MAX {
-                keys_blur.borrow_mut()[key_code] = KeyCodeState::Up;
-            }
-        }) as Box<dyn FnMut(web_sys::FocusEvent)>);
-        window.set_onblur(Some(onblur.as_ref().unchecked_ref()));
-        onblur.forget();
-
-        let keys_keyup = keys.clone();
-        let onkeyup = Closure::wrap(Box::new(move |e: web_sys::KeyboardEvent| {
-            if (e.key_code() as usize) < key_codes::F1 || e.key_code() as usize > key_codes::F12 {
-                e.prevent_default();
-            }
-            if e.key_code() < KEY_CODE_MAX.try_into().unwrap() {
-                keys_keyup.borrow_mut()[e.key_code() as usize] = KeyCodeState::Up;
-            }
-        }) as Box<dyn FnMut(web_sys::KeyboardEvent)>);
-        window.set_onkeyup(Some(onkeyup.as_ref().unchecked_ref()));
-        onkeyup.forget();
-
-        let keys_keydown = keys.clone();
-        let onkeydown = Closure::wrap(Box::new(move |e: web_sys::KeyboardEvent| {
-            if (e.key_code() as usize) < key_codes::F1 || e.key_code() as usize > key_codes::F12 {
-                e.prevent_default();
-            }
-            if e.key_code() < KEY_CODE_MAX.try_into().unwrap() {
-                keys_keydown.borrow_mut()[e.key_code() as usize] = KeyCodeState::Down;
+    /// Applies the events that arrived from the input source since the last frame.
+    pub(super) fn poll_input(&mut self) {
+        self.source.poll(&mut self.events);
+        for event in self.events.drain(..) {
+            match event {
+                KeyEvent::Down(key_code) if key_code < KEY_CODE_MAX => {
+                    self.keys[key_code] = KeyCodeState::Down
+                }
+                KeyEvent::Up(key_code) if key_code < KEY_CODE_MAX => {
+                    self.keys[key_code] = self.keys[key_code].released()
+                }
+                KeyEvent::CodeDown { code, key } => {
+                    self.codes[code as usize] = KeyCodeState::Down;
+                    match self
+                        .logical_keys
+                        .iter_mut()
+                        .find(|pressed| pressed.code == code)
+                    {
+                        // Already held, a key repeat
+                        Some(pressed)
+                            if matches!(pressed.state, KeyCodeState::Down | KeyCodeState::Held) => {
+                        }
+                        Some(pressed) => {
+                            pressed.key = key;
+                            pressed.state = KeyCodeState::Down;
+                        }
+                        None => self.logical_keys.push(LogicalKeyState {
+                            code,
+                            key,
+                            state: KeyCodeState::Down,
+                        }),
+                    }
+                }
+                KeyEvent::CodeUp { code, key } => {
+                    self.codes[code as usize] = self.codes[code as usize].released();
+                    let pressed = self.logical_keys.iter_mut().find(|pressed| {
+                        if code == Code::Unidentified {
+                            pressed.key == key
+                        } else {
+                            pressed.code == code
+                        }
+                    });
+                    if let Some(pressed) = pressed {
+                        pressed.state = pressed.state.released();
+                    }
+                }
+                KeyEvent::Text(character) => self.text_input.push(character),
+                KeyEvent::Blur => {
+                    for state in self.keys.iter_mut().chain(self.codes.iter_mut()) {
+                        *state = state.released();
+                    }
+                    for pressed in self.logical_keys.iter_mut() {
+                        pressed.state = pressed.state.released();
+                    }
+                    for button in self.mouse_buttons.iter_mut() {
+                        *button = button.released();
+                    }
+                    for touch in self.touches.iter_mut() {
+                        touch.state = touch.state.released();
+                    }
+                }
+                KeyEvent::GamepadConnected { gamepad } if gamepad < GAMEPAD_MAX => {
+                    self.gamepads[gamepad].connected = true
+                }
+                KeyEvent::GamepadDisconnected { gamepad } if gamepad < GAMEPAD_MAX => {
+                    let state = &mut self.gamepads[gamepad];
+                    for button in state.buttons.iter_mut() {
+                        if *button != KeyCodeState::None {
+                            *button = button.released();
+                        }
+                    }
+                    state.axes = [0.0; AXIS_MAX];
+                    state.connected = false;
+                }
+                KeyEvent::GamepadButtonDown { gamepad, button }
+                    if gamepad < GAMEPAD_MAX && button < BUTTON_MAX =>
+                {
+                    self.gamepads[gamepad].buttons[button] = KeyCodeState::Down
+                }
+                KeyEvent::GamepadButtonUp { gamepad, button }
+                    if gamepad < GAMEPAD_MAX && button < BUTTON_MAX =>
+                {
+                    let state = &mut self.gamepads[gamepad].buttons[button];
+                    *state = state.released();
+                }
+                KeyEvent::GamepadAxis {
+                    gamepad,
+                    axis,
+                    value,
+                } if gamepad < GAMEPAD_MAX && axis < AXIS_MAX => {
+                    self.gamepads[gamepad].axes[axis] = value.clamp(-1.0, 1.0)
+                }
+                KeyEvent::PointerMove { x, y } => self.pointer_pos = Some(na::Point2::new(x, y)),
+                KeyEvent::PointerLeave => self.pointer_pos = None,
+                KeyEvent::MouseDown(button) if button < MOUSE_BUTTON_MAX => {
+                    self.mouse_buttons[button] = KeyCodeState::Down
+                }
+                KeyEvent::MouseUp(button) if button < MOUSE_BUTTON_MAX => {
+                    self.mouse_buttons[button] = self.mouse_buttons[button].released()
+                }
+                KeyEvent::Wheel { x, y } => self.wheel_delta += na::Vector2::new(x, y),
+                KeyEvent::TouchStart { id, x, y } => {
+                    let touch = Touch {
+                        id,
+                        pos: na::Point2::new(x, y),
+                        state: KeyCodeState::Down,
+                    };
+                    match self.touches.iter_mut().find(|touch| touch.id == id) {
+                        Some(existing) => *existing = touch,
+                        None => self.touches.push(touch),
+                    }
+                }
+                KeyEvent::TouchMove { id, x, y } => {
+                    if let Some(touch) = self.touches.iter_mut().find(|touch| touch.id == id) {
+                        touch.pos = na::Point2::new(x, y);
+                    }
+                }
+                KeyEvent::TouchEnd { id } => {
+                    if let Some(touch) = self.touches.iter_mut().find(|touch| touch.id == id) {
+                        touch.state = touch.state.released();
+                    }
+                }
+                _ => (),
             }
-        }) as Box<dyn FnMut(web_sys::KeyboardEvent)>);
-        window.set_onkeydown(Some(onkeydown.as_ref().unchecked_ref()));
-        onkeydown.forget();
-
-        KeyManager { keys }
+        }
     }
 
     /// Transition key states as we only get KeyCodeState::Up && KeyCodeState::Down
-    /// states set from the listener, it's up to us to transition them to KeyCodeState::None
+    /// states set from the input source, it's up to us to transition them to KeyCodeState::None
     /// && KeyCodeState::Held at the end of the frame.
+    ///
+    /// This runs after every update rather than every frame, so presses and
+    /// releases in frames without an update are still seen by the next one.
     pub(super) fn post_tick_update_key_states(&mut self) {
-        for index in 0..KEY_CODE_MAX {
-            let value = self.keys.borrow()[index];
-            match value {
-                KeyCodeState::Up => self.keys.borrow_mut()[index] = KeyCodeState::None,
-                KeyCodeState::Down => self.keys.borrow_mut()[index] = KeyCodeState::Held,
-                _ => (),
+        for state in self.keys.iter_mut().chain(self.codes.iter_mut()) {
+            *state = state.advanced();
+        }
+        self.logical_keys
+            .retain(|pressed| pressed.state != KeyCodeState::Up);
+        for pressed in self.logical_keys.iter_mut() {
+            pressed.state = pressed.state.advanced();
+        }
+        self.text_input.clear();
+        for gamepad in self.gamepads.iter_mut() {
+            gamepad.previous_axes = gamepad.axes;
+            for button in gamepad.buttons.iter_mut() {
+                *button = button.advanced();
             }
         }
+        for button in self.mouse_buttons.iter_mut() {
+            *button = button.advanced();
+        }
+        self.wheel_delta = na::Vector2::zeros();
+        self.touches.retain(|touch| touch.state != KeyCodeState::Up);
+        for touch in self.touches.iter_mut() {
+            touch.state = touch.state.advanced();
+        }
+    }
+
+    /// Sets the transform from canvas pixels to world coordinates, the engine
+    /// updates it from the renderer every frame.
+    pub(super) fn set_screen_to_world(&mut self, screen_to_world: na::Matrix3<f32>) {
+        self.screen_to_world = screen_to_world;
     }
 
     /// Returns true if key was just pressed.
@@ -86,7 +289,7 @@ impl KeyManager {
     /// A list of KeyCodes can be found in the `key_code` module,
     /// but any KeyCode reported by Javascript is supported.
     pub fn key_down(&self, key_code: usize) -> bool {
-        self.key_state(key_code) == KeyCodeState::Down
+        self.key_state(key_code).is_down()
     }
 
     /// Returns true every frame the key is pressed. This includes
@@ -95,10 +298,7 @@ impl KeyManager {
     /// A list of KeyCodes can be found in the `key_code` module,
     /// but any KeyCode reported by Javascript is supported.
     pub fn key_pressed(&self, key_code: usize) -> bool {
-        match self.key_state(key_code) {
-            KeyCodeState::Down | KeyCodeState::Held => true,
-            _ => false,
-        }
+        self.key_state(key_code).is_pressed()
     }
 
     /// Returns true if key was just released.
@@ -109,8 +309,386 @@ impl KeyManager {
         self.key_state(key_code) == KeyCodeState::Up
     }
 
-    /// Returns the KeyCodeState for a given KeyCode.
+    /// Returns the KeyCodeState for a given KeyCode, KeyCodes out of range are
+    /// never pressed.
     fn key_state(&self, key_code: usize) -> KeyCodeState {
-        self.keys.borrow()[key_code]
+        if key_code < KEY_CODE_MAX {
+            self.keys[key_code]
+        } else {
+            KeyCodeState::None
+        }
+    }
+
+    /// Returns true if the physical key was just pressed.
+    pub fn code_down(&self, code: Code) -> bool {
+        self.codes[code as usize].is_down()
+    }
+
+    /// Returns true every frame the physical key is pressed, including the frame
+    /// where it was just pressed.
+    pub fn code_pressed(&self, code: Code) -> bool {
+        self.codes[code as usize].is_pressed()
+    }
+
+    /// Returns true if the physical key was just released.
+    pub fn code_up(&self, code: Code) -> bool {
+        self.codes[code as usize] == KeyCodeState::Up
+    }
+
+    /// Returns true if a key meaning `key` under the current layout was just pressed.
+    pub fn logical_key_down(&self, key: Key) -> bool {
+        self.logical_key_state(key).is_down()
+    }
+
+    /// Returns true every frame a key meaning `key` under the current layout is
+    /// pressed, including the frame where it was just pressed.
+    pub fn logical_key_pressed(&self, key: Key) -> bool {
+        self.logical_key_state(key).is_pressed()
+    }
+
+    /// Returns true if a key meaning `key` under the current layout was just released.
+    pub fn logical_key_up(&self, key: Key) -> bool {
+        self.logical_key_state(key) == KeyCodeState::Up
+    }
+
+    /// A key pressed by two physical keys at once counts as pressed while either is.
+    fn logical_key_state(&self, key: Key) -> KeyCodeState {
+        let mut states = self
+            .logical_keys
+            .iter()
+            .filter(|pressed| pressed.key == key)
+            .map(|pressed| pressed.state);
+        let first = match states.next() {
+            Some(state) => state,
+            None => return KeyCodeState::None,
+        };
+        states.fold(first, |result, state| match (result, state) {
+            (KeyCodeState::Held, _) | (_, KeyCodeState::Held) => KeyCodeState::Held,
+            (KeyCodeState::Down, _) | (_, KeyCodeState::Down) => KeyCodeState::Down,
+            (KeyCodeState::Tapped, _) | (_, KeyCodeState::Tapped) => KeyCodeState::Tapped,
+            _ => result,
+        })
+    }
+
+    /// Returns the text typed since the last update, including text entered
+    /// through an input method editor.
+    pub fn text_input(&self) -> &str {
+        &self.text_input
+    }
+
+    /// Returns true if a gamepad is plugged in at the given index.
+    pub fn gamepad_connected(&self, gamepad: usize) -> bool {
+        gamepad < GAMEPAD_MAX && self.gamepads[gamepad].connected
+    }
+
+    /// Returns true if any gamepad is plugged in.
+    pub fn any_gamepad_connected(&self) -> bool {
+        self.gamepads.iter().any(|gamepad| gamepad.connected)
+    }
+
+    /// Returns true if the gamepad button was just pressed.
+    pub fn button_down(&self, gamepad: usize, button: usize) -> bool {
+        self.button_state(gamepad, button).is_down()
+    }
+
+    /// Returns true every frame the gamepad button is pressed, including the
+    /// frame where it was just pressed.
+    pub fn button_pressed(&self, gamepad: usize, button: usize) -> bool {
+        self.button_state(gamepad, button).is_pressed()
+    }
+
+    /// Returns true if the gamepad button was just released.
+    pub fn button_up(&self, gamepad: usize, button: usize) -> bool {
+        self.button_state(gamepad, button) == KeyCodeState::Up
+    }
+
+    fn button_state(&self, gamepad: usize, button: usize) -> KeyCodeState {
+        if gamepad < GAMEPAD_MAX && button < BUTTON_MAX {
+            self.gamepads[gamepad].buttons[button]
+        } else {
+            KeyCodeState::None
+        }
+    }
+
+    /// Returns an axis from -1 to 1 in Gamepad API orientation, so down and right
+    /// are positive. Stick axes have the stick deadzone applied.
+    pub fn axis(&self, gamepad: usize, axis: usize) -> f32 {
+        if gamepad >= GAMEPAD_MAX {
+            return 0.0;
+        }
+        self.axis_of(&self.gamepads[gamepad].axes, axis)
+    }
+
+    /// Returns an axis like `axis` does, as it was in the previous frame.
+    pub fn previous_axis(&self, gamepad: usize, axis: usize) -> f32 {
+        if gamepad >= GAMEPAD_MAX {
+            return 0.0;
+        }
+        self.axis_of(&self.gamepads[gamepad].previous_axes, axis)
+    }
+
+    fn axis_of(&self, axes: &[f32; AXIS_MAX], axis: usize) -> f32 {
+        if axis >= AXIS_MAX {
+            return 0.0;
+        }
+        match Stick::of_axis(axis) {
+            Some(stick) => {
+                let (x_axis, y_axis) = stick.axes();
+                let (x, y) =
+                    gamepad::apply_deadzone(axes[x_axis], axes[y_axis], self.stick_deadzone);
+                if axis == x_axis {
+                    x
+                } else {
+                    y
+                }
+            }
+            None => axes[axis],
+        }
+    }
+
+    /// Returns how far a stick is tilted with the deadzone applied, with y pointing
+    /// up like the rest of the engine.
+    pub fn stick(&self, gamepad: usize, stick: Stick) -> na::Vector2<f32> {
+        let (x_axis, y_axis) = stick.axes();
+        na::Vector2::new(self.axis(gamepad, x_axis), -self.axis(gamepad, y_axis))
+    }
+
+    pub fn get_stick_deadzone(&self) -> f32 {
+        self.stick_deadzone
+    }
+
+    /// Sets how far a stick has to be tilted before it registers, from 0 to 1.
+    pub fn set_stick_deadzone(&mut self, deadzone: f32) {
+        self.stick_deadzone = deadzone.clamp(0.0, 0.99);
+    }
+
+    /// Returns the mouse position in canvas pixels, or `None` if the mouse is not
+    /// over the canvas.
+    pub fn pointer_pos(&self) -> Option<na::Point2<f32>> {
+        self.pointer_pos
+    }
+
+    /// Returns the mouse position in world coordinates, as seen through the
+    /// camera of the last rendered frame.
+    pub fn pointer_world_pos(&self) -> Option<na::Point2<f32>> {
+        self.pointer_pos.map(|pos| self.screen_to_world(pos))
+    }
+
+    /// Converts a position in canvas pixels, like a touch position, to world
+    /// coordinates.
+    pub fn screen_to_world(&self, screen: na::Point2<f32>) -> na::Point2<f32> {
+        self.screen_to_world.transform_point(&screen)
+    }
+
+    /// Returns true if the mouse button was just pressed.
+    pub fn mouse_down(&self, button: usize) -> bool {
+        self.mouse_button_state(button).is_down()
+    }
+
+    /// Returns true every frame the mouse button is pressed, including the frame
+    /// where it was just pressed.
+    pub fn mouse_pressed(&self, button: usize) -> bool {
+        self.mouse_button_state(button).is_pressed()
+    }
+
+    /// Returns true if the mouse button was just released.
+    pub fn mouse_up(&self, button: usize) -> bool {
+        self.mouse_button_state(button) == KeyCodeState::Up
+    }
+
+    fn mouse_button_state(&self, button: usize) -> KeyCodeState {
+        if button < MOUSE_BUTTON_MAX {
+            self.mouse_buttons[button]
+        } else {
+            KeyCodeState::None
+        }
+    }
+
+    /// Returns how far the wheel scrolled since the last update in pixels, positive
+    /// y scrolls down like in the browser.
+    pub fn wheel_delta(&self) -> na::Vector2<f32> {
+        self.wheel_delta
+    }
+
+    /// Returns every finger on the screen, including the ones lifted this frame.
+    pub fn touches(&self) -> &[Touch] {
+        &self.touches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn programmatic_input_goes_through_key_states() {
+        let input = ProgrammaticInput::new();
+        let mut key_manager = KeyManager::new(Box::new(input.clone()));
+
+        input.press(key_codes::E);
+        key_manager.poll_input();
+        assert!(key_manager.key_down(key_codes::E));
+        assert!(key_manager.key_pressed(key_codes::E));
+
+        key_manager.post_tick_update_key_states();
+        key_manager.poll_input();
+        assert!(!key_manager.key_down(key_codes::E));
+        assert!(key_manager.key_pressed(key_codes::E));
+
+        key_manager.post_tick_update_key_states();
+        input.release(key_codes::E);
+        key_manager.poll_input();
+        assert!(key_manager.key_up(key_codes::E));
+        assert!(!key_manager.key_pressed(key_codes::E));
+
+        key_manager.post_tick_update_key_states();
+        input.press(key_codes::SPACE);
+        input.blur();
+        key_manager.poll_input();
+        // Losing focus releases everything, pressed or not, but a press no
+        // update has seen is still seen by the next one
+        assert!(key_manager.key_down(key_codes::SPACE));
+        assert!(key_manager.key_up(key_codes::E));
+        key_manager.post_tick_update_key_states();
+        assert!(key_manager.key_up(key_codes::SPACE));
+        assert!(!key_manager.key_pressed(key_codes::SPACE));
+
+        // Pressed and released between two updates
+        key_manager.post_tick_update_key_states();
+        input.press(key_codes::E);
+        key_manager.poll_input();
+        input.release(key_codes::E);
+        key_manager.poll_input();
+        assert!(key_manager.key_down(key_codes::E));
+        assert!(!key_manager.key_up(key_codes::E));
+        key_manager.post_tick_update_key_states();
+        assert!(key_manager.key_up(key_codes::E));
+    }
+
+    #[test]
+    fn gamepads_go_through_button_states_and_deadzones() {
+        let input = ProgrammaticInput::new();
+        let mut key_manager = KeyManager::new(Box::new(input.clone()));
+        assert!(!key_manager.any_gamepad_connected());
+
+        input.connect_gamepad(1);
+        input.press_button(1, buttons::A);
+        input.move_stick(1, Stick::Left, na::Vector2::new(0.1, 0.0));
+        key_manager.poll_input();
+        assert!(key_manager.gamepad_connected(1));
+        assert!(!key_manager.gamepad_connected(0));
+        assert!(key_manager.button_down(1, buttons::A));
+        assert!(!key_manager.button_pressed(0, buttons::A));
+        // Inside the deadzone
+        assert_eq!(
+            key_manager.stick(1, Stick::Left),
+            na::Vector2::new(0.0, 0.0)
+        );
+
+        key_manager.post_tick_update_key_states();
+        input.move_stick(1, Stick::Left, na::Vector2::new(0.0, 1.0));
+        key_manager.poll_input();
+        assert!(!key_manager.button_down(1, buttons::A));
+        assert!(key_manager.button_pressed(1, buttons::A));
+        assert_eq!(
+            key_manager.stick(1, Stick::Left),
+            na::Vector2::new(0.0, 1.0)
+        );
+        assert_eq!(key_manager.axis(1, 1), -1.0);
+
+        key_manager.set_stick_deadzone(0.5);
+        input.move_stick(1, Stick::Left, na::Vector2::new(0.75, 0.0));
+        key_manager.poll_input();
+        assert_eq!(
+            key_manager.stick(1, Stick::Left),
+            na::Vector2::new(0.5, 0.0)
+        );
+
+        input.disconnect_gamepad(1);
+        key_manager.poll_input();
+        assert!(!key_manager.gamepad_connected(1));
+        assert!(key_manager.button_up(1, buttons::A));
+        assert_eq!(
+            key_manager.stick(1, Stick::Left),
+            na::Vector2::new(0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn tracks_mouse_and_touches() {
+        let input = ProgrammaticInput::new();
+        let mut key_manager = KeyManager::new(Box::new(input.clone()));
+        key_manager.set_screen_to_world(na::Matrix3::new(
+            2.0, 0.0, -100.0, 0.0, -2.0, 100.0, 0.0, 0.0, 1.0,
+        ));
+        assert_eq!(key_manager.pointer_pos(), None);
+
+        input.move_pointer(na::Point2::new(10.0, 20.0));
+        input.press_mouse(mouse_buttons::LEFT);
+        input.scroll(na::Vector2::new(0.0, 3.0));
+        input.scroll(na::Vector2::new(0.0, 5.0));
+        input.touch_start(7, na::Point2::new(1.0, 1.0));
+        key_manager.poll_input();
+        assert_eq!(
+            key_manager.pointer_world_pos(),
+            Some(na::Point2::new(-80.0, 60.0))
+        );
+        assert!(key_manager.mouse_down(mouse_buttons::LEFT));
+        assert!(!key_manager.mouse_pressed(mouse_buttons::RIGHT));
+        assert_eq!(key_manager.wheel_delta(), na::Vector2::new(0.0, 8.0));
+        assert!(key_manager.touches()[0].is_down());
+
+        key_manager.post_tick_update_key_states();
+        input.release_mouse(mouse_buttons::LEFT);
+        input.touch_move(7, na::Point2::new(4.0, 5.0));
+        input.touch_start(8, na::Point2::new(0.0, 0.0));
+        key_manager.poll_input();
+        assert!(key_manager.mouse_up(mouse_buttons::LEFT));
+        assert_eq!(key_manager.wheel_delta(), na::Vector2::zeros());
+        let touch = key_manager.touches()[0];
+        assert_eq!(touch.get_id(), 7);
+        assert!(!touch.is_down() && !touch.is_up());
+        assert_eq!(touch.get_pos(), na::Point2::new(4.0, 5.0));
+
+        key_manager.post_tick_update_key_states();
+        input.touch_end(7);
+        input.leave_pointer();
+        key_manager.poll_input();
+        assert_eq!(key_manager.pointer_pos(), None);
+        assert!(key_manager.touches()[0].is_up());
+        key_manager.post_tick_update_key_states();
+        assert_eq!(key_manager.touches().len(), 1);
+        assert_eq!(key_manager.touches()[0].get_id(), 8);
+    }
+
+    #[test]
+    fn tracks_physical_and_logical_keys_and_text() {
+        let input = ProgrammaticInput::new();
+        let mut key_manager = KeyManager::new(Box::new(input.clone()));
+
+        // The key labelled Z on an AZERTY keyboard, with shift held
+        input.press_code(Code::ShiftLeft);
+        input.press_code_as(Code::KeyW, Key::from_dom("Z"));
+        input.type_text("Zoé");
+        key_manager.poll_input();
+        assert!(key_manager.code_down(Code::KeyW));
+        assert!(key_manager.logical_key_down(Key::Character('z')));
+        assert!(!key_manager.logical_key_pressed(Key::Character('w')));
+        assert!(key_manager.logical_key_pressed(Key::Named(NamedKey::Shift)));
+        assert_eq!(key_manager.text_input(), "Zoé");
+
+        key_manager.post_tick_update_key_states();
+        // Released after shift, the browser reports a lower case key now
+        input.release_code(Code::ShiftLeft);
+        input.release_code_as(Code::KeyW, Key::from_dom("z"));
+        key_manager.poll_input();
+        assert_eq!(key_manager.text_input(), "");
+        assert!(key_manager.code_up(Code::KeyW));
+        assert!(key_manager.logical_key_up(Key::Character('z')));
+        assert!(!key_manager.code_pressed(Code::ShiftLeft));
+
+        key_manager.post_tick_update_key_states();
+        assert!(!key_manager.logical_key_up(Key::Character('z')));
+        assert!(!key_manager.code_up(Code::KeyW));
     }
 }
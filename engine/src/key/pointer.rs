@@ -0,0 +1,55 @@
+//! Mouse and touch state, polled the same way as keys.
+//!
+//! Positions are in canvas pixels with the origin in the top left corner and y
+//! pointing down. The `KeyManager` converts them to world coordinates with the
+//! camera of the last rendered frame.
+
+use super::KeyCodeState;
+
+pub(super) const MOUSE_BUTTON_MAX: usize = 5;
+
+/// Wheel deltas given in lines are converted to pixels with this line height.
+pub(super) const WHEEL_LINE_HEIGHT: f32 = 16.0;
+/// Wheel deltas given in pages are converted to pixels with this page height.
+pub(super) const WHEEL_PAGE_HEIGHT: f32 = 800.0;
+
+/// Button numbers as reported by `MouseEvent.button`.
+pub mod mouse_buttons {
+    pub const LEFT: usize = 0;
+    pub const MIDDLE: usize = 1;
+    pub const RIGHT: usize = 2;
+    pub const BACK: usize = 3;
+    pub const FORWARD: usize = 4;
+}
+
+/// A finger on the screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Touch {
+    pub(super) id: i32,
+    pub(super) pos: na::Point2<f32>,
+    pub(super) state: KeyCodeState,
+}
+
+impl Touch {
+    /// The identifier the browser gave this touch, it stays the same while the
+    /// finger is on the screen.
+    pub fn get_id(&self) -> i32 {
+        self.id
+    }
+
+    /// The position in canvas pixels.
+    pub fn get_pos(&self) -> na::Point2<f32> {
+        self.pos
+    }
+
+    /// Returns true if the finger just touched the screen.
+    pub fn is_down(&self) -> bool {
+        self.state.is_down()
+    }
+
+    /// Returns true if the finger was just lifted, this is the last frame the
+    /// touch is reported.
+    pub fn is_up(&self) -> bool {
+        self.state == KeyCodeState::Up
+    }
+}
@@ -0,0 +1,794 @@
+//! Recording and playback of key, gamepad, mouse and touch states, so a run can
+//! be reproduced exactly.
+//!
+//! A `Recorder` stores the key states at the start of every frame together with
+//! the frame's timestamp. Only keys, buttons and gamepad inputs whose state
+//! changed since the previous frame are stored. A `Playback` restores those states into a
+//! `KeyManager` frame by frame, feeding the same timestamps back into the engine.
+use std::convert::TryInto;
+
+use super::gamepad::{GamepadState, AXIS_MAX, BUTTON_MAX, GAMEPAD_MAX};
+use super::pointer::MOUSE_BUTTON_MAX;
+use super::{
+    Code, Key, KeyCodeState, KeyManager, LogicalKeyState, NamedKey, Touch, CODE_MAX, KEY_CODE_MAX,
+};
+
+const MAGIC: &[u8; 8] = b"LD46KEYS";
+const VERSION: u8 = 1;
+
+/// A key state change at the start of a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyChange {
+    pub key_code: u16,
+    state: KeyCodeState,
+}
+
+/// A gamepad state change at the start of a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GamepadChange {
+    Connected {
+        gamepad: u8,
+        connected: bool,
+    },
+    Button {
+        gamepad: u8,
+        button: u8,
+        state: KeyCodeState,
+    },
+    Axis {
+        gamepad: u8,
+        axis: u8,
+        value: f32,
+    },
+}
+
+/// A physical key state change at the start of a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct CodeChange {
+    code: Code,
+    state: KeyCodeState,
+}
+
+/// A mouse button state change at the start of a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct MouseChange {
+    button: u8,
+    state: KeyCodeState,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedFrame {
+    pub timestamp: f64,
+    pub changes: Vec<KeyChange>,
+    gamepad_changes: Vec<GamepadChange>,
+    code_changes: Vec<CodeChange>,
+    /// Every logical key pressed at the start of the frame, not just the changes.
+    logical_keys: Vec<LogicalKeyState>,
+    text: String,
+    mouse_changes: Vec<MouseChange>,
+    pointer_pos: Option<na::Point2<f32>>,
+    wheel_delta: na::Vector2<f32>,
+    /// Every touch at the start of the frame, not just the changes.
+    touches: Vec<Touch>,
+    /// The new transform to world coordinates, if it changed.
+    screen_to_world: Option<na::Matrix3<f32>>,
+}
+
+/// A sequence of recorded frames, which can be written to and read from bytes.
+///
+/// The format is little endian: the magic `LD46KEYS`, a version byte and a `u32`
+/// frame count, followed by each frame as an `f64` timestamp, a `u16` change count
+/// and then a `u16` key code and `u8` state for every change. A frame continues
+/// with a `u16` gamepad change count, and every gamepad change is a `u8` kind
+/// and `u8` gamepad index followed by a `u8` connected flag (kind 0), a `u8`
+/// button and `u8` state (kind 1) or a `u8` axis and `f32` value (kind 2). It
+/// ends with a `u16` count of `u8` code and `u8` state pairs, a `u8` count of
+/// pressed logical keys and the text typed as a `u16` byte length and UTF-8,
+/// then a `u8` count of `u8` mouse button and `u8` state pairs, the pointer as
+/// a `u8` flag followed by two `f32`s if it's over the canvas, the wheel delta
+/// as two `f32`s, a `u8` count of touches and a `u8` flag followed by the nine
+/// `f32`s of the new screen to world transform, column by column, if it
+/// changed. A touch is an `i32` ID, two `f32`s and a `u8` state. A logical key is a `u8` code, a `u8` tag followed by a `u32`
+/// character (tag 0) or a `u8` named key (tag 1), and a `u8` state.
+/// The `Recorder` cuts off text, logical keys and touches beyond what their
+/// counts can hold.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording {
+    frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    pub fn get_frames(&self) -> &Vec<RecordedFrame> {
+        &self.frames
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in self.frames.iter() {
+            bytes.extend_from_slice(&frame.timestamp.to_le_bytes());
+            bytes.extend_from_slice(&(frame.changes.len() as u16).to_le_bytes());
+            for change in frame.changes.iter() {
+                bytes.extend_from_slice(&change.key_code.to_le_bytes());
+                bytes.push(change.state as u8);
+            }
+            bytes.extend_from_slice(&(frame.gamepad_changes.len() as u16).to_le_bytes());
+            for change in frame.gamepad_changes.iter() {
+                match *change {
+                    GamepadChange::Connected { gamepad, connected } => {
+                        bytes.extend_from_slice(&[0, gamepad, connected as u8]);
+                    }
+                    GamepadChange::Button {
+                        gamepad,
+                        button,
+                        state,
+                    } => {
+                        bytes.extend_from_slice(&[1, gamepad, button, state as u8]);
+                    }
+                    GamepadChange::Axis {
+                        gamepad,
+                        axis,
+                        value,
+                    } => {
+                        bytes.extend_from_slice(&[2, gamepad, axis]);
+                        bytes.extend_from_slice(&value.to_le_bytes());
+                    }
+                }
+            }
+            bytes.extend_from_slice(&(frame.code_changes.len() as u16).to_le_bytes());
+            for change in frame.code_changes.iter() {
+                bytes.extend_from_slice(&[change.code as u8, change.state as u8]);
+            }
+            let logical_key_count: u8 = frame.logical_keys.len().try_into().unwrap();
+            bytes.push(logical_key_count);
+            for pressed in frame.logical_keys.iter() {
+                bytes.push(pressed.code as u8);
+                match pressed.key {
+                    Key::Character(character) => {
+                        bytes.push(0);
+                        bytes.extend_from_slice(&(character as u32).to_le_bytes());
+                    }
+                    Key::Named(named) => bytes.extend_from_slice(&[1, named as u8]),
+                }
+                bytes.push(pressed.state as u8);
+            }
+            let text_length: u16 = frame.text.len().try_into().unwrap();
+            bytes.extend_from_slice(&text_length.to_le_bytes());
+            bytes.extend_from_slice(frame.text.as_bytes());
+            bytes.push(frame.mouse_changes.len() as u8);
+            for change in frame.mouse_changes.iter() {
+                bytes.extend_from_slice(&[change.button, change.state as u8]);
+            }
+            match frame.pointer_pos {
+                Some(pos) => {
+                    bytes.push(1);
+                    write_f32s(&mut bytes, pos.coords.as_slice());
+                }
+                None => bytes.push(0),
+            }
+            write_f32s(&mut bytes, frame.wheel_delta.as_slice());
+            let touch_count: u8 = frame.touches.len().try_into().unwrap();
+            bytes.push(touch_count);
+            for touch in frame.touches.iter() {
+                bytes.extend_from_slice(&touch.id.to_le_bytes());
+                write_f32s(&mut bytes, touch.pos.coords.as_slice());
+                bytes.push(touch.state as u8);
+            }
+            match frame.screen_to_world {
+                Some(screen_to_world) => {
+                    bytes.push(1);
+                    write_f32s(&mut bytes, screen_to_world.as_slice());
+                }
+                None => bytes.push(0),
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Recording, String> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(String::from("Not a key recording"));
+        }
+        let version = reader.take(1)?[0];
+        if version != VERSION {
+            return Err(format!("Unsupported key recording version {}", version));
+        }
+
+        let frame_count = u32::from_le_bytes(reader.take(4)?.try_into().unwrap());
+        let mut frames = Vec::with_capacity(frame_count as usize);
+        for _ in 0..frame_count {
+            let timestamp = f64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+            let change_count = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+            let mut changes = Vec::with_capacity(change_count as usize);
+            for _ in 0..change_count {
+                let key_code = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+                if key_code as usize >= KEY_CODE_MAX {
+                    return Err(format!("Key code {} out of range", key_code));
+                }
+                let state = read_state(&mut reader)?;
+                changes.push(KeyChange { key_code, state });
+            }
+            let change_count = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+            let mut gamepad_changes = Vec::with_capacity(change_count as usize);
+            for _ in 0..change_count {
+                gamepad_changes.push(read_gamepad_change(&mut reader)?);
+            }
+            let change_count = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+            let mut code_changes = Vec::with_capacity(change_count as usize);
+            for _ in 0..change_count {
+                let code = read_code(&mut reader)?;
+                let state = read_state(&mut reader)?;
+                code_changes.push(CodeChange { code, state });
+            }
+            let logical_key_count = reader.take(1)?[0];
+            let mut logical_keys = Vec::with_capacity(logical_key_count as usize);
+            for _ in 0..logical_key_count {
+                logical_keys.push(read_logical_key(&mut reader)?);
+            }
+            let text_length = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+            let text = String::from_utf8(reader.take(text_length as usize)?.to_vec())
+                .map_err(|_| String::from("Recorded text is not UTF-8"))?;
+            let change_count = reader.take(1)?[0];
+            let mut mouse_changes = Vec::with_capacity(change_count as usize);
+            for _ in 0..change_count {
+                let button = reader.take(1)?[0];
+                if button as usize >= MOUSE_BUTTON_MAX {
+                    return Err(format!("Mouse button {} out of range", button));
+                }
+                let state = read_state(&mut reader)?;
+                mouse_changes.push(MouseChange { button, state });
+            }
+            let pointer_pos = match read_flag(&mut reader)? {
+                true => Some(na::Point2::new(
+                    read_f32(&mut reader)?,
+                    read_f32(&mut reader)?,
+                )),
+                false => None,
+            };
+            let wheel_delta = na::Vector2::new(read_f32(&mut reader)?, read_f32(&mut reader)?);
+            let touch_count = reader.take(1)?[0];
+            let mut touches = Vec::with_capacity(touch_count as usize);
+            for _ in 0..touch_count {
+                let id = i32::from_le_bytes(reader.take(4)?.try_into().unwrap());
+                let pos = na::Point2::new(read_f32(&mut reader)?, read_f32(&mut reader)?);
+                let state = read_state(&mut reader)?;
+                touches.push(Touch { id, pos, state });
+            }
+            let screen_to_world = match read_flag(&mut reader)? {
+                true => {
+                    let mut values = [0.0; 9];
+                    for value in values.iter_mut() {
+                        *value = read_f32(&mut reader)?;
+                    }
+                    Some(na::Matrix3::from_column_slice(&values))
+                }
+                false => None,
+            };
+            frames.push(RecordedFrame {
+                timestamp,
+                changes,
+                gamepad_changes,
+                code_changes,
+                logical_keys,
+                text,
+                mouse_changes,
+                pointer_pos,
+                wheel_delta,
+                touches,
+                screen_to_world,
+            });
+        }
+
+        if reader.position != bytes.len() {
+            return Err(String::from("Trailing bytes after key recording"));
+        }
+        Ok(Recording { frames })
+    }
+}
+
+fn write_f32s(bytes: &mut Vec<u8>, values: &[f32]) {
+    for value in values.iter() {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn read_f32(reader: &mut Reader) -> Result<f32, String> {
+    Ok(f32::from_le_bytes(reader.take(4)?.try_into().unwrap()))
+}
+
+fn read_flag(reader: &mut Reader) -> Result<bool, String> {
+    match reader.take(1)?[0] {
+        0 => Ok(false),
+        1 => Ok(true),
+        flag => Err(format!("Unknown flag {}", flag)),
+    }
+}
+
+fn read_state(reader: &mut Reader) -> Result<KeyCodeState, String> {
+    match reader.take(1)?[0] {
+        0 => Ok(KeyCodeState::None),
+        1 => Ok(KeyCodeState::Down),
+        2 => Ok(KeyCodeState::Held),
+        3 => Ok(KeyCodeState::Up),
+        4 => Ok(KeyCodeState::Tapped),
+        state => Err(format!("Unknown key state {}", state)),
+    }
+}
+
+fn read_gamepad_change(reader: &mut Reader) -> Result<GamepadChange, String> {
+    let header = reader.take(2)?;
+    let (kind, gamepad) = (header[0], header[1]);
+    if gamepad as usize >= GAMEPAD_MAX {
+        return Err(format!("Gamepad {} out of range", gamepad));
+    }
+    match kind {
+        0 => Ok(GamepadChange::Connected {
+            gamepad,
+            connected: reader.take(1)?[0] != 0,
+        }),
+        1 => {
+            let button = reader.take(1)?[0];
+            if button as usize >= BUTTON_MAX {
+                return Err(format!("Gamepad button {} out of range", button));
+            }
+            let state = read_state(reader)?;
+            Ok(GamepadChange::Button {
+                gamepad,
+                button,
+                state,
+            })
+        }
+        2 => {
+            let axis = reader.take(1)?[0];
+            if axis as usize >= AXIS_MAX {
+                return Err(format!("Gamepad axis {} out of range", axis));
+            }
+            let value = f32::from_le_bytes(reader.take(4)?.try_into().unwrap());
+            Ok(GamepadChange::Axis {
+                gamepad,
+                axis,
+                value,
+            })
+        }
+        kind => Err(format!("Unknown gamepad change {}", kind)),
+    }
+}
+
+fn read_code(reader: &mut Reader) -> Result<Code, String> {
+    let code = reader.take(1)?[0];
+    Code::ALL
+        .get(code as usize)
+        .copied()
+        .ok_or_else(|| format!("Code {} out of range", code))
+}
+
+fn read_logical_key(reader: &mut Reader) -> Result<LogicalKeyState, String> {
+    let code = read_code(reader)?;
+    let key = match reader.take(1)?[0] {
+        0 => {
+            let character = u32::from_le_bytes(reader.take(4)?.try_into().unwrap());
+            Key::Character(
+                std::char::from_u32(character)
+                    .ok_or_else(|| format!("Invalid character {}", character))?,
+            )
+        }
+        1 => {
+            let named = reader.take(1)?[0];
+            Key::Named(
+                NamedKey::ALL
+                    .get(named as usize)
+                    .copied()
+                    .ok_or_else(|| format!("Named key {} out of range", named))?,
+            )
+        }
+        tag => return Err(format!("Unknown logical key {}", tag)),
+    };
+    let state = read_state(reader)?;
+    Ok(LogicalKeyState { code, key, state })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.position + count > self.bytes.len() {
+            return Err(String::from("Unexpected end of key recording"));
+        }
+        let taken = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        Ok(taken)
+    }
+}
+
+/// Captures the input states of a `KeyManager` once per frame.
+pub struct Recorder {
+    recording: Recording,
+    keys: [KeyCodeState; KEY_CODE_MAX],
+    gamepads: [GamepadState; GAMEPAD_MAX],
+    codes: [KeyCodeState; CODE_MAX],
+    mouse_buttons: [KeyCodeState; MOUSE_BUTTON_MAX],
+    screen_to_world: na::Matrix3<f32>,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder {
+            recording: Recording::default(),
+            keys: [KeyCodeState::None; KEY_CODE_MAX],
+            gamepads: [GamepadState::new(); GAMEPAD_MAX],
+            codes: [KeyCodeState::None; CODE_MAX],
+            mouse_buttons: [KeyCodeState::None; MOUSE_BUTTON_MAX],
+            screen_to_world: na::Matrix3::identity(),
+        }
+    }
+
+    /// Records the key states a frame at `timestamp` is about to see.
+    pub fn record_frame(&mut self, key_manager: &KeyManager, timestamp: f64) {
+        let mut changes = Vec::new();
+        for (key_code, state) in key_manager.keys.iter().enumerate() {
+            if *state != self.keys[key_code] {
+                changes.push(KeyChange {
+                    key_code: key_code as u16,
+                    state: *state,
+                });
+                self.keys[key_code] = *state;
+            }
+        }
+
+        let mut gamepad_changes = Vec::new();
+        for (index, gamepad) in key_manager.gamepads.iter().enumerate() {
+            let recorded = &mut self.gamepads[index];
+            if gamepad.connected != recorded.connected {
+                gamepad_changes.push(GamepadChange::Connected {
+                    gamepad: index as u8,
+                    connected: gamepad.connected,
+                });
+            }
+            for (button, state) in gamepad.buttons.iter().enumerate() {
+                if *state != recorded.buttons[button] {
+                    gamepad_changes.push(GamepadChange::Button {
+                        gamepad: index as u8,
+                        button: button as u8,
+                        state: *state,
+                    });
+                }
+            }
+            for (axis, value) in gamepad.axes.iter().enumerate() {
+                if *value != recorded.axes[axis] {
+                    gamepad_changes.push(GamepadChange::Axis {
+                        gamepad: index as u8,
+                        axis: axis as u8,
+                        value: *value,
+                    });
+                }
+            }
+            *recorded = *gamepad;
+        }
+
+        let mut code_changes = Vec::new();
+        for (index, state) in key_manager.codes.iter().enumerate() {
+            if *state != self.codes[index] {
+                code_changes.push(CodeChange {
+                    code: Code::ALL[index],
+                    state: *state,
+                });
+                self.codes[index] = *state;
+            }
+        }
+
+        let mut mouse_changes = Vec::new();
+        for (button, state) in key_manager.mouse_buttons.iter().enumerate() {
+            if *state != self.mouse_buttons[button] {
+                mouse_changes.push(MouseChange {
+                    button: button as u8,
+                    state: *state,
+                });
+                self.mouse_buttons[button] = *state;
+            }
+        }
+
+        let screen_to_world = if key_manager.screen_to_world != self.screen_to_world {
+            self.screen_to_world = key_manager.screen_to_world;
+            Some(self.screen_to_world)
+        } else {
+            None
+        };
+
+        self.recording.frames.push(RecordedFrame {
+            timestamp,
+            changes,
+            gamepad_changes,
+            code_changes,
+            // Clamped to what their lengths in the byte format can count, so a
+            // huge paste doesn't throw off the frames read after it
+            logical_keys: key_manager
+                .logical_keys
+                .iter()
+                .take(u8::MAX as usize)
+                .copied()
+                .collect(),
+            text: truncate(&key_manager.text_input, u16::MAX as usize).to_string(),
+            mouse_changes,
+            pointer_pos: key_manager.pointer_pos,
+            wheel_delta: key_manager.wheel_delta,
+            touches: key_manager
+                .touches
+                .iter()
+                .take(u8::MAX as usize)
+                .copied()
+                .collect(),
+            screen_to_world,
+        });
+    }
+
+    pub fn finish(self) -> Recording {
+        self.recording
+    }
+}
+
+/// The longest start of `text` that's at most `max` bytes long.
+fn truncate(text: &str, max: usize) -> &str {
+    let mut end = max.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Recorder::new()
+    }
+}
+
+/// Replays a `Recording` into a `KeyManager`.
+pub struct Playback {
+    recording: Recording,
+    position: usize,
+    keys: [KeyCodeState; KEY_CODE_MAX],
+    gamepads: [GamepadState; GAMEPAD_MAX],
+    codes: [KeyCodeState; CODE_MAX],
+    mouse_buttons: [KeyCodeState; MOUSE_BUTTON_MAX],
+    screen_to_world: na::Matrix3<f32>,
+}
+
+impl Playback {
+    pub fn new(recording: Recording) -> Playback {
+        Playback {
+            recording,
+            position: 0,
+            keys: [KeyCodeState::None; KEY_CODE_MAX],
+            gamepads: [GamepadState::new(); GAMEPAD_MAX],
+            codes: [KeyCodeState::None; CODE_MAX],
+            mouse_buttons: [KeyCodeState::None; MOUSE_BUTTON_MAX],
+            screen_to_world: na::Matrix3::identity(),
+        }
+    }
+
+    /// Restores the key states of the next recorded frame and returns its
+    /// timestamp, or `None` once the recording is over.
+    pub fn next_frame(&mut self, key_manager: &mut KeyManager) -> Option<f64> {
+        let frame = self.recording.frames.get(self.position)?;
+        self.position += 1;
+        for change in frame.changes.iter() {
+            self.keys[change.key_code as usize] = change.state;
+        }
+        for change in frame.gamepad_changes.iter() {
+            match *change {
+                GamepadChange::Connected { gamepad, connected } => {
+                    self.gamepads[gamepad as usize].connected = connected
+                }
+                GamepadChange::Button {
+                    gamepad,
+                    button,
+                    state,
+                } => self.gamepads[gamepad as usize].buttons[button as usize] = state,
+                GamepadChange::Axis {
+                    gamepad,
+                    axis,
+                    value,
+                } => self.gamepads[gamepad as usize].axes[axis as usize] = value,
+            }
+        }
+        for change in frame.code_changes.iter() {
+            self.codes[change.code as usize] = change.state;
+        }
+        key_manager.keys = self.keys;
+        key_manager.gamepads = self.gamepads;
+        key_manager.codes = self.codes;
+        for change in frame.mouse_changes.iter() {
+            self.mouse_buttons[change.button as usize] = change.state;
+        }
+        if let Some(screen_to_world) = frame.screen_to_world {
+            self.screen_to_world = screen_to_world;
+        }
+        key_manager.logical_keys = frame.logical_keys.clone();
+        key_manager.text_input = frame.text.clone();
+        key_manager.mouse_buttons = self.mouse_buttons;
+        key_manager.pointer_pos = frame.pointer_pos;
+        key_manager.wheel_delta = frame.wheel_delta;
+        key_manager.touches = frame.touches.clone();
+        key_manager.screen_to_world = self.screen_to_world;
+        Some(frame.timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{buttons, key_codes, mouse_buttons, ProgrammaticInput, Stick};
+    use super::*;
+    use crate::driver::NativeDriver;
+    use crate::renderer::{Renderer, SoftwareBackend};
+    use crate::{Engine, GameObject, World};
+
+    /// Walks right while D is held or the left stick is tilted, back while the
+    /// physical A key is held, and hops up whenever space or A is pressed or a
+    /// character is typed. Clicks pull it towards the pointer, scrolling moves it
+    /// up and every finger on the screen pushes it right.
+    struct WalkingWorld {
+        game_objects: Vec<GameObject>,
+    }
+
+    impl World for WalkingWorld {
+        fn update(&mut self, key_manager: &KeyManager, dt: f64) {
+            let walker = &mut self.game_objects[0];
+            if key_manager.key_pressed(key_codes::D) {
+                walker.pos.x += 0.3 * dt as f32;
+            }
+            walker.pos.x += key_manager.stick(0, Stick::Left).x * dt as f32;
+            if key_manager.code_pressed(Code::KeyA) {
+                walker.pos.x -= 0.1 * dt as f32;
+            }
+            walker.pos.y += key_manager.text_input().len() as f32;
+            if key_manager.key_down(key_codes::SPACE) || key_manager.button_down(0, buttons::A) {
+                walker.pos.y += 1.0;
+            }
+            if key_manager.mouse_down(mouse_buttons::LEFT) {
+                if let Some(pointer) = key_manager.pointer_world_pos() {
+                    walker.pos += (pointer - walker.pos) * 0.5;
+                }
+            }
+            walker.pos.y += key_manager.wheel_delta().y * 0.01;
+            walker.pos.x += key_manager.touches().len() as f32;
+        }
+
+        fn get_game_objects(&self) -> Vec<&GameObject> {
+            self.game_objects.iter().collect()
+        }
+
+        fn get_game_objects_mut(&mut self) -> Vec<&mut GameObject> {
+            self.game_objects.iter_mut().collect()
+        }
+    }
+
+    fn new_engine(key_manager: KeyManager) -> Engine {
+        let world = WalkingWorld {
+            game_objects: vec![GameObject::new(na::Point2::new(0.0, 0.0))],
+        };
+        Engine::new(
+            Box::new(world),
+            key_manager,
+            Renderer::new(Box::new(SoftwareBackend::new())),
+        )
+    }
+
+    fn walker_pos(engine: &Engine) -> na::Point2<f32> {
+        engine.get_world().get_game_objects()[0].pos
+    }
+
+    #[test]
+    fn replays_a_recorded_run_exactly() {
+        let input = ProgrammaticInput::new();
+        let mut engine = new_engine(KeyManager::new(Box::new(input.clone())));
+        engine.start_recording();
+        // An uneven frame rate, so some frames run no update and some run two
+        let mut driver = NativeDriver::new(23.0, na::Vector2::new(4.0, 3.0));
+        for frame in 0..120 {
+            match frame {
+                10 | 50 => input.press(key_codes::D),
+                30 | 80 => input.release(key_codes::D),
+                40 => input.connect_gamepad(0),
+                60 => input.move_stick(0, Stick::Left, na::Vector2::new(-0.3, 0.2)),
+                65 => input.press_button(0, buttons::A),
+                66 => input.release_button(0, buttons::A),
+                90 => input.disconnect_gamepad(0),
+                95 => input.press_code_as(Code::KeyA, Key::character('q')),
+                100 => {
+                    input.release_code(Code::KeyA);
+                    input.type_text("höhe");
+                }
+                _ => (),
+            }
+            if frame % 7 == 0 {
+                input.press(key_codes::SPACE);
+            } else if frame % 7 == 3 {
+                input.release(key_codes::SPACE);
+            }
+            driver.step(&mut engine);
+        }
+        let recording = engine.stop_recording().unwrap();
+        assert_eq!(recording.get_frames().len(), 120);
+
+        let bytes = recording.to_bytes();
+        let loaded = Recording::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded, recording);
+
+        let mut replayed = new_engine(KeyManager::headless());
+        NativeDriver::new(0.0, na::Vector2::new(4.0, 3.0))
+            .replay(&mut replayed, Playback::new(loaded));
+        assert_eq!(replayed.get_update_count(), engine.get_update_count());
+        assert_eq!(walker_pos(&replayed), walker_pos(&engine));
+        assert!(walker_pos(&engine).x > 0.0);
+        assert!(walker_pos(&engine).y > 0.0);
+    }
+
+    #[test]
+    fn replays_pointer_and_touch_input() {
+        let input = ProgrammaticInput::new();
+        let mut engine = new_engine(KeyManager::new(Box::new(input.clone())));
+        engine.start_recording();
+        let mut driver = NativeDriver::new(17.0, na::Vector2::new(4.0, 3.0));
+        for frame in 0..60 {
+            match frame {
+                5 => input.move_pointer(na::Point2::new(3.0, 1.0)),
+                8 | 30 => input.press_mouse(mouse_buttons::LEFT),
+                9 | 31 => input.release_mouse(mouse_buttons::LEFT),
+                20 => input.scroll(na::Vector2::new(0.0, 120.0)),
+                25 => input.leave_pointer(),
+                29 => input.move_pointer(na::Point2::new(0.0, 3.0)),
+                40 => input.touch_start(7, na::Point2::new(1.0, 1.0)),
+                45 => input.touch_move(7, na::Point2::new(2.0, 1.0)),
+                50 => input.touch_end(7),
+                _ => (),
+            }
+            driver.step(&mut engine);
+        }
+        let recording = engine.stop_recording().unwrap();
+        let loaded = Recording::from_bytes(&recording.to_bytes()).unwrap();
+        assert_eq!(loaded, recording);
+
+        // A different canvas size, the pointer still lands where it was recorded
+        let mut replayed = new_engine(KeyManager::headless());
+        NativeDriver::new(0.0, na::Vector2::new(8.0, 6.0))
+            .replay(&mut replayed, Playback::new(loaded));
+        assert_eq!(walker_pos(&replayed), walker_pos(&engine));
+        assert_ne!(walker_pos(&engine), na::Point2::origin());
+    }
+
+    #[test]
+    fn clamps_text_too_long_for_a_frame() {
+        let mut key_manager = KeyManager::headless();
+        let mut recorder = Recorder::new();
+        key_manager.text_input = "ö".repeat(40000);
+        recorder.record_frame(&key_manager, 0.0);
+        key_manager.text_input = String::from("a");
+        recorder.record_frame(&key_manager, 1.0);
+        let recording = recorder.finish();
+
+        // Cut at a character boundary below the 65535 bytes a frame can hold
+        assert_eq!(recording.get_frames()[0].text, "ö".repeat(32767));
+        assert_eq!(recording.get_frames()[1].text, "a");
+        let loaded = Recording::from_bytes(&recording.to_bytes()).unwrap();
+        assert_eq!(loaded, recording);
+    }
+
+    #[test]
+    fn rejects_broken_recordings() {
+        assert!(Recording::from_bytes(b"LD46KEY").is_err());
+        assert!(Recording::from_bytes(b"NOTKEYS!\x01\x00\x00\x00\x00").is_err());
+
+        let mut bytes = Recording::default().to_bytes();
+        assert_eq!(Recording::from_bytes(&bytes), Ok(Recording::default()));
+        bytes.push(0);
+        assert!(Recording::from_bytes(&bytes).is_err());
+        bytes.pop();
+        bytes[MAGIC.len()] = VERSION + 1;
+        assert!(Recording::from_bytes(&bytes).is_err());
+    }
+}
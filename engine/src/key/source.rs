@@ -0,0 +1,564 @@
+//! Where a `KeyManager` gets its key presses from.
+use std::cell::RefCell;
+use std::convert::TryInto;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use super::gamepad::{Stick, AXIS_MAX, BUTTON_MAX, GAMEPAD_MAX};
+use super::pointer::{WHEEL_LINE_HEIGHT, WHEEL_PAGE_HEIGHT};
+use super::{key_codes, Code, Key, NamedKey, KEY_CODE_MAX};
+
+/// Something that happened to the keys, gamepads, mouse or touches since the last
+/// frame. Positions are in canvas pixels with the origin in the top left corner.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyEvent {
+    Down(usize),
+    Up(usize),
+    /// A physical key was pressed, key repeats are not reported.
+    CodeDown {
+        code: Code,
+        key: Key,
+    },
+    CodeUp {
+        code: Code,
+        key: Key,
+    },
+    /// A character was typed, either by a key or by an input method editor.
+    Text(char),
+    /// Focus was lost, every key counts as released.
+    Blur,
+    GamepadConnected {
+        gamepad: usize,
+    },
+    /// The gamepad is gone, all of its buttons count as released.
+    GamepadDisconnected {
+        gamepad: usize,
+    },
+    GamepadButtonDown {
+        gamepad: usize,
+        button: usize,
+    },
+    GamepadButtonUp {
+        gamepad: usize,
+        button: usize,
+    },
+    /// An axis moved, `value` is from -1 to 1 as reported by the Gamepad API.
+    GamepadAxis {
+        gamepad: usize,
+        axis: usize,
+        value: f32,
+    },
+    PointerMove {
+        x: f32,
+        y: f32,
+    },
+    /// The mouse left the canvas.
+    PointerLeave,
+    MouseDown(usize),
+    MouseUp(usize),
+    /// The wheel scrolled by this many pixels.
+    Wheel {
+        x: f32,
+        y: f32,
+    },
+    TouchStart {
+        id: i32,
+        x: f32,
+        y: f32,
+    },
+    TouchMove {
+        id: i32,
+        x: f32,
+        y: f32,
+    },
+    /// The finger was lifted or the touch was cancelled.
+    TouchEnd {
+        id: i32,
+    },
+}
+
+/// A source of `KeyEvent`s, polled by the `KeyManager` once per frame.
+pub trait InputSource {
+    /// Moves every event received since the previous call into `events`, oldest first.
+    fn poll(&mut self, events: &mut Vec<KeyEvent>);
+}
+
+/// Listens to keyboard and focus events of the browser window and polls the
+/// Gamepad API.
+pub struct DomInput {
+    events: Rc<RefCell<Vec<KeyEvent>>>,
+    gamepads: [PolledGamepad; GAMEPAD_MAX],
+}
+
+/// What a gamepad looked like the last time it was polled.
+#[derive(Clone, Copy)]
+struct PolledGamepad {
+    connected: bool,
+    buttons: [bool; BUTTON_MAX],
+    axes: [f32; AXIS_MAX],
+}
+
+impl PolledGamepad {
+    fn new() -> PolledGamepad {
+        PolledGamepad {
+            connected: false,
+            buttons: [false; BUTTON_MAX],
+            axes: [0.0; AXIS_MAX],
+        }
+    }
+}
+
+impl DomInput {
+    pub fn new() -> DomInput {
+        let window = web_sys::window().expect("global window does not exists");
+        let events = Rc::new(RefCell::new(Vec::new()));
+
+        // REGISTER CALLBACKS
+
+        let events_blur = events.clone();
+        let onblur = Closure::wrap(Box::new(move |_e: web_sys::FocusEvent| {
+            events_blur.borrow_mut().push(KeyEvent::Blur);
+        }) as Box<dyn FnMut(web_sys::FocusEvent)>);
+        window.set_onblur(Some(onblur.as_ref().unchecked_ref()));
+        onblur.forget();
+
+        let events_keyup = events.clone();
+        let onkeyup = Closure::wrap(Box::new(move |e: web_sys::KeyboardEvent| {
+            if should_prevent_default(&e) {
+                e.prevent_default();
+            }
+            let mut events = events_keyup.borrow_mut();
+            if e.key_code() < KEY_CODE_MAX.try_into().unwrap() {
+                events.push(KeyEvent::Up(e.key_code() as usize));
+            }
+            events.push(KeyEvent::CodeUp {
+                code: Code::parse(&e.code()).unwrap_or(Code::Unidentified),
+                key: Key::from_dom(&e.key()),
+            });
+        }) as Box<dyn FnMut(web_sys::KeyboardEvent)>);
+        window.set_onkeyup(Some(onkeyup.as_ref().unchecked_ref()));
+        onkeyup.forget();
+
+        let events_keydown = events.clone();
+        let onkeydown = Closure::wrap(Box::new(move |e: web_sys::KeyboardEvent| {
+            if should_prevent_default(&e) {
+                e.prevent_default();
+            }
+            let mut events = events_keydown.borrow_mut();
+            if e.key_code() < KEY_CODE_MAX.try_into().unwrap() {
+                events.push(KeyEvent::Down(e.key_code() as usize));
+            }
+            let key = Key::from_dom(&e.key());
+            if !e.repeat() {
+                events.push(KeyEvent::CodeDown {
+                    code: Code::parse(&e.code()).unwrap_or(Code::Unidentified),
+                    key,
+                });
+            }
+            // Keys going to an input method editor arrive as compositionend instead
+            if let Key::Character(_) = key {
+                if !e.is_composing() && !e.ctrl_key() && !e.meta_key() {
+                    events.extend(e.key().chars().map(KeyEvent::Text));
+                }
+            }
+        }) as Box<dyn FnMut(web_sys::KeyboardEvent)>);
+        window.set_onkeydown(Some(onkeydown.as_ref().unchecked_ref()));
+        onkeydown.forget();
+
+        // Composition only happens in editable elements, so show and focus one
+        // (a hidden input will do) for text entry; its events bubble up to here.
+        let events_compositionend = events.clone();
+        let oncompositionend = Closure::wrap(Box::new(move |e: web_sys::CompositionEvent| {
+            if let Some(data) = e.data() {
+                events_compositionend
+                    .borrow_mut()
+                    .extend(data.chars().map(KeyEvent::Text));
+            }
+        })
+            as Box<dyn FnMut(web_sys::CompositionEvent)>);
+        window
+            .add_event_listener_with_callback(
+                "compositionend",
+                oncompositionend.as_ref().unchecked_ref(),
+            )
+            .expect("should register compositionend OK");
+        oncompositionend.forget();
+
+        DomInput {
+            events,
+            gamepads: [PolledGamepad::new(); GAMEPAD_MAX],
+        }
+    }
+
+    /// Also listens to mouse, wheel and touch events on the canvas. Right clicks
+    /// and touches no longer open the context menu or scroll the page.
+    pub fn listen_to_pointer(&self, canvas: &web_sys::HtmlCanvasElement) {
+        let events_mousemove = self.events.clone();
+        let canvas_mousemove = canvas.clone();
+        let onmousemove = Closure::wrap(Box::new(move |e: web_sys::MouseEvent| {
+            let (x, y) = canvas_pos(&canvas_mousemove, e.client_x(), e.client_y());
+            events_mousemove
+                .borrow_mut()
+                .push(KeyEvent::PointerMove { x, y });
+        }) as Box<dyn FnMut(web_sys::MouseEvent)>);
+        canvas.set_onmousemove(Some(onmousemove.as_ref().unchecked_ref()));
+        onmousemove.forget();
+
+        let events_mouseleave = self.events.clone();
+        let onmouseleave = Closure::wrap(Box::new(move |_e: web_sys::MouseEvent| {
+            events_mouseleave.borrow_mut().push(KeyEvent::PointerLeave);
+        }) as Box<dyn FnMut(web_sys::MouseEvent)>);
+        canvas.set_onmouseleave(Some(onmouseleave.as_ref().unchecked_ref()));
+        onmouseleave.forget();
+
+        let events_mousedown = self.events.clone();
+        let onmousedown = Closure::wrap(Box::new(move |e: web_sys::MouseEvent| {
+            e.prevent_default();
+            events_mousedown
+                .borrow_mut()
+                .push(KeyEvent::MouseDown(e.button() as usize));
+        }) as Box<dyn FnMut(web_sys::MouseEvent)>);
+        canvas.set_onmousedown(Some(onmousedown.as_ref().unchecked_ref()));
+        onmousedown.forget();
+
+        // Released buttons are reported even when the mouse left the canvas
+        let events_mouseup = self.events.clone();
+        let onmouseup = Closure::wrap(Box::new(move |e: web_sys::MouseEvent| {
+            events_mouseup
+                .borrow_mut()
+                .push(KeyEvent::MouseUp(e.button() as usize));
+        }) as Box<dyn FnMut(web_sys::MouseEvent)>);
+        web_sys::window()
+            .expect("global window does not exists")
+            .set_onmouseup(Some(onmouseup.as_ref().unchecked_ref()));
+        onmouseup.forget();
+
+        let oncontextmenu = Closure::wrap(Box::new(move |e: web_sys::MouseEvent| {
+            e.prevent_default();
+        }) as Box<dyn FnMut(web_sys::MouseEvent)>);
+        canvas.set_oncontextmenu(Some(oncontextmenu.as_ref().unchecked_ref()));
+        oncontextmenu.forget();
+
+        let events_wheel = self.events.clone();
+        let onwheel = Closure::wrap(Box::new(move |e: web_sys::WheelEvent| {
+            e.prevent_default();
+            let scale = match e.delta_mode() {
+                web_sys::WheelEvent::DOM_DELTA_LINE => WHEEL_LINE_HEIGHT,
+                web_sys::WheelEvent::DOM_DELTA_PAGE => WHEEL_PAGE_HEIGHT,
+                _ => 1.0,
+            };
+            events_wheel.borrow_mut().push(KeyEvent::Wheel {
+                x: e.delta_x() as f32 * scale,
+                y: e.delta_y() as f32 * scale,
+            });
+        }) as Box<dyn FnMut(web_sys::WheelEvent)>);
+        canvas.set_onwheel(Some(onwheel.as_ref().unchecked_ref()));
+        onwheel.forget();
+
+        let events_touchstart = self.events.clone();
+        let canvas_touchstart = canvas.clone();
+        let ontouchstart = Closure::wrap(Box::new(move |e: web_sys::TouchEvent| {
+            e.prevent_default();
+            for touch in changed_touches(&e) {
+                let (x, y) = canvas_pos(&canvas_touchstart, touch.client_x(), touch.client_y());
+                events_touchstart.borrow_mut().push(KeyEvent::TouchStart {
+                    id: touch.identifier(),
+                    x,
+                    y,
+                });
+            }
+        }) as Box<dyn FnMut(web_sys::TouchEvent)>);
+        canvas.set_ontouchstart(Some(ontouchstart.as_ref().unchecked_ref()));
+        ontouchstart.forget();
+
+        let events_touchmove = self.events.clone();
+        let canvas_touchmove = canvas.clone();
+        let ontouchmove = Closure::wrap(Box::new(move |e: web_sys::TouchEvent| {
+            e.prevent_default();
+            for touch in changed_touches(&e) {
+                let (x, y) = canvas_pos(&canvas_touchmove, touch.client_x(), touch.client_y());
+                events_touchmove.borrow_mut().push(KeyEvent::TouchMove {
+                    id: touch.identifier(),
+                    x,
+                    y,
+                });
+            }
+        }) as Box<dyn FnMut(web_sys::TouchEvent)>);
+        canvas.set_ontouchmove(Some(ontouchmove.as_ref().unchecked_ref()));
+        ontouchmove.forget();
+
+        let events_touchend = self.events.clone();
+        let ontouchend = Closure::wrap(Box::new(move |e: web_sys::TouchEvent| {
+            e.prevent_default();
+            for touch in changed_touches(&e) {
+                events_touchend.borrow_mut().push(KeyEvent::TouchEnd {
+                    id: touch.identifier(),
+                });
+            }
+        }) as Box<dyn FnMut(web_sys::TouchEvent)>);
+        canvas.set_ontouchend(Some(ontouchend.as_ref().unchecked_ref()));
+        canvas.set_ontouchcancel(Some(ontouchend.as_ref().unchecked_ref()));
+        ontouchend.forget();
+    }
+
+    /// The Gamepad API has no events for buttons and axes, so the gamepads are
+    /// compared against the previous poll instead.
+    fn poll_gamepads(&mut self, events: &mut Vec<KeyEvent>) {
+        let gamepads = match web_sys::window().map(|window| window.navigator().get_gamepads()) {
+            Some(Ok(gamepads)) => gamepads,
+            _ => return,
+        };
+
+        for (index, previous) in self.gamepads.iter_mut().enumerate() {
+            let gamepad = gamepads
+                .get(index as u32)
+                .dyn_into::<web_sys::Gamepad>()
+                .ok()
+                .filter(|gamepad| gamepad.connected());
+            let gamepad = match gamepad {
+                Some(gamepad) => gamepad,
+                None => {
+                    if previous.connected {
+                        events.push(KeyEvent::GamepadDisconnected { gamepad: index });
+                        *previous = PolledGamepad::new();
+                    }
+                    continue;
+                }
+            };
+            if !previous.connected {
+                events.push(KeyEvent::GamepadConnected { gamepad: index });
+                previous.connected = true;
+            }
+
+            let buttons = gamepad.buttons();
+            for button in 0..usize::min(BUTTON_MAX, buttons.length() as usize) {
+                let pressed = buttons
+                    .get(button as u32)
+                    .dyn_into::<web_sys::GamepadButton>()
+                    .map(|button| button.pressed())
+                    .unwrap_or(false);
+                if pressed != previous.buttons[button] {
+                    previous.buttons[button] = pressed;
+                    events.push(if pressed {
+                        KeyEvent::GamepadButtonDown {
+                            gamepad: index,
+                            button,
+                        }
+                    } else {
+                        KeyEvent::GamepadButtonUp {
+                            gamepad: index,
+                            button,
+                        }
+                    });
+                }
+            }
+
+            let axes = gamepad.axes();
+            for axis in 0..usize::min(AXIS_MAX, axes.length() as usize) {
+                let value = axes.get(axis as u32).as_f64().unwrap_or(0.0) as f32;
+                if value != previous.axes[axis] {
+                    previous.axes[axis] = value;
+                    events.push(KeyEvent::GamepadAxis {
+                        gamepad: index,
+                        axis,
+                        value,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Keeps the browser from scrolling or opening menus on key presses, except for
+/// the function keys and keys going to an input method editor.
+fn should_prevent_default(e: &web_sys::KeyboardEvent) -> bool {
+    let is_function_key =
+        (e.key_code() as usize) >= key_codes::F1 && e.key_code() as usize <= key_codes::F12;
+    !is_function_key
+        && !e.is_composing()
+        && Key::from_dom(&e.key()) != Key::Named(NamedKey::Process)
+}
+
+/// Converts a position relative to the browser viewport to canvas pixels, taking
+/// the difference between the canvas' CSS size and its framebuffer into account.
+fn canvas_pos(canvas: &web_sys::HtmlCanvasElement, client_x: i32, client_y: i32) -> (f32, f32) {
+    let rect = canvas.get_bounding_client_rect();
+    let mut x = client_x as f64 - rect.left();
+    let mut y = client_y as f64 - rect.top();
+    if rect.width() > 0.0 && rect.height() > 0.0 {
+        x *= canvas.width() as f64 / rect.width();
+        y *= canvas.height() as f64 / rect.height();
+    }
+    (x as f32, y as f32)
+}
+
+fn changed_touches(e: &web_sys::TouchEvent) -> Vec<web_sys::Touch> {
+    let touches = e.changed_touches();
+    (0..touches.length())
+        .filter_map(|index| touches.get(index))
+        .collect()
+}
+
+impl Default for DomInput {
+    fn default() -> Self {
+        DomInput::new()
+    }
+}
+
+impl InputSource for DomInput {
+    fn poll(&mut self, events: &mut Vec<KeyEvent>) {
+        events.append(&mut self.events.borrow_mut());
+        self.poll_gamepads(events);
+    }
+}
+
+/// Input driven from code, for tests and simulations.
+///
+/// Clones share the same queue, so keep a clone around to press keys after
+/// handing one to a `KeyManager`.
+#[derive(Clone, Default)]
+pub struct ProgrammaticInput {
+    events: Rc<RefCell<Vec<KeyEvent>>>,
+}
+
+impl ProgrammaticInput {
+    pub fn new() -> ProgrammaticInput {
+        ProgrammaticInput::default()
+    }
+
+    pub fn press(&self, key_code: usize) {
+        self.events.borrow_mut().push(KeyEvent::Down(key_code));
+    }
+
+    pub fn release(&self, key_code: usize) {
+        self.events.borrow_mut().push(KeyEvent::Up(key_code));
+    }
+
+    /// Presses a physical key, with the logical key it has on a US keyboard.
+    pub fn press_code(&self, code: Code) {
+        self.press_code_as(code, code.us_key());
+    }
+
+    /// Presses a physical key that means `key` under the simulated layout.
+    pub fn press_code_as(&self, code: Code, key: Key) {
+        self.events
+            .borrow_mut()
+            .push(KeyEvent::CodeDown { code, key });
+    }
+
+    pub fn release_code(&self, code: Code) {
+        self.release_code_as(code, code.us_key());
+    }
+
+    pub fn release_code_as(&self, code: Code, key: Key) {
+        self.events
+            .borrow_mut()
+            .push(KeyEvent::CodeUp { code, key });
+    }
+
+    /// Types text without pressing any keys, like an input method editor does.
+    pub fn type_text(&self, text: &str) {
+        self.events
+            .borrow_mut()
+            .extend(text.chars().map(KeyEvent::Text));
+    }
+
+    pub fn blur(&self) {
+        self.events.borrow_mut().push(KeyEvent::Blur);
+    }
+
+    pub fn connect_gamepad(&self, gamepad: usize) {
+        self.events
+            .borrow_mut()
+            .push(KeyEvent::GamepadConnected { gamepad });
+    }
+
+    pub fn disconnect_gamepad(&self, gamepad: usize) {
+        self.events
+            .borrow_mut()
+            .push(KeyEvent::GamepadDisconnected { gamepad });
+    }
+
+    pub fn press_button(&self, gamepad: usize, button: usize) {
+        self.events
+            .borrow_mut()
+            .push(KeyEvent::GamepadButtonDown { gamepad, button });
+    }
+
+    pub fn release_button(&self, gamepad: usize, button: usize) {
+        self.events
+            .borrow_mut()
+            .push(KeyEvent::GamepadButtonUp { gamepad, button });
+    }
+
+    /// Moves a single axis, `value` is from -1 to 1 in Gamepad API orientation.
+    pub fn move_axis(&self, gamepad: usize, axis: usize, value: f32) {
+        self.events.borrow_mut().push(KeyEvent::GamepadAxis {
+            gamepad,
+            axis,
+            value,
+        });
+    }
+
+    /// Tilts a stick, with y pointing up like `KeyManager::stick` reports it.
+    pub fn move_stick(&self, gamepad: usize, stick: Stick, tilt: na::Vector2<f32>) {
+        let (x_axis, y_axis) = stick.axes();
+        self.move_axis(gamepad, x_axis, tilt.x);
+        self.move_axis(gamepad, y_axis, -tilt.y);
+    }
+
+    /// Moves the mouse to a position in canvas pixels.
+    pub fn move_pointer(&self, pos: na::Point2<f32>) {
+        self.events
+            .borrow_mut()
+            .push(KeyEvent::PointerMove { x: pos.x, y: pos.y });
+    }
+
+    pub fn leave_pointer(&self) {
+        self.events.borrow_mut().push(KeyEvent::PointerLeave);
+    }
+
+    pub fn press_mouse(&self, button: usize) {
+        self.events.borrow_mut().push(KeyEvent::MouseDown(button));
+    }
+
+    pub fn release_mouse(&self, button: usize) {
+        self.events.borrow_mut().push(KeyEvent::MouseUp(button));
+    }
+
+    /// Scrolls the wheel by a number of pixels, positive y scrolls down.
+    pub fn scroll(&self, delta: na::Vector2<f32>) {
+        self.events.borrow_mut().push(KeyEvent::Wheel {
+            x: delta.x,
+            y: delta.y,
+        });
+    }
+
+    pub fn touch_start(&self, id: i32, pos: na::Point2<f32>) {
+        self.events.borrow_mut().push(KeyEvent::TouchStart {
+            id,
+            x: pos.x,
+            y: pos.y,
+        });
+    }
+
+    pub fn touch_move(&self, id: i32, pos: na::Point2<f32>) {
+        self.events.borrow_mut().push(KeyEvent::TouchMove {
+            id,
+            x: pos.x,
+            y: pos.y,
+        });
+    }
+
+    pub fn touch_end(&self, id: i32) {
+        self.events.borrow_mut().push(KeyEvent::TouchEnd { id });
+    }
+}
+
+impl InputSource for ProgrammaticInput {
+    fn poll(&mut self, events: &mut Vec<KeyEvent>) {
+        events.append(&mut self.events.borrow_mut());
+    }
+}
@@ -5,45 +5,75 @@ extern crate nalgebra as na;
 extern crate num_derive;
 extern crate wee_alloc;
 
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
-
 use downcast_rs::Downcast;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::WebGlRenderingContext;
 
+pub use collision::{Collider, CollisionEvent, Contact, Phase};
 use renderer::Renderer;
 
+pub mod animation;
+pub mod assets;
+pub mod collision;
+pub mod driver;
+pub mod entity;
 pub mod key;
+pub mod particles;
+pub mod physics;
 pub mod renderer;
+pub mod spatial;
+pub mod transform;
+pub mod tween;
 
 pub trait Renderable {
     fn render(&self, renderer: &mut Renderer);
 }
 
 pub trait World {
-    fn tick(&mut self, key_manager: &key::KeyManager, timestamp: f64);
+    /// Advances the simulation by `dt` milliseconds. The engine always passes the
+    /// same fixed `dt`, calling this as many times per frame as needed.
+    fn update(&mut self, key_manager: &key::KeyManager, dt: f64);
     fn get_game_objects(&self) -> Vec<&GameObject>;
+    fn get_game_objects_mut(&mut self) -> Vec<&mut GameObject>;
+
+    /// Worlds keeping their objects in `Entities` return them here, so the
+    /// engine can move their bodies, animate them and report collisions between
+    /// them after every update. They are then also rendered from the `Entities`, looking
+    /// up what's in view in its spatial index.
+    fn get_entities_mut(&mut self) -> Option<&mut entity::Entities> {
+        None
+    }
+
+    /// Starts loading the images the world's textures are made of, called once
+    /// when the engine is created.
+    fn load_assets(&mut self, _assets: &mut assets::Assets) {}
 }
 
 pub struct GameObject {
     pub pos: na::Point2<f32>,
+    prev_pos: na::Point2<f32>,
+    /// Counterclockwise, in radians.
+    pub rotation: f32,
+    pub scale: na::Vector2<f32>,
+    /// Moved by the engine if the object has a `physics::Body`.
     pub speed: na::Vector2<f32>,
     collider: Option<Collider>,
     pub rend: Vec<Box<dyn Rend>>,
-    pub props: HashMap<String, f32>,
+    components: entity::Components,
 }
 
 impl GameObject {
     pub fn new(pos: na::Point2<f32>) -> GameObject {
         GameObject {
             pos,
+            prev_pos: pos,
+            rotation: 0.0,
+            scale: na::Vector2::new(1.0, 1.0),
             speed: na::Vector2::zeros(),
             collider: None,
             rend: vec![],
-            props: HashMap::new(),
+            components: entity::Components::new(),
         }
     }
 
@@ -51,6 +81,21 @@ impl GameObject {
         self.collider = Some(collider);
     }
 
+    /// The position, rotation and scale in the world.
+    pub fn get_transform(&self) -> transform::Transform {
+        transform::Transform {
+            translation: self.pos.coords,
+            rotation: self.rotation,
+            scale: self.scale,
+        }
+    }
+
+    pub fn set_transform(&mut self, transform: transform::Transform) {
+        self.pos = na::Point2::from(transform.translation);
+        self.rotation = transform.rotation;
+        self.scale = transform.scale;
+    }
+
     pub fn add_rend(&mut self, rend: Box<dyn Rend>) {
         self.rend.push(rend);
     }
@@ -58,126 +103,482 @@ impl GameObject {
     pub fn get_collider(&self) -> &Option<Collider> {
         &self.collider
     }
+
+    /// Adds a component, replacing any previous component of the same type.
+    pub fn add_component<C: 'static>(&mut self, component: C) {
+        self.components.insert(component);
+    }
+
+    pub fn remove_component<C: 'static>(&mut self) -> Option<C> {
+        self.components.remove()
+    }
+
+    pub fn get_component<C: 'static>(&self) -> Option<&C> {
+        self.components.get()
+    }
+
+    pub fn get_component_mut<C: 'static>(&mut self) -> Option<&mut C> {
+        self.components.get_mut()
+    }
+
+    pub fn has_component<C: 'static>(&self) -> bool {
+        self.components.contains::<C>()
+    }
+
+    /// How far from `pos` the object's rends draw, counting how far it moved
+    /// in the last update. `None` if any of them could draw anywhere.
+    pub fn get_render_range(&self) -> Option<f32> {
+        let mut range: f32 = 0.0;
+        for rend in self.rend.iter() {
+            range = range.max(rend.get_range(self)?);
+        }
+        Some(range + na::distance(&self.prev_pos, &self.pos))
+    }
+
+    /// Whether anything the object draws could be inside the rectangle from
+    /// `min` to `max`.
+    pub fn is_visible_within(&self, min: na::Point2<f32>, max: na::Point2<f32>) -> bool {
+        match self.get_render_range() {
+            Some(range) => {
+                let closest = na::Point2::new(
+                    self.pos.x.clamp(min.x, max.x),
+                    self.pos.y.clamp(min.y, max.y),
+                );
+                na::distance_squared(&self.pos, &closest) <= range.powi(2)
+            }
+            None => true,
+        }
+    }
+
+    /// Position to render at, blended between the position before and after the
+    /// last update by `alpha` (see `Renderer::get_alpha`).
+    pub fn get_interpolated_pos(&self, alpha: f32) -> na::Point2<f32> {
+        self.prev_pos + (self.pos - self.prev_pos) * alpha
+    }
 }
 
 pub trait Rend: Downcast {
     fn render(&self, renderer: &mut Renderer, game_object: &GameObject);
+
+    /// How far from the object's position this draws, so the engine can skip
+    /// it when that's out of view. `None`, the default, always renders it,
+    /// which rends drawing in screen space or moving the camera need.
+    fn get_range(&self, _game_object: &GameObject) -> Option<f32> {
+        None
+    }
 }
 impl_downcast!(Rend);
 
-pub struct Collider {
-    range: f32,
+impl Renderable for GameObject {
+    fn render(&self, renderer: &mut Renderer) {
+        for r in self.rend.iter() {
+            r.render(renderer, &self);
+        }
+    }
+}
+
+/// Default length of a simulation step, in milliseconds.
+pub const TIMESTEP: f64 = 1000.0 / 60.0;
+
+/// Longest frame the engine will try to catch up with. Anything longer (a
+/// backgrounded tab, a breakpoint) is treated as this long.
+const MAX_FRAME_TIME: f64 = 250.0;
+
+/// Owns a `World` together with everything it needs to run: its input and its
+/// renderer. Drivers from the `driver` module call `frame` to move it forward.
+pub struct Engine {
+    world: Box<dyn World>,
+    key_manager: key::KeyManager,
+    renderer: Renderer,
+
+    timestep: f64,
+    accumulator: f64,
+    last_timestamp: Option<f64>,
+    update_count: usize,
+
+    recorder: Option<key::replay::Recorder>,
 }
 
-impl Collider {
-    pub fn new(range: f32) -> Collider {
-        Collider { range }
+impl Engine {
+    pub fn new(
+        mut world: Box<dyn World>,
+        key_manager: key::KeyManager,
+        mut renderer: Renderer,
+    ) -> Engine {
+        world.load_assets(renderer.get_assets_mut());
+        Engine {
+            world,
+            key_manager,
+            renderer,
+
+            timestep: TIMESTEP,
+            accumulator: 0.0,
+            last_timestamp: None,
+            update_count: 0,
+
+            recorder: None,
+        }
+    }
+
+    /// Sets the length of a simulation step in milliseconds.
+    pub fn set_timestep(&mut self, timestep: f64) {
+        self.timestep = timestep;
     }
 
-    fn get_range(&self) -> f32 {
-        return self.range;
+    pub fn get_timestep(&self) -> f64 {
+        self.timestep
     }
 
-    pub fn collide(
-        &self,
-        game_object: &GameObject,
-        pos: &na::Point2<f32>,
-        speed: &mut na::Vector2<f32>,
-    ) -> bool {
-        if f32::abs(game_object.pos.x - pos.x) > (self.get_range() + 16.0)
-            || f32::abs(game_object.pos.y - pos.y) > (self.get_range() + 16.0)
-        {
-            return false;
+    /// Number of fixed updates run since the engine was created.
+    pub fn get_update_count(&self) -> usize {
+        self.update_count
+    }
+
+    /// Runs as many fixed updates as fit in the time since the previous frame,
+    /// then renders the world into a viewport of the given size.
+    ///
+    /// Time that doesn't add up to a whole step is carried over to the next frame
+    /// and rendering interpolates between the last two updates by that fraction,
+    /// so the simulation is the same at any frame rate.
+    pub fn frame(&mut self, timestamp: f64, viewport: na::Vector2<f32>) {
+        let frame_time = match self.last_timestamp {
+            Some(last_timestamp) => f64::min(timestamp - last_timestamp, MAX_FRAME_TIME),
+            None => 0.0,
+        };
+        self.last_timestamp = Some(timestamp);
+        self.accumulator += frame_time;
+
+        self.renderer.set_viewport(viewport);
+        self.key_manager.poll_input();
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record_frame(&self.key_manager, timestamp);
+        }
+
+        while self.accumulator >= self.timestep {
+            match self.world.get_entities_mut() {
+                Some(entities) => entities.save_previous_positions(),
+                None => {
+                    for game_object in self.world.get_game_objects_mut() {
+                        game_object.prev_pos = game_object.pos;
+                    }
+                }
+            }
+            self.world.update(&self.key_manager, self.timestep);
+            if let Some(entities) = self.world.get_entities_mut() {
+                entities.apply_commands();
+                physics::step(entities, self.timestep);
+                animation::step(entities, self.timestep);
+                tween::step(entities, self.timestep);
+                particles::step(entities, self.timestep);
+                entities.update_transforms();
+                entities.update_contacts();
+            }
+            self.key_manager.post_tick_update_key_states();
+            self.accumulator -= self.timestep;
+            self.update_count += 1;
         }
-        if na::distance_squared(&game_object.pos, pos) < (self.get_range() + 16.0).powi(2) {
-            if pos.x > game_object.pos.x {
-                speed.x = f32::max(0.0, speed.x);
-            } else {
-                speed.x = f32::min(0.0, speed.x);
+
+        self.renderer
+            .set_alpha((self.accumulator / self.timestep) as f32);
+        self.render();
+        self.renderer.flush();
+        // Pointer positions in the next frame map to the world as shown in this
+        // one, a replay restores the transform it recorded in its place
+        self.key_manager
+            .set_screen_to_world(self.renderer.get_screen_to_world());
+    }
+
+    /// Renders everything in view. Objects that could draw anywhere go first,
+    /// so the camera they set is the one the rest are culled with.
+    fn render(&mut self) {
+        let renderer = &mut self.renderer;
+        let (drawn, culled) = match self.world.get_entities_mut() {
+            Some(entities) => {
+                let unbounded = entities.get_unbounded();
+                for entity in unbounded.iter() {
+                    entities.get(*entity).unwrap().render(renderer);
+                }
+                let (min, max) = renderer.get_visible_rect();
+                let visible = entities.visible_within(min, max);
+                for entity in visible.iter() {
+                    entities.get(*entity).unwrap().render(renderer);
+                }
+                let drawn = unbounded.len() + visible.len();
+                (drawn, entities.len() - drawn)
             }
-            if pos.y > game_object.pos.y {
-                speed.y = f32::max(0.0, speed.y);
-            } else {
-                speed.y = f32::min(0.0, speed.y);
+            None => {
+                let game_objects = self.world.get_game_objects();
+                let (unbounded, bounded): (Vec<&GameObject>, Vec<&GameObject>) = game_objects
+                    .iter()
+                    .partition(|game_object| game_object.get_render_range().is_none());
+                for game_object in unbounded.iter() {
+                    game_object.render(renderer);
+                }
+                let (min, max) = renderer.get_visible_rect();
+                let mut drawn = unbounded.len();
+                for game_object in bounded.iter() {
+                    if game_object.is_visible_within(min, max) {
+                        game_object.render(renderer);
+                        drawn += 1;
+                    }
+                }
+                (drawn, game_objects.len() - drawn)
             }
-            return true;
-        } else {
-            return false;
         };
+        renderer.record_culling(drawn, culled);
     }
-}
 
-impl Renderable for GameObject {
-    fn render(&self, renderer: &mut Renderer) {
-        for r in self.rend.iter() {
-            r.render(renderer, &self);
-        }
+    pub fn get_world(&self) -> &dyn World {
+        self.world.as_ref()
     }
-}
 
-pub fn start(mut world: Box<dyn World>) -> Result<(), JsValue> {
-    let mut key_manager = key::KeyManager::new();
+    pub fn get_world_mut(&mut self) -> &mut dyn World {
+        self.world.as_mut()
+    }
+
+    pub fn get_key_manager(&self) -> &key::KeyManager {
+        &self.key_manager
+    }
+
+    pub fn get_key_manager_mut(&mut self) -> &mut key::KeyManager {
+        &mut self.key_manager
+    }
+
+    /// Starts recording key states and timestamps of every following frame,
+    /// replacing any recording in progress.
+    pub fn start_recording(&mut self) {
+        self.recorder = Some(key::replay::Recorder::new());
+    }
 
+    /// Stops recording and returns what was recorded so far, if anything.
+    pub fn stop_recording(&mut self) -> Option<key::replay::Recording> {
+        self.recorder.take().map(|recorder| recorder.finish())
+    }
+
+    pub fn get_renderer(&self) -> &Renderer {
+        &self.renderer
+    }
+
+    pub fn get_renderer_mut(&mut self) -> &mut Renderer {
+        &mut self.renderer
+    }
+}
+
+/// Runs the world in the browser, rendering to the `#canvas` element.
+pub fn start(world: Box<dyn World>) -> Result<(), JsValue> {
     let document = web_sys::window().unwrap().document().unwrap();
     let canvas = document.get_element_by_id("canvas").unwrap();
     let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into::<web_sys::HtmlCanvasElement>()?;
 
+    let input = key::DomInput::new();
+    input.listen_to_pointer(&canvas);
+    let key_manager = key::KeyManager::new(Box::new(input));
+
     let gl = canvas
         .get_context("webgl")?
         .unwrap()
         .dyn_into::<WebGlRenderingContext>()?;
 
-    let mut renderer = Renderer::new(gl);
+    let mut renderer = Renderer::new(Box::new(renderer::WebGlBackend::new(gl)));
     renderer.load_shader(
         include_str!("shaders/vertex.glsl"),
         include_str!("shaders/fragment.glsl"),
     );
     log::info! {"Engine initialised"};
 
-    let f = Rc::new(RefCell::new(None));
-    let g = f.clone();
+    driver::BrowserDriver::new(canvas).run(Engine::new(world, key_manager, renderer));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use driver::NativeDriver;
+    use renderer::SoftwareBackend;
+
+    #[test]
+    fn it_works() {
+        assert_eq!(2 + 2, 4);
+    }
+
+    /// Falls with drag, which only comes out the same at any frame rate when
+    /// stepped with a fixed dt.
+    struct FallingWorld {
+        game_objects: Vec<GameObject>,
+    }
 
-    *g.borrow_mut() = Some(Closure::wrap(Box::new(move |timestamp: f64| {
-        let viewport = na::Vector2::new(canvas.width() as f32, canvas.height() as f32);
-        // log::debug!("{:?}: {}", viewport, timestamp);
-        renderer
-            .gl
-            .viewport(0, 0, viewport.x as i32, viewport.y as i32);
+    impl World for FallingWorld {
+        fn update(&mut self, _key_manager: &key::KeyManager, dt: f64) {
+            let ball = &mut self.game_objects[0];
+            ball.speed.y -= 0.01 * dt as f32;
+            ball.speed *= 0.9f32.powf(dt as f32 / 100.0);
+            ball.pos += ball.speed * dt as f32;
+        }
 
-        renderer.set_viewport(viewport);
+        fn get_game_objects(&self) -> Vec<&GameObject> {
+            self.game_objects.iter().collect()
+        }
 
-        world.tick(&key_manager, timestamp);
-        let gameobjects = world.get_game_objects();
-        key_manager.post_tick_update_key_states();
-        for gameobject in gameobjects.iter() {
-            gameobject.render(&mut renderer);
+        fn get_game_objects_mut(&mut self) -> Vec<&mut GameObject> {
+            self.game_objects.iter_mut().collect()
         }
+    }
 
-        renderer.flush();
+    /// Draws a quad around its object, or anywhere if it has no range.
+    struct Square(Option<f32>);
 
-        request_animation_frame(f.borrow().as_ref().unwrap());
-        // let _ = f.borrow_mut().take();
-        // return;
-    }) as Box<dyn FnMut(f64)>));
+    impl Rend for Square {
+        fn render(&self, renderer: &mut Renderer, game_object: &GameObject) {
+            let texture = renderer::TextureMap::new(1, 1, "white".to_string()).get_texture(0, 0);
+            renderer.draw_quad(game_object.pos, na::Vector2::new(2.0, 2.0), &texture);
+        }
 
-    request_animation_frame(g.borrow().as_ref().unwrap());
-    Ok(())
-}
+        fn get_range(&self, _game_object: &GameObject) -> Option<f32> {
+            self.0
+        }
+    }
 
-fn window() -> web_sys::Window {
-    web_sys::window().expect("no global `window` exists")
-}
+    struct EntityWorld {
+        entities: entity::Entities,
+    }
 
-fn request_animation_frame(f: &Closure<dyn FnMut(f64)>) {
-    window()
-        .request_animation_frame(f.as_ref().unchecked_ref())
-        .expect("should register `requestAnimationFrame` OK");
-}
+    impl World for EntityWorld {
+        fn update(&mut self, _key_manager: &key::KeyManager, _dt: f64) {}
+
+        fn get_game_objects(&self) -> Vec<&GameObject> {
+            self.entities.get_game_objects()
+        }
+
+        fn get_game_objects_mut(&mut self) -> Vec<&mut GameObject> {
+            self.entities.get_game_objects_mut()
+        }
+
+        fn get_entities_mut(&mut self) -> Option<&mut entity::Entities> {
+            Some(&mut self.entities)
+        }
+
+        fn load_assets(&mut self, assets: &mut assets::Assets) {
+            assets.load_url("white", "images/white.png");
+        }
+    }
 
-#[cfg(test)]
-mod tests {
     #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
+    fn culls_objects_out_of_view() {
+        let mut entities = entity::Entities::new();
+        // The view is 1600 by 1200 around the origin
+        for (x, range) in [
+            (0.0, Some(1.0)),
+            (801.0, Some(2.0)),
+            (900.0, Some(2.0)),
+            (5000.0, None),
+        ] {
+            let mut game_object = GameObject::new(na::Point2::new(x, 0.0));
+            game_object.add_rend(Box::new(Square(range)));
+            entities.spawn(game_object);
+        }
+        let mut engine = Engine::new(
+            Box::new(EntityWorld { entities }),
+            key::KeyManager::headless(),
+            Renderer::new(Box::new(SoftwareBackend::new())),
+        );
+        engine.frame(0.0, na::Vector2::new(4.0, 3.0));
+
+        let stats = engine.get_renderer().get_stats();
+        assert_eq!((stats.drawn_objects, stats.culled_objects), (3, 1));
+        assert_eq!(stats.quads, 3);
+        // Fetching only works in the browser
+        assert!(matches!(
+            engine.get_renderer().get_assets().get_state("white"),
+            Some(assets::LoadState::Failed(_))
+        ));
+    }
+
+    fn simulate(frame_rate: f64) -> (usize, na::Point2<f32>) {
+        let world = FallingWorld {
+            game_objects: vec![GameObject::new(na::Point2::new(0.0, 0.0))],
+        };
+        let mut engine = Engine::new(
+            Box::new(world),
+            key::KeyManager::headless(),
+            Renderer::new(Box::new(SoftwareBackend::new())),
+        );
+        engine.set_timestep(10.0);
+        let mut driver = NativeDriver::new(1000.0 / frame_rate, na::Vector2::new(4.0, 3.0));
+        for frame in 0..=(frame_rate as usize) {
+            driver.step_to(&mut engine, frame as f64 * 1000.0 / frame_rate);
+        }
+        driver.step_to(&mut engine, 1005.0);
+
+        let world = engine
+            .get_world()
+            .get_game_objects()
+            .iter()
+            .map(|game_object| game_object.pos)
+            .collect::<Vec<_>>();
+        let alpha = engine.get_renderer().get_alpha();
+        assert!((0.0..1.0).contains(&alpha));
+        (engine.get_update_count(), world[0])
+    }
+
+    /// Counts the presses of space in x and its releases in y.
+    struct TappingWorld {
+        game_objects: Vec<GameObject>,
+    }
+
+    impl World for TappingWorld {
+        fn update(&mut self, key_manager: &key::KeyManager, _dt: f64) {
+            let counter = &mut self.game_objects[0];
+            if key_manager.key_down(key::key_codes::SPACE) {
+                counter.pos.x += 1.0;
+            }
+            if key_manager.key_up(key::key_codes::SPACE) {
+                counter.pos.y += 1.0;
+            }
+        }
+
+        fn get_game_objects(&self) -> Vec<&GameObject> {
+            self.game_objects.iter().collect()
+        }
+
+        fn get_game_objects_mut(&mut self) -> Vec<&mut GameObject> {
+            self.game_objects.iter_mut().collect()
+        }
+    }
+
+    #[test]
+    fn sees_taps_between_updates() {
+        let input = key::ProgrammaticInput::new();
+        let world = TappingWorld {
+            game_objects: vec![GameObject::new(na::Point2::new(0.0, 0.0))],
+        };
+        let mut engine = Engine::new(
+            Box::new(world),
+            key::KeyManager::new(Box::new(input.clone())),
+            Renderer::new(Box::new(SoftwareBackend::new())),
+        );
+        let mut driver = NativeDriver::new(1000.0 / 144.0, na::Vector2::new(4.0, 3.0));
+        driver.run(&mut engine, 4);
+        let updates = engine.get_update_count();
+
+        // Released in the next frame, before any update ran
+        input.press(key::key_codes::SPACE);
+        driver.step(&mut engine);
+        assert_eq!(engine.get_update_count(), updates);
+        input.release(key::key_codes::SPACE);
+        driver.step(&mut engine);
+        assert_eq!(engine.get_update_count(), updates + 1);
+        let counter = |engine: &Engine| engine.get_world().get_game_objects()[0].pos;
+        assert_eq!(counter(&engine), na::Point2::new(1.0, 0.0));
+
+        driver.run(&mut engine, 6);
+        assert_eq!(counter(&engine), na::Point2::new(1.0, 1.0));
+    }
+
+    #[test]
+    fn simulation_does_not_depend_on_frame_rate() {
+        let at_60 = simulate(60.0);
+        assert_eq!(at_60.0, 100);
+        assert_eq!(simulate(30.0), at_60);
+        assert_eq!(simulate(144.0), at_60);
     }
 }
@@ -0,0 +1,352 @@
+//! Emits and moves lots of short lived sprites, for fire, sparks and the like.
+//!
+//! An `Emitter` component spawns particles at its `GameObject` at a steady rate
+//! or in bursts, each with a random lifetime, velocity and acceleration picked
+//! from ranges. Their size and colour go from a start to an end value over
+//! their life. Like `physics::step`, the engine calls `step` after every update
+//! of a `World` that exposes its `Entities`, rends draw the particles with
+//! `Emitter::render`.
+//!
+//! The randomness comes from a seed, so the same emitter stepped the same way
+//! always gives the same particles.
+//!
+//! Times are in milliseconds, speeds in world units per second.
+extern crate nalgebra as na;
+
+use crate::entity::Entities;
+use crate::renderer::{BlendMode, QuadTransform, Renderer, Texture};
+use crate::transform::Transform;
+use crate::GameObject;
+
+/// A small, fast pseudorandom number generator (xorshift64*), good enough for
+/// effects and the same everywhere.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // Zero would stay zero forever
+        Rng {
+            state: seed ^ 0x9e37_79b9_7f4a_7c15,
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as u32
+    }
+
+    /// Between 0 inclusive and 1 exclusive.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Between `min` and `max`.
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// Between `min` and `max` in each dimension.
+    pub fn range2(&mut self, min: na::Vector2<f32>, max: na::Vector2<f32>) -> na::Vector2<f32> {
+        na::Vector2::new(self.range(min.x, max.x), self.range(min.y, max.y))
+    }
+}
+
+/// What particle positions are relative to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Space {
+    /// Particles stay where they were emitted, leaving a trail behind an
+    /// emitter that moves.
+    World,
+    /// Particles move, turn and scale along with the emitter.
+    Local,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Particle {
+    /// In the world or relative to the emitter, depending on its `Space`.
+    pub pos: na::Point2<f32>,
+    pub velocity: na::Vector2<f32>,
+    pub acceleration: na::Vector2<f32>,
+    pub age: f64,
+    pub lifetime: f64,
+}
+
+impl Particle {
+    /// How far along its life the particle is, from 0 to 1.
+    pub fn get_progress(&self) -> f32 {
+        (self.age / self.lifetime).min(1.0) as f32
+    }
+}
+
+/// Emits particles at its `GameObject`.
+#[derive(Clone, Debug)]
+pub struct Emitter {
+    texture: Texture,
+    rate: f64,
+    lifetime: (f64, f64),
+    offset: (na::Vector2<f32>, na::Vector2<f32>),
+    velocity: (na::Vector2<f32>, na::Vector2<f32>),
+    acceleration: (na::Vector2<f32>, na::Vector2<f32>),
+    size: (f32, f32),
+    color: (na::Vector4<f32>, na::Vector4<f32>),
+    blend: BlendMode,
+    space: Space,
+    max_particles: usize,
+
+    emitting: bool,
+    rng: Rng,
+    /// Particles owed by the rate but not emitted yet, below 1.
+    pending: f64,
+    particles: Vec<Particle>,
+}
+
+impl Emitter {
+    /// Emits nothing until given a rate or a burst. Particles live for a
+    /// second, stand still, are 16 units wide, white and blended additively.
+    pub fn new(texture: Texture, seed: u64) -> Emitter {
+        Emitter {
+            texture,
+            rate: 0.0,
+            lifetime: (1000.0, 1000.0),
+            offset: (na::Vector2::zeros(), na::Vector2::zeros()),
+            velocity: (na::Vector2::zeros(), na::Vector2::zeros()),
+            acceleration: (na::Vector2::zeros(), na::Vector2::zeros()),
+            size: (16.0, 16.0),
+            color: (na::Vector4::repeat(1.0), na::Vector4::repeat(1.0)),
+            blend: BlendMode::Additive,
+            space: Space::World,
+            max_particles: 1000,
+
+            emitting: true,
+            rng: Rng::new(seed),
+            pending: 0.0,
+            particles: Vec::new(),
+        }
+    }
+
+    /// Emits `rate` particles per second.
+    pub fn with_rate(mut self, rate: f64) -> Emitter {
+        self.set_rate(rate);
+        self
+    }
+
+    /// How long particles live, in milliseconds.
+    pub fn with_lifetime(mut self, min: f64, max: f64) -> Emitter {
+        assert!(min > 0.0 && max >= min, "particles must live a while");
+        self.lifetime = (min, max);
+        self
+    }
+
+    /// Where particles appear relative to the emitter, anywhere in the box
+    /// from `min` to `max`.
+    pub fn with_offset(mut self, min: na::Vector2<f32>, max: na::Vector2<f32>) -> Emitter {
+        self.offset = (min, max);
+        self
+    }
+
+    pub fn with_velocity(mut self, min: na::Vector2<f32>, max: na::Vector2<f32>) -> Emitter {
+        self.velocity = (min, max);
+        self
+    }
+
+    pub fn with_acceleration(mut self, min: na::Vector2<f32>, max: na::Vector2<f32>) -> Emitter {
+        self.acceleration = (min, max);
+        self
+    }
+
+    /// Particles grow or shrink from `start` to `end` over their life.
+    pub fn with_size(mut self, start: f32, end: f32) -> Emitter {
+        self.size = (start, end);
+        self
+    }
+
+    /// Particles are tinted from `start` to `end` over their life.
+    pub fn with_color(mut self, start: na::Vector4<f32>, end: na::Vector4<f32>) -> Emitter {
+        self.color = (start, end);
+        self
+    }
+
+    pub fn with_blend(mut self, blend: BlendMode) -> Emitter {
+        self.blend = blend;
+        self
+    }
+
+    pub fn with_space(mut self, space: Space) -> Emitter {
+        self.space = space;
+        self
+    }
+
+    /// Stops emitting while this many particles are alive.
+    pub fn with_max_particles(mut self, max_particles: usize) -> Emitter {
+        self.max_particles = max_particles;
+        self
+    }
+
+    pub fn set_rate(&mut self, rate: f64) {
+        assert!(rate >= 0.0, "can't emit a negative number of particles");
+        self.rate = rate;
+    }
+
+    pub fn get_rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Pauses or resumes emitting at the rate, particles alive keep going.
+    pub fn set_emitting(&mut self, emitting: bool) {
+        self.emitting = emitting;
+        self.pending = 0.0;
+    }
+
+    pub fn is_emitting(&self) -> bool {
+        self.emitting
+    }
+
+    pub fn get_particles(&self) -> &Vec<Particle> {
+        &self.particles
+    }
+
+    /// Emits `count` particles at once, at the emitter's `transform`.
+    pub fn burst(&mut self, transform: &Transform, count: usize) {
+        for _ in 0..count {
+            self.emit(transform);
+        }
+    }
+
+    fn emit(&mut self, transform: &Transform) {
+        if self.particles.len() >= self.max_particles {
+            return;
+        }
+        let offset = self.rng.range2(self.offset.0, self.offset.1);
+        let pos = match self.space {
+            Space::World => transform.apply(na::Point2::from(offset)),
+            Space::Local => na::Point2::from(offset),
+        };
+        let velocity = self.rng.range2(self.velocity.0, self.velocity.1);
+        let acceleration = self.rng.range2(self.acceleration.0, self.acceleration.1);
+        let lifetime =
+            self.lifetime.0 + (self.lifetime.1 - self.lifetime.0) * self.rng.next_f32() as f64;
+        self.particles.push(Particle {
+            pos,
+            velocity,
+            acceleration,
+            age: 0.0,
+            lifetime,
+        });
+    }
+
+    /// Moves particles on by `dt` milliseconds, dropping the ones that died,
+    /// and emits new ones at the emitter's `transform`.
+    fn advance(&mut self, transform: &Transform, dt: f64) {
+        let seconds = (dt / 1000.0) as f32;
+        for particle in self.particles.iter_mut() {
+            particle.age += dt;
+            particle.velocity += particle.acceleration * seconds;
+            particle.pos += particle.velocity * seconds;
+        }
+        self.particles
+            .retain(|particle| particle.age < particle.lifetime);
+
+        if self.emitting {
+            self.pending += self.rate * dt / 1000.0;
+            while self.pending >= 1.0 {
+                self.pending -= 1.0;
+                self.emit(transform);
+            }
+        }
+    }
+
+    /// Draws every particle centred on its position, `depth` is the depth of
+    /// them all.
+    pub fn render(&self, renderer: &mut Renderer, game_object: &GameObject, depth: f32) {
+        let transform = game_object.get_transform();
+        let quad_transform = QuadTransform::new().with_pivot(na::Vector2::new(0.5, 0.5));
+        for particle in self.particles.iter() {
+            let progress = particle.get_progress();
+            let size = self.size.0 + (self.size.1 - self.size.0) * progress;
+            let (pos, size) = match self.space {
+                Space::World => (particle.pos, na::Vector2::repeat(size)),
+                Space::Local => (
+                    transform.apply(particle.pos),
+                    na::Vector2::repeat(size).component_mul(&transform.scale.abs()),
+                ),
+            };
+            renderer.draw_quad_blended(
+                pos,
+                size,
+                &self.texture,
+                depth,
+                self.color.0.lerp(&self.color.1, progress),
+                &quad_transform,
+                self.blend,
+            );
+        }
+    }
+}
+
+/// Moves the particles of every `Emitter` forward by `dt` milliseconds.
+pub fn step(entities: &mut Entities, dt: f64) {
+    // Emitting doesn't move anything, so this skips marking entities as moved
+    for (_, game_object) in entities.query_in_place_mut::<Emitter>() {
+        let transform = game_object.get_transform();
+        if let Some(emitter) = game_object.get_component_mut::<Emitter>() {
+            emitter.advance(&transform, dt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::{SoftwareBackend, TextureMap};
+
+    fn sparks(seed: u64) -> Emitter {
+        let texture = TextureMap::new(1, 1, "spark".to_string()).get_texture(0, 0);
+        Emitter::new(texture, seed)
+            .with_rate(100.0)
+            .with_lifetime(200.0, 400.0)
+            .with_velocity(na::Vector2::new(-10.0, 20.0), na::Vector2::new(10.0, 40.0))
+            .with_acceleration(na::Vector2::new(0.0, -10.0), na::Vector2::new(0.0, -10.0))
+            .with_size(8.0, 0.0)
+    }
+
+    #[test]
+    fn emits_the_same_particles_for_the_same_seed() {
+        let mut entities = Entities::new();
+        let mut game_object = GameObject::new(na::Point2::new(100.0, 0.0));
+        game_object.add_component(sparks(7));
+        let entity = entities.spawn(game_object);
+
+        let mut local = sparks(7).with_space(Space::Local);
+        let transform = Transform::default();
+        for _ in 0..10 {
+            step(&mut entities, 10.0);
+            local.advance(&transform, 10.0);
+        }
+        let emitter = entities.get_component::<Emitter>(entity).unwrap();
+        assert_eq!(emitter.get_particles().len(), 10);
+        for (world, local) in emitter.get_particles().iter().zip(local.get_particles()) {
+            let offset = world.pos - local.pos - na::Vector2::new(100.0, 0.0);
+            assert!(offset.norm() < 1e-3);
+            assert_eq!(world.velocity, local.velocity);
+            assert!(world.velocity.y >= 20.0 && world.velocity.y < 40.0);
+            assert!(world.lifetime >= 200.0 && world.lifetime < 400.0);
+        }
+        assert_ne!(sparks(8).rng.next_u32(), sparks(7).rng.next_u32());
+
+        local.set_emitting(false);
+        local.advance(&transform, 400.0);
+        assert!(local.get_particles().is_empty());
+
+        let mut renderer = Renderer::new(Box::new(SoftwareBackend::new()));
+        let game_object = entities.get(entity).unwrap();
+        emitter.render(&mut renderer, game_object, 0.0);
+        renderer.flush();
+        assert_eq!(renderer.get_stats().translucent_quads, 10);
+        assert_eq!(renderer.get_stats().draw_calls, 1);
+    }
+}
@@ -0,0 +1,183 @@
+//! Moves `GameObject`s that have a `Body`.
+//!
+//! After every update of a `World` that exposes its `Entities`, the engine
+//! calls `step`: forces change each body's `speed`, drag slows it down, the
+//! body moves and then gets pushed out of whatever its collider is blocked by.
+//! Speeds are in world units per second.
+use crate::entity::{Entities, Entity};
+
+/// Makes the engine move a `GameObject` by its `speed`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Body {
+    mass: f32,
+    drag: f32,
+    max_speed: f32,
+    rest_speed: f32,
+    force: na::Vector2<f32>,
+    impulse: na::Vector2<f32>,
+}
+
+impl Body {
+    /// A body without drag or a speed limit.
+    pub fn new(mass: f32) -> Body {
+        assert!(mass > 0.0, "mass must be positive");
+        Body {
+            mass,
+            drag: 0.0,
+            max_speed: f32::INFINITY,
+            rest_speed: 0.0,
+            force: na::Vector2::zeros(),
+            impulse: na::Vector2::zeros(),
+        }
+    }
+
+    /// Sets how fast the body slows down by itself, the speed is multiplied by
+    /// `e^(-drag)` every second.
+    pub fn with_drag(mut self, drag: f32) -> Body {
+        self.drag = drag;
+        self
+    }
+
+    pub fn with_max_speed(mut self, max_speed: f32) -> Body {
+        self.max_speed = max_speed;
+        self
+    }
+
+    /// Sets the speed under which the body stops.
+    pub fn with_rest_speed(mut self, rest_speed: f32) -> Body {
+        self.rest_speed = rest_speed;
+        self
+    }
+
+    pub fn get_mass(&self) -> f32 {
+        self.mass
+    }
+
+    pub fn get_drag(&self) -> f32 {
+        self.drag
+    }
+
+    pub fn get_max_speed(&self) -> f32 {
+        self.max_speed
+    }
+
+    pub fn set_max_speed(&mut self, max_speed: f32) {
+        self.max_speed = max_speed;
+    }
+
+    pub fn get_rest_speed(&self) -> f32 {
+        self.rest_speed
+    }
+
+    /// Pushes the body during the next step, forces add up until then.
+    pub fn apply_force(&mut self, force: na::Vector2<f32>) {
+        self.force += force;
+    }
+
+    /// Changes the speed by `impulse / mass` at the next step, for throwing
+    /// things.
+    pub fn apply_impulse(&mut self, impulse: na::Vector2<f32>) {
+        self.impulse += impulse;
+    }
+
+    /// Returns the speed after `dt` milliseconds, using up the forces.
+    fn integrate(&mut self, speed: na::Vector2<f32>, dt: f64) -> na::Vector2<f32> {
+        let seconds = dt as f32 / 1000.0;
+        let mut speed = speed + (self.force * seconds + self.impulse) / self.mass;
+        self.force = na::Vector2::zeros();
+        self.impulse = na::Vector2::zeros();
+
+        speed *= (-self.drag * seconds).exp();
+        let norm = speed.norm();
+        if norm > self.max_speed {
+            speed *= self.max_speed / norm;
+        }
+        if norm < self.rest_speed {
+            speed = na::Vector2::zeros();
+        }
+        speed
+    }
+}
+
+/// Moves every entity with a `Body` forward by `dt` milliseconds.
+pub fn step(entities: &mut Entities, dt: f64) {
+    let bodies: Vec<Entity> = entities
+        .query::<Body>()
+        .map(|(entity, _, _)| entity)
+        .collect();
+    for entity in bodies {
+        let game_object = entities.get_mut(entity).unwrap();
+        let speed = game_object.speed;
+        let mut speed = game_object
+            .get_component_mut::<Body>()
+            .unwrap()
+            .integrate(speed, dt);
+        let mut pos = game_object.pos + speed * (dt as f32 / 1000.0);
+
+        if let Some(collider) = *game_object.get_collider() {
+            for other in entities.colliders_within(pos, collider.get_range()) {
+                let other_object = entities.get(other).unwrap();
+                let other_collider = other_object.get_collider().as_ref().unwrap();
+                if other == entity || !collider.blocked_by(other_collider) {
+                    continue;
+                }
+                if let Some(contact) = collider.contact(pos, other_collider, other_object.pos) {
+                    contact.resolve(&mut pos, &mut speed);
+                }
+            }
+        }
+
+        let game_object = entities.get_mut(entity).unwrap();
+        game_object.pos = pos;
+        game_object.speed = speed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Collider, GameObject};
+
+    #[test]
+    fn integrates_forces_and_drag() {
+        let mut body = Body::new(2.0)
+            .with_drag(1.0)
+            .with_max_speed(10.0)
+            .with_rest_speed(0.5);
+        body.apply_force(na::Vector2::new(4.0, 0.0));
+        let speed = body.integrate(na::Vector2::zeros(), 1000.0);
+        assert!((speed.x - 2.0 * (-1.0f32).exp()).abs() < 1e-5);
+        // The force was used up and drag slows the body under its rest speed
+        let speed = body.integrate(speed, 1000.0);
+        assert_eq!(speed, na::Vector2::zeros());
+
+        body.apply_impulse(na::Vector2::new(0.0, 100.0));
+        let speed = body.integrate(speed, 0.0);
+        assert_eq!(speed.norm(), 10.0);
+        assert_eq!(
+            body.integrate(na::Vector2::new(0.4, 0.0), 0.0),
+            na::Vector2::zeros()
+        );
+    }
+
+    #[test]
+    fn steps_bodies_and_slides_them_along_walls() {
+        let mut entities = Entities::new();
+        let mut ball = GameObject::new(na::Point2::new(0.0, 0.0));
+        ball.speed = na::Vector2::new(100.0, 100.0);
+        ball.add_collider(Collider::circle(5.0));
+        ball.add_component(Body::new(1.0));
+        let ball = entities.spawn(ball);
+        let mut wall = GameObject::new(na::Point2::new(18.0, 0.0));
+        wall.add_collider(Collider::aabb(na::Vector2::new(5.0, 100.0)));
+        entities.spawn(wall);
+        let mut ghost = GameObject::new(na::Point2::new(0.0, 20.0));
+        ghost.add_collider(Collider::circle(10.0).as_trigger());
+        entities.spawn(ghost);
+
+        step(&mut entities, 100.0);
+        let ball = entities.get(ball).unwrap();
+        assert_eq!(ball.pos, na::Point2::new(8.0, 10.0));
+        assert_eq!(ball.speed, na::Vector2::new(0.0, 100.0));
+    }
+}
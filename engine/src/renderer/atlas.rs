@@ -0,0 +1,243 @@
+extern crate nalgebra as na;
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Image, Texture};
+
+/// A named sprite in an `Atlas`, in pixels from the top left of the image.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+    /// The point placed at a sprite's position, as a fraction of its size from
+    /// the bottom left corner, see `QuadTransform::with_pivot`.
+    #[serde(default = "default_pivot")]
+    pub pivot: [f32; 2],
+}
+
+fn default_pivot() -> [f32; 2] {
+    [0.5, 0.0]
+}
+
+impl Frame {
+    /// A frame with its pivot at the bottom centre.
+    pub fn new(x: u32, y: u32, w: u32, h: u32) -> Frame {
+        Frame {
+            x,
+            y,
+            w,
+            h,
+            pivot: default_pivot(),
+        }
+    }
+
+    pub fn with_pivot(mut self, pivot: na::Vector2<f32>) -> Frame {
+        self.pivot = [pivot.x, pivot.y];
+        self
+    }
+
+    pub fn get_pivot(&self) -> na::Vector2<f32> {
+        na::Vector2::new(self.pivot[0], self.pivot[1])
+    }
+
+    /// Size in pixels.
+    pub fn get_size(&self) -> na::Vector2<f32> {
+        na::Vector2::new(self.w as f32, self.h as f32)
+    }
+}
+
+/// Sprites packed into one texture, looked up by name instead of by their
+/// place in a grid like with a `TextureMap`.
+///
+/// Atlases are described in JSON:
+///
+/// ```json
+/// {
+///   "texture": "spritesheet",
+///   "width": 256,
+///   "height": 256,
+///   "frames": {
+///     "player": { "x": 192, "y": 128, "w": 64, "h": 128, "pivot": [0.5, 0.0] }
+///   }
+/// }
+/// ```
+///
+/// The pivot can be left out, it's at the bottom centre by default. `pack`
+/// builds an atlas and its image out of separate images.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Atlas {
+    #[serde(rename = "texture")]
+    texture_name: String,
+    width: u32,
+    height: u32,
+    frames: BTreeMap<String, Frame>,
+}
+
+impl Atlas {
+    /// An atlas without frames for a texture of `width` by `height` pixels.
+    pub fn new(texture_name: &str, width: u32, height: u32) -> Atlas {
+        Atlas {
+            texture_name: texture_name.to_string(),
+            width,
+            height,
+            frames: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Atlas, String> {
+        let atlas: Atlas = serde_json::from_str(json).map_err(|error| error.to_string())?;
+        for (name, frame) in atlas.frames.iter() {
+            if frame.x + frame.w > atlas.width || frame.y + frame.h > atlas.height {
+                return Err(format!("frame {} doesn't fit in the texture", name));
+            }
+        }
+        Ok(atlas)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn with_frame(mut self, name: &str, frame: Frame) -> Atlas {
+        self.insert(name, frame);
+        self
+    }
+
+    /// Adds a frame, replacing any frame with the same name.
+    pub fn insert(&mut self, name: &str, frame: Frame) {
+        self.frames.insert(name.to_string(), frame);
+    }
+
+    pub fn get_texture_name(&self) -> &str {
+        &self.texture_name
+    }
+
+    pub fn get_frame(&self, name: &str) -> Option<&Frame> {
+        self.frames.get(name)
+    }
+
+    /// Names of all frames, in alphabetical order.
+    pub fn get_frame_names(&self) -> impl Iterator<Item = &str> {
+        self.frames.keys().map(|name| name.as_str())
+    }
+
+    /// The part of the texture showing the frame called `name`, with the
+    /// frame's pivot.
+    pub fn get(&self, name: &str) -> Option<Texture> {
+        let frame = self.frames.get(name)?;
+        let (width, height) = (self.width as f32, self.height as f32);
+        Some(Texture {
+            start: na::Vector2::new(frame.x as f32 / width, frame.y as f32 / height),
+            size: na::Vector2::new(frame.w as f32 / width, frame.h as f32 / height),
+            texture_name: self.texture_name.to_owned(),
+            pivot: frame.get_pivot(),
+        })
+    }
+}
+
+/// Packs `sprites` into rows of an image `width` pixels wide, tallest first, and
+/// describes where each one ended up. The height is rounded up to a power of
+/// two, so the image works with WebGL if `width` is one too.
+pub fn pack(
+    texture_name: &str,
+    sprites: &[(String, Image)],
+    width: u32,
+) -> Result<(Image, Atlas), String> {
+    let mut order: Vec<&(String, Image)> = sprites.iter().collect();
+    order.sort_by(|(a_name, a), (b_name, b)| b.height.cmp(&a.height).then(a_name.cmp(b_name)));
+
+    let mut frames = Vec::with_capacity(order.len());
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for (name, sprite) in order {
+        let (w, h) = (sprite.width as u32, sprite.height as u32);
+        if w > width {
+            return Err(format!("{} is wider than the atlas", name));
+        }
+        if x + w > width {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+        frames.push((name, sprite, Frame::new(x, y, w, h)));
+        x += w;
+        row_height = row_height.max(h);
+    }
+
+    let height = (y + row_height).max(1).next_power_of_two();
+    let mut image = Image::filled(width as usize, height as usize, [0, 0, 0, 0]);
+    let mut atlas = Atlas::new(texture_name, width, height);
+    for (name, sprite, frame) in frames {
+        for row in 0..sprite.height {
+            let from = row * sprite.width * 4;
+            let to = ((frame.y as usize + row) * image.width + frame.x as usize) * 4;
+            image.pixels[to..to + sprite.width * 4]
+                .copy_from_slice(&sprite.pixels[from..from + sprite.width * 4]);
+        }
+        atlas.insert(name, frame);
+    }
+    Ok((image, atlas))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_sprites_and_reads_them_back_by_name() {
+        let sprites = vec![
+            ("log".to_string(), Image::filled(2, 1, [1, 1, 1, 255])),
+            ("player".to_string(), Image::filled(1, 2, [2, 2, 2, 255])),
+            ("tree".to_string(), Image::filled(2, 2, [3, 3, 3, 255])),
+        ];
+        let (image, atlas) = pack("sprites", &sprites, 4).unwrap();
+        assert_eq!((image.width, image.height), (4, 4));
+        assert_eq!(atlas.get_frame("player"), Some(&Frame::new(0, 0, 1, 2)));
+        assert_eq!(atlas.get_frame("tree"), Some(&Frame::new(1, 0, 2, 2)));
+        // Doesn't fit next to the others anymore
+        assert_eq!(atlas.get_frame("log"), Some(&Frame::new(0, 2, 2, 1)));
+        assert_eq!(image.pixel(2, 1), [3, 3, 3, 255]);
+        assert_eq!(image.pixel(1, 2), [1, 1, 1, 255]);
+        assert_eq!(image.pixel(3, 3), [0, 0, 0, 0]);
+
+        let atlas = Atlas::from_json(&atlas.to_json()).unwrap();
+        let texture = atlas.get("tree").unwrap();
+        assert_eq!(texture.start, na::Vector2::new(0.25, 0.0));
+        assert_eq!(texture.size, na::Vector2::new(0.5, 0.5));
+        assert!(atlas.get("stump").is_none());
+        assert!(pack("sprites", &sprites, 1).is_err());
+    }
+
+    #[test]
+    fn reads_frames_with_default_pivots() {
+        let atlas = Atlas::from_json(
+            r#"{
+                "texture": "ui",
+                "width": 256,
+                "height": 64,
+                "frames": {
+                    "thermometer": { "x": 128, "y": 0, "w": 64, "h": 32, "pivot": [0.5, 0.5] },
+                    "logs": { "x": 0, "y": 0, "w": 64, "h": 64 }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(atlas.get_texture_name(), "ui");
+        assert_eq!(
+            atlas.get_frame("thermometer").unwrap().get_pivot(),
+            na::Vector2::new(0.5, 0.5)
+        );
+        assert_eq!(
+            atlas.get_frame("logs").unwrap().get_pivot(),
+            na::Vector2::new(0.5, 0.0)
+        );
+        assert_eq!(
+            atlas.get("thermometer").unwrap().get_pivot(),
+            na::Vector2::new(0.5, 0.5)
+        );
+        assert!(Atlas::from_json(r#"{ "texture": "ui", "width": 1, "height": 1, "frames": { "big": { "x": 0, "y": 0, "w": 2, "h": 1 } } }"#).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+extern crate nalgebra as na;
+
+use downcast_rs::Downcast;
+
+use super::Image;
+
+/// Alpha below which texels of opaque batches are discarded, so sprites can have
+/// transparent surroundings and still write depth.
+pub const ALPHA_CUTOFF: f32 = 0.5;
+
+/// How a batch is combined with what has already been drawn. Colours are
+/// premultiplied by alpha.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Covers what's behind by the alpha of the quad.
+    #[default]
+    Alpha,
+    /// Adds to what's behind, for glowing things like embers.
+    Additive,
+    /// Darkens what's behind by the colour of the quad, for shadows.
+    Multiply,
+}
+
+/// A single batch of quads sharing one texture and blend mode, ready to be
+/// drawn by a `Backend`.
+///
+/// `vertices` is laid out as `VERTEX_SIZE` floats per vertex and four vertices
+/// per quad, in the order the `Renderer` pushed them.
+pub struct DrawCall<'a> {
+    pub texture_name: &'a str,
+    pub vertices: &'a [f32],
+    pub blend: BlendMode,
+    /// Translucent batches come after all opaque ones, sorted back to front.
+    /// They are depth tested but don't write depth.
+    pub translucent: bool,
+    pub projection: na::Matrix4<f32>,
+    pub transform: na::Matrix4<f32>,
+    pub fire_pos: na::Point2<f32>,
+    pub fire_heat: f32,
+}
+
+impl<'a> DrawCall<'a> {
+    /// Alpha below which texels are discarded, translucent batches only skip
+    /// fully transparent ones.
+    pub fn get_alpha_cutoff(&self) -> f32 {
+        if self.translucent {
+            1.0 / 512.0
+        } else {
+            ALPHA_CUTOFF
+        }
+    }
+}
+
+/// The part of the renderer that talks to the actual graphics API.
+///
+/// The `Renderer` does all the batching and camera maths, a `Backend` only has to
+/// put the resulting `DrawCall`s on screen (or somewhere else).
+pub trait Backend: Downcast {
+    fn load_shader(&mut self, vertex: &str, fragment: &str) -> i32;
+    fn set_viewport(&mut self, viewport: na::Vector2<f32>);
+    /// Makes `image` the texture drawn for `texture_name`, replacing any
+    /// texture it had.
+    fn load_texture(&mut self, texture_name: &str, image: &Image);
+    fn begin_frame(&mut self);
+    fn draw(&mut self, draw_call: &DrawCall);
+    fn end_frame(&mut self);
+}
+impl_downcast!(Backend);
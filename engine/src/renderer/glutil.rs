@@ -1,6 +1,7 @@
-use wasm_bindgen::JsCast;
 use web_sys::{WebGlProgram, WebGlRenderingContext, WebGlShader, WebGlTexture};
 
+use super::Image;
+
 pub fn compile_shader(
     gl: &WebGlRenderingContext,
     shader_type: u32,
@@ -62,30 +63,37 @@ pub fn link_program(
     }
 }
 
-pub fn load_texture(gl: &WebGlRenderingContext, img_id: &str) -> WebGlTexture {
-    let document = web_sys::window().unwrap().document().unwrap();
-    log::debug!("{}", img_id);
-    let texture_image = document
-        .get_element_by_id(img_id)
-        .unwrap()
-        .dyn_into::<web_sys::HtmlImageElement>()
-        .unwrap();
-
-    let texture = gl.create_texture().unwrap();
-
-    gl.active_texture(WebGlRenderingContext::TEXTURE0);
-    gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&texture));
-
-    gl.tex_image_2d_with_u32_and_u32_and_image(
+/// Creates a texture from decoded pixels.
+pub fn upload_texture(gl: &WebGlRenderingContext, image: &Image) -> Result<WebGlTexture, String> {
+    let texture = create_texture(gl)?;
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
         WebGlRenderingContext::TEXTURE_2D,
         0,
         WebGlRenderingContext::RGBA as i32,
+        image.width as i32,
+        image.height as i32,
+        0,
         WebGlRenderingContext::RGBA,
         WebGlRenderingContext::UNSIGNED_BYTE,
-        &texture_image,
+        Some(&image.pixels),
     )
-    .unwrap();
+    .map_err(|error| format!("Unable to upload image: {:?}", error))?;
+    set_texture_parameters(gl);
+    Ok(texture)
+}
+
+/// Creates a texture and binds it to `TEXTURE_2D` of the first texture unit.
+fn create_texture(gl: &WebGlRenderingContext) -> Result<WebGlTexture, String> {
+    let texture = gl
+        .create_texture()
+        .ok_or_else(|| String::from("Unable to create texture object"))?;
+
+    gl.active_texture(WebGlRenderingContext::TEXTURE0);
+    gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&texture));
+    Ok(texture)
+}
 
+fn set_texture_parameters(gl: &WebGlRenderingContext) {
     gl.tex_parameteri(
         WebGlRenderingContext::TEXTURE_2D,
         WebGlRenderingContext::TEXTURE_MIN_FILTER,
@@ -98,5 +106,4 @@ pub fn load_texture(gl: &WebGlRenderingContext, img_id: &str) -> WebGlTexture {
     );
 
     gl.generate_mipmap(WebGlRenderingContext::TEXTURE_2D);
-    return texture;
 }
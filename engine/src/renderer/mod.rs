@@ -1,16 +1,31 @@
 extern crate nalgebra as na;
 
 use halfbrown::HashMap;
-use web_sys::{WebGlBuffer, WebGlProgram, WebGlRenderingContext, WebGlTexture};
+
+use crate::assets::Assets;
+
+pub use atlas::{pack, Atlas, Frame};
+pub use backend::{Backend, BlendMode, DrawCall};
+pub use software::{Image, RecordedDrawCall, SoftwareBackend};
+pub use webgl::WebGlBackend;
 
 const FLOAT32_BYTES: i32 = 4;
 
-const MAX_QUADS: usize = 11000;
+/// Most quads a backend is given in one draw call, the `Renderer` splits
+/// bigger batches.
+pub const MAX_QUADS: usize = 11000;
 const MAX_VERTICES: usize = MAX_QUADS * 4;
 const MAX_INDICES: usize = MAX_QUADS * 6;
-const VERTEX_SIZE: usize = 8;
+const VERTEX_SIZE: usize = 9;
+
+// Vertices are indexed with u16
+const _: () = assert!(MAX_VERTICES <= u16::MAX as usize + 1);
 
+mod atlas;
+mod backend;
 mod glutil;
+mod software;
+mod webgl;
 
 #[derive(Clone, Hash)]
 pub struct TextureMap {
@@ -36,6 +51,7 @@ impl TextureMap {
             start: na::Vector2::new(width * column as f32, height * row as f32),
             size: na::Vector2::new(width, height),
             texture_name: self.texture_name.to_owned(),
+            pivot: na::Vector2::new(0.5, 0.0),
         }
     }
 
@@ -47,6 +63,7 @@ impl TextureMap {
             start: na::Vector2::new(width * column as f32, height * row as f32),
             size: na::Vector2::new(width * w, height * h),
             texture_name: self.texture_name.to_owned(),
+            pivot: na::Vector2::new(0.5, 0.0),
         }
     }
     pub fn get_very_custom(&self, start: na::Vector2<f32>, size: na::Vector2<f32>) -> Texture {
@@ -54,6 +71,7 @@ impl TextureMap {
             start,
             size,
             texture_name: self.texture_name.to_owned(),
+            pivot: na::Vector2::new(0.5, 0.0),
         }
     }
 }
@@ -63,76 +81,130 @@ pub struct Texture {
     start: na::Vector2<f32>,
     size: na::Vector2<f32>,
     texture_name: String,
+    /// Where the sprite should be anchored, bottom centre unless an `Atlas`
+    /// frame says otherwise.
+    pivot: na::Vector2<f32>,
 }
 
-pub struct Renderer {
-    vertices: HashMap<String, Vec<f32>>,
-    textures: HashMap<String, WebGlTexture>,
-    indices: Vec<u16>,
+impl Texture {
+    /// The point to place at a sprite's position, as a fraction of its size
+    /// from the bottom left corner, for `QuadTransform::with_pivot`.
+    pub fn get_pivot(&self) -> na::Vector2<f32> {
+        self.pivot
+    }
+}
 
-    pub gl: WebGlRenderingContext,
-    vertex_buffer: WebGlBuffer,
-    index_buffer: WebGlBuffer,
+/// Where a quad is anchored and how it's turned, for
+/// `Renderer::draw_quad_transformed`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuadTransform {
+    /// Counterclockwise around the pivot, in radians.
+    pub rotation: f32,
+    /// Multiplies the size, a negative scale flips the quad along that axis.
+    pub scale: na::Vector2<f32>,
+    /// The point of the quad placed at its position, as a fraction of its size
+    /// from the bottom left corner.
+    pub pivot: na::Vector2<f32>,
+}
 
-    programs: HashMap<i32, WebGlProgram>,
-    selected_program: i32,
-    viewport: na::Vector2<f32>,
+impl QuadTransform {
+    /// No rotation or scaling, with the pivot at the bottom centre like quads
+    /// drawn without a transform.
+    pub fn new() -> QuadTransform {
+        QuadTransform {
+            rotation: 0.0,
+            scale: na::Vector2::new(1.0, 1.0),
+            pivot: na::Vector2::new(0.5, 0.0),
+        }
+    }
 
-    fire_pos: na::Point2<f32>,
-    fire_heat: f32,
-    camera: na::Point2<f32>,
+    pub fn with_rotation(mut self, rotation: f32) -> QuadTransform {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: na::Vector2<f32>) -> QuadTransform {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_pivot(mut self, pivot: na::Vector2<f32>) -> QuadTransform {
+        self.pivot = pivot;
+        self
+    }
+
+    /// Mirrors the quad left to right.
+    pub fn flip_x(mut self) -> QuadTransform {
+        self.scale.x = -self.scale.x;
+        self
+    }
+
+    /// Mirrors the quad upside down.
+    pub fn flip_y(mut self) -> QuadTransform {
+        self.scale.y = -self.scale.y;
+        self
+    }
 }
 
-impl Renderer {
-    pub fn new(gl: WebGlRenderingContext) -> Renderer {
-        // Configure GL
-        gl.enable(WebGlRenderingContext::DEPTH_TEST);
-        gl.depth_func(WebGlRenderingContext::LESS);
-
-        gl.enable(WebGlRenderingContext::BLEND);
-        gl.blend_func(
-            WebGlRenderingContext::ONE,
-            WebGlRenderingContext::ONE_MINUS_SRC_ALPHA,
-        );
+impl Default for QuadTransform {
+    fn default() -> QuadTransform {
+        QuadTransform::new()
+    }
+}
 
-        // Initialise indices
-        let mut indices = Vec::with_capacity(MAX_INDICES);
-        let mut offset = 0;
-        for _ in 0..MAX_QUADS {
-            indices.push(offset + 0);
-            indices.push(offset + 1);
-            indices.push(offset + 2);
+/// What the `Renderer` drew in the last frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    /// Draw calls handed to the backend, batches over `MAX_QUADS` count once
+    /// per piece.
+    pub draw_calls: usize,
+    pub quads: usize,
+    pub translucent_quads: usize,
+    /// Objects the engine rendered and skipped for being out of view.
+    pub drawn_objects: usize,
+    pub culled_objects: usize,
+}
 
-            indices.push(offset + 2);
-            indices.push(offset + 3);
-            indices.push(offset + 1);
+/// A quad that can't be batched by texture alone, as it has to be drawn after
+/// everything behind it.
+struct TranslucentQuad {
+    texture_name: String,
+    blend: BlendMode,
+    depth: f32,
+    /// Where its vertices start in `Renderer::translucent_vertices`.
+    start: usize,
+}
 
-            offset += 4;
-        }
+pub struct Renderer {
+    vertices: HashMap<String, Vec<f32>>,
+    translucent: Vec<TranslucentQuad>,
+    translucent_vertices: Vec<f32>,
+    backend: Box<dyn Backend>,
+    assets: Assets,
+    stats: RenderStats,
+    culling: (usize, usize),
 
-        // Initialise buffers
-        let vertex_buffer = gl
-            .create_buffer()
-            .ok_or("failed to create vertex buffer")
-            .unwrap();
+    viewport: na::Vector2<f32>,
+    alpha: f32,
 
-        let index_buffer = gl
-            .create_buffer()
-            .ok_or("failed to create index buffer")
-            .unwrap();
+    fire_pos: na::Point2<f32>,
+    fire_heat: f32,
+    camera: na::Point2<f32>,
+}
 
+impl Renderer {
+    pub fn new(backend: Box<dyn Backend>) -> Renderer {
         Renderer {
-            indices,
             vertices: HashMap::new(),
-            textures: HashMap::new(),
-
-            gl,
-            vertex_buffer,
-            index_buffer,
+            translucent: Vec::new(),
+            translucent_vertices: Vec::new(),
+            backend,
+            assets: Assets::new(),
+            stats: RenderStats::default(),
+            culling: (0, 0),
 
-            selected_program: 0,
-            programs: HashMap::new(),
             viewport: na::Vector2::zeros(),
+            alpha: 1.0,
 
             camera: na::Point2::new(0.0, 0.0),
             fire_heat: 0.0,
@@ -140,22 +212,41 @@ impl Renderer {
         }
     }
 
-    pub fn load_shader(&mut self, vertex: &str, fragment: &str) -> i32 {
-        let vert_shader =
-            glutil::compile_shader(&self.gl, WebGlRenderingContext::VERTEX_SHADER, vertex).unwrap();
-        let frag_shader =
-            glutil::compile_shader(&self.gl, WebGlRenderingContext::FRAGMENT_SHADER, fragment)
-                .unwrap();
+    pub fn get_backend(&self) -> &dyn Backend {
+        self.backend.as_ref()
+    }
+
+    pub fn get_backend_mut(&mut self) -> &mut dyn Backend {
+        self.backend.as_mut()
+    }
 
-        let program = glutil::link_program(&self.gl, &vert_shader, &frag_shader).unwrap();
-        let key = self.programs.len() as i32;
-        self.programs.insert(key, program);
+    /// Images for textures, uploaded to the backend as they finish loading.
+    pub fn get_assets(&self) -> &Assets {
+        &self.assets
+    }
 
-        return key;
+    pub fn get_assets_mut(&mut self) -> &mut Assets {
+        &mut self.assets
+    }
+
+    /// Returns the statistics of the last `flush`.
+    pub fn get_stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    /// Counts the objects drawn and culled this frame, reported in the stats of
+    /// the next `flush`.
+    pub fn record_culling(&mut self, drawn_objects: usize, culled_objects: usize) {
+        self.culling = (drawn_objects, culled_objects);
+    }
+
+    pub fn load_shader(&mut self, vertex: &str, fragment: &str) -> i32 {
+        self.backend.load_shader(vertex, fragment)
     }
 
     pub fn set_viewport(&mut self, viewport: na::Vector2<f32>) {
         self.viewport = viewport;
+        self.backend.set_viewport(viewport);
     }
 
     pub fn get_viewport(&self) -> na::Vector2<f32> {
@@ -167,6 +258,16 @@ impl Renderer {
         na::Vector2::new(width, width / aspect_ratio)
     }
 
+    /// Sets how far the current frame is between the previous and the latest
+    /// simulation step, from 0 to 1.
+    pub fn set_alpha(&mut self, alpha: f32) {
+        self.alpha = alpha;
+    }
+
+    pub fn get_alpha(&self) -> f32 {
+        self.alpha
+    }
+
     pub fn set_camera(&mut self, camera: na::Point2<f32>) {
         self.camera = na::Point2::new(camera.x.round(), camera.y.round());
     }
@@ -175,6 +276,40 @@ impl Renderer {
         self.camera
     }
 
+    /// The corners of the world area in view, bottom left and top right.
+    pub fn get_visible_rect(&self) -> (na::Point2<f32>, na::Point2<f32>) {
+        if self.viewport.x <= 0.0 || self.viewport.y <= 0.0 {
+            return (self.camera, self.camera);
+        }
+        let half_viewport = self.get_viewport() / 2.0;
+        (self.camera - half_viewport, self.camera + half_viewport)
+    }
+
+    /// Returns the transform from canvas pixels, with the origin in the top left
+    /// corner and y pointing down, to world coordinates under the current camera.
+    pub fn get_screen_to_world(&self) -> na::Matrix3<f32> {
+        if self.viewport.x <= 0.0 || self.viewport.y <= 0.0 {
+            return na::Matrix3::identity();
+        }
+        let world_viewport = self.get_viewport();
+        na::Matrix3::new(
+            world_viewport.x / self.viewport.x,
+            0.0,
+            self.camera.x - world_viewport.x / 2.0,
+            0.0,
+            -world_viewport.y / self.viewport.y,
+            self.camera.y + world_viewport.y / 2.0,
+            0.0,
+            0.0,
+            1.0,
+        )
+    }
+
+    /// Converts a position in canvas pixels to world coordinates.
+    pub fn screen_to_world(&self, screen: na::Point2<f32>) -> na::Point2<f32> {
+        self.get_screen_to_world().transform_point(&screen)
+    }
+
     pub fn set_fire_pos(&mut self, fire_pos: na::Point2<f32>) {
         self.fire_pos = fire_pos;
     }
@@ -199,7 +334,7 @@ impl Renderer {
             size,
             texture,
             depth,
-            na::Vector3::new(1.0, 1.0, 1.0),
+            na::Vector4::new(1.0, 1.0, 1.0, 1.0),
         );
     }
 
@@ -208,9 +343,9 @@ impl Renderer {
         pos: na::Point2<f32>,
         size: na::Vector2<f32>,
         texture: &Texture,
-        tint: na::Vector3<f32>,
+        tint: na::Vector4<f32>,
     ) {
-        if tint.norm_squared() != 3.0 {
+        if tint.norm_squared() != 4.0 {
             // log::debug!("{:?}", tint);
         }
         self.draw_quad_with_depth_and_tint(pos, size, texture, -pos.y, tint);
@@ -222,213 +357,479 @@ impl Renderer {
         size: na::Vector2<f32>,
         texture: &Texture,
         depth: f32,
-        tint: na::Vector3<f32>,
+        tint: na::Vector4<f32>,
     ) {
-        if !self.vertices.contains_key(&texture.texture_name) {
-            self.vertices.insert(
-                texture.texture_name.to_owned(),
-                Vec::with_capacity(MAX_VERTICES * VERTEX_SIZE),
-            );
-        }
-        let vertices = self.vertices.get_mut(&texture.texture_name).unwrap();
-
-        vertices.push(pos.x - size.x / 2.0);
-        vertices.push(pos.y);
-        vertices.push(depth);
-        vertices.push(tint.x);
-        vertices.push(tint.y);
-        vertices.push(tint.z);
-        vertices.push(texture.start.x);
-        vertices.push(texture.start.y + texture.size.y);
-
-        vertices.push(pos.x + size.x / 2.0);
-        vertices.push(pos.y);
-        vertices.push(depth);
-        vertices.push(tint.x);
-        vertices.push(tint.y);
-        vertices.push(tint.z);
-        vertices.push(texture.start.x + texture.size.x);
-        vertices.push(texture.start.y + texture.size.y);
-
-        vertices.push(pos.x - size.x / 2.0);
-        vertices.push(pos.y + size.y);
-        vertices.push(depth);
-        vertices.push(tint.x);
-        vertices.push(tint.y);
-        vertices.push(tint.z);
-        vertices.push(texture.start.x);
-        vertices.push(texture.start.y);
-
-        vertices.push(pos.x + size.x / 2.0);
-        vertices.push(pos.y + size.y);
-        vertices.push(depth);
-        vertices.push(tint.x);
-        vertices.push(tint.y);
-        vertices.push(tint.z);
-        vertices.push(texture.start.x + texture.size.x);
-        vertices.push(texture.start.y);
+        self.draw_quad_transformed(pos, size, texture, depth, tint, &QuadTransform::new());
     }
 
-    pub fn flush(&mut self) {
-        self.gl.clear_color(0.8, 1.0, 0.8, 1.0);
-        self.gl.clear(
-            WebGlRenderingContext::COLOR_BUFFER_BIT | WebGlRenderingContext::DEPTH_BUFFER_BIT,
-        );
+    /// Draws a quad with its pivot at `pos`, scaled and rotated around it.
+    pub fn draw_quad_transformed(
+        &mut self,
+        pos: na::Point2<f32>,
+        size: na::Vector2<f32>,
+        texture: &Texture,
+        depth: f32,
+        tint: na::Vector4<f32>,
+        transform: &QuadTransform,
+    ) {
+        self.draw_quad_blended(pos, size, texture, depth, tint, transform, BlendMode::Alpha);
+    }
 
-        for (texture_name, vertices) in self.vertices.iter() {
-            if !self.textures.contains_key(texture_name) {
-                self.textures.insert(
-                    texture_name.to_string(),
-                    glutil::load_texture(&self.gl, texture_name),
+    /// Draws a quad like `draw_quad_transformed`, combined with what's behind
+    /// it by `blend`.
+    ///
+    /// Quads with a tint alpha below 1 or a blend mode other than
+    /// `BlendMode::Alpha` are translucent: they are drawn after all opaque
+    /// quads, from back to front.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_quad_blended(
+        &mut self,
+        pos: na::Point2<f32>,
+        size: na::Vector2<f32>,
+        texture: &Texture,
+        depth: f32,
+        tint: na::Vector4<f32>,
+        transform: &QuadTransform,
+        blend: BlendMode,
+    ) {
+        let vertices = if tint.w < 1.0 || blend != BlendMode::Alpha {
+            self.translucent.push(TranslucentQuad {
+                texture_name: texture.texture_name.to_owned(),
+                blend,
+                depth,
+                start: self.translucent_vertices.len(),
+            });
+            &mut self.translucent_vertices
+        } else {
+            if !self.vertices.contains_key(&texture.texture_name) {
+                self.vertices.insert(
+                    texture.texture_name.to_owned(),
+                    Vec::with_capacity(MAX_VERTICES * VERTEX_SIZE),
                 );
             }
-            self.draw(
-                vertices,
-                self.textures.get(texture_name).unwrap(),
-                texture_name == "ui",
+            self.vertices.get_mut(&texture.texture_name).unwrap()
+        };
+
+        let size = size.component_mul(&transform.scale);
+        let (sin, cos) = transform.rotation.sin_cos();
+        // Bottom left, bottom right, top left, top right
+        for (corner_x, corner_y) in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].iter() {
+            let x = (corner_x - transform.pivot.x) * size.x;
+            let y = (corner_y - transform.pivot.y) * size.y;
+            vertices.push(pos.x + x * cos - y * sin);
+            vertices.push(pos.y + x * sin + y * cos);
+            vertices.push(depth);
+            vertices.push(tint.x);
+            vertices.push(tint.y);
+            vertices.push(tint.z);
+            vertices.push(tint.w);
+            vertices.push(texture.start.x + texture.size.x * corner_x);
+            vertices.push(texture.start.y + texture.size.y * (1.0 - corner_y));
+        }
+    }
+
+    pub fn flush(&mut self) {
+        for (texture_name, image) in self.assets.take_loaded() {
+            self.backend.load_texture(&texture_name, &image);
+        }
+        self.backend.begin_frame();
+
+        let projection = self.get_projection();
+        let camera_transform = self.get_camera_transform();
+        let transform = |texture_name: &str| {
+            if texture_name == "ui" {
+                na::Matrix4::identity()
+            } else {
+                camera_transform
+            }
+        };
+        let mut stats = RenderStats {
+            drawn_objects: self.culling.0,
+            culled_objects: self.culling.1,
+            ..RenderStats::default()
+        };
+        self.culling = (0, 0);
+        for (texture_name, vertices) in self.vertices.iter() {
+            draw_split(
+                self.backend.as_mut(),
+                &mut stats,
+                &DrawCall {
+                    texture_name: self.assets.resolve(texture_name),
+                    vertices,
+                    blend: BlendMode::Alpha,
+                    translucent: false,
+                    projection,
+                    transform: transform(texture_name),
+                    fire_pos: self.fire_pos,
+                    fire_heat: self.fire_heat,
+                },
             );
         }
         for (_, vertices) in self.vertices.iter_mut() {
             vertices.clear();
         }
 
-        self.gl.clear_color(0.0, 0.0, 0.0, 1.0);
-        self.gl.color_mask(false, false, false, true);
-        self.gl.clear(WebGlRenderingContext::COLOR_BUFFER_BIT);
-        self.gl.color_mask(true, true, true, true);
+        // Back to front by the depth seen by the camera, a stable sort keeps
+        // quads at the same depth in the order they were drawn
+        let camera_depth = camera_transform[(2, 3)];
+        let view_depth = |quad: &TranslucentQuad| {
+            if quad.texture_name == "ui" {
+                quad.depth
+            } else {
+                quad.depth + camera_depth
+            }
+        };
+        self.translucent
+            .sort_by(|a, b| view_depth(a).total_cmp(&view_depth(b)));
+        let mut batch = Vec::new();
+        for (i, quad) in self.translucent.iter().enumerate() {
+            batch.extend_from_slice(
+                &self.translucent_vertices[quad.start..quad.start + 4 * VERTEX_SIZE],
+            );
+            let next = self.translucent.get(i + 1);
+            if next.is_some_and(|next| {
+                next.texture_name == quad.texture_name && next.blend == quad.blend
+            }) {
+                continue;
+            }
+            stats.translucent_quads += batch.len() / (4 * VERTEX_SIZE);
+            draw_split(
+                self.backend.as_mut(),
+                &mut stats,
+                &DrawCall {
+                    texture_name: self.assets.resolve(&quad.texture_name),
+                    vertices: &batch,
+                    blend: quad.blend,
+                    translucent: true,
+                    projection,
+                    transform: transform(&quad.texture_name),
+                    fire_pos: self.fire_pos,
+                    fire_heat: self.fire_heat,
+                },
+            );
+            batch.clear();
+        }
+        self.translucent.clear();
+        self.translucent_vertices.clear();
+
+        self.stats = stats;
+        self.backend.end_frame();
     }
 
-    fn draw(&self, vertices: &Vec<f32>, texture: &WebGlTexture, ui: bool) {
-        let program = self.programs.get(&self.selected_program).unwrap();
-        self.gl.use_program(Some(program));
+    fn get_projection(&self) -> na::Matrix4<f32> {
+        let viewport = self.get_viewport();
+        na::Orthographic3::new(
+            -viewport.x / 2.0,
+            viewport.x / 2.0,
+            -viewport.y / 2.0,
+            viewport.y / 2.0,
+            0.1,
+            viewport.y * 4.0,
+        )
+        .to_homogeneous()
+    }
 
-        self.gl.bind_buffer(
-            WebGlRenderingContext::ARRAY_BUFFER,
-            Some(&self.vertex_buffer),
-        );
+    fn get_camera_transform(&self) -> na::Matrix4<f32> {
+        let viewport = self.get_viewport();
+        na::Translation3::new(
+            -self.camera.x,
+            -self.camera.y,
+            self.camera.y - viewport.y * 2.0,
+        )
+        .to_homogeneous()
+    }
+}
+
+/// Draws `draw_call` in pieces of at most `MAX_QUADS` quads, counting them in
+/// `stats`. Empty batches aren't drawn at all.
+fn draw_split(backend: &mut dyn Backend, stats: &mut RenderStats, draw_call: &DrawCall) {
+    for vertices in draw_call.vertices.chunks(MAX_VERTICES * VERTEX_SIZE) {
+        backend.draw(&DrawCall {
+            vertices,
+            ..*draw_call
+        });
+        stats.draw_calls += 1;
+        stats.quads += vertices.len() / (4 * VERTEX_SIZE);
+    }
+}
 
-        // danger zone: this is a live view to memory. No allocations in this block!
-        unsafe {
-            let vert_array = js_sys::Float32Array::view(&vertices.as_slice());
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            self.gl.buffer_data_with_array_buffer_view(
-                WebGlRenderingContext::ARRAY_BUFFER,
-                &vert_array,
-                WebGlRenderingContext::STATIC_DRAW,
-            );
-        }
+    fn software_renderer(width: f32, height: f32) -> Renderer {
+        let mut renderer = Renderer::new(Box::new(SoftwareBackend::new()));
+        renderer.set_viewport(na::Vector2::new(width, height));
+        renderer
+    }
 
-        self.gl.bind_buffer(
-            WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
-            Some(&self.index_buffer),
+    fn software_backend(renderer: &Renderer) -> &SoftwareBackend {
+        renderer
+            .get_backend()
+            .downcast_ref::<SoftwareBackend>()
+            .unwrap()
+    }
+
+    #[test]
+    fn batches_quads_per_texture() {
+        let mut renderer = software_renderer(16.0, 12.0);
+        let trees = TextureMap::new(4, 4, "spritesheet".to_string());
+        let ui = TextureMap::new(4, 1, "ui".to_string());
+
+        renderer.draw_quad(
+            na::Point2::new(0.0, 0.0),
+            na::Vector2::new(10.0, 10.0),
+            &trees.get_texture(0, 0),
+        );
+        renderer.draw_quad(
+            na::Point2::new(20.0, 0.0),
+            na::Vector2::new(10.0, 10.0),
+            &trees.get_texture(1, 0),
+        );
+        renderer.draw_quad_with_depth_and_tint(
+            na::Point2::new(0.0, 0.0),
+            na::Vector2::new(10.0, 10.0),
+            &ui.get_texture(0, 0),
+            -0.11,
+            na::Vector4::new(0.5, 0.25, 1.0, 1.0),
+        );
+        renderer.flush();
+
+        let backend = software_backend(&renderer);
+        let mut draw_calls = backend.get_draw_calls().clone();
+        draw_calls.sort_by(|a, b| a.texture_name.cmp(&b.texture_name));
+        assert_eq!(draw_calls.len(), 2);
+        assert_eq!(draw_calls[0].texture_name, "spritesheet");
+        assert_eq!(draw_calls[0].quad_count(), 2);
+        assert_eq!(draw_calls[1].texture_name, "ui");
+        assert_eq!(draw_calls[1].quad_count(), 1);
+        // Bottom left vertex: position, depth, tint, uv
+        assert_eq!(
+            draw_calls[1].vertices[..VERTEX_SIZE],
+            [-5.0, 0.0, -0.11, 0.5, 0.25, 1.0, 1.0, 0.0, 1.0]
         );
 
-        // danger zone: this is a live view to memory. No allocations in this block!
-        unsafe {
-            let index_array = js_sys::Uint16Array::view(&self.indices.as_slice());
+        renderer.flush();
+        let backend = software_backend(&renderer);
+        assert_eq!(backend.get_frame_count(), 2);
+        assert!(backend
+            .get_draw_calls()
+            .iter()
+            .all(|draw_call| draw_call.quad_count() == 0));
+    }
 
-            self.gl.buffer_data_with_array_buffer_view(
-                WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
-                &index_array,
-                WebGlRenderingContext::STATIC_DRAW,
+    #[test]
+    fn splits_batches_over_max_quads() {
+        // Nothing to rasterise into, only the draw calls are recorded
+        let mut renderer = software_renderer(0.0, 0.0);
+        let texture = TextureMap::new(1, 1, "grass".to_string()).get_texture(0, 0);
+        for i in 0..MAX_QUADS + 5 {
+            renderer.draw_quad(
+                na::Point2::new(i as f32, 0.0),
+                na::Vector2::new(1.0, 1.0),
+                &texture,
             );
         }
-
-        let position_attrib_location = self.gl.get_attrib_location(&program, "aPosition") as u32;
-        let color_attrib_location = self.gl.get_attrib_location(&program, "aColor") as u32;
-        let texcoord_attrib_location = self.gl.get_attrib_location(&program, "aTexCoord") as u32;
-        let sampler_uniform_location = self.gl.get_uniform_location(&program, "uSampler").unwrap();
-        let viewport_uniform_location =
-            self.gl.get_uniform_location(&program, "uViewport").unwrap();
-        let viewport_transform_location = self
-            .gl
-            .get_uniform_location(&program, "uTransform")
-            .unwrap();
-        let fire_position_uniform_location =
-            self.gl.get_uniform_location(&program, "uFirePos").unwrap();
-        let fire_heat_uniform_location =
-            self.gl.get_uniform_location(&program, "uFireHeat").unwrap();
-
-        self.gl.vertex_attrib_pointer_with_i32(
-            position_attrib_location,
-            3,
-            WebGlRenderingContext::FLOAT,
-            false,
-            (VERTEX_SIZE as i32) * FLOAT32_BYTES,
-            0,
+        renderer.draw_quad_with_tint(
+            na::Point2::new(0.0, 0.0),
+            na::Vector2::new(1.0, 1.0),
+            &texture,
+            na::Vector4::new(1.0, 1.0, 1.0, 0.5),
         );
-        self.gl.vertex_attrib_pointer_with_i32(
-            color_attrib_location,
-            3,
-            WebGlRenderingContext::FLOAT,
-            false,
-            (VERTEX_SIZE as i32) * FLOAT32_BYTES,
-            3 * FLOAT32_BYTES,
+        renderer.flush();
+
+        let quad_counts: Vec<usize> = software_backend(&renderer)
+            .get_draw_calls()
+            .iter()
+            .map(|draw_call| draw_call.quad_count())
+            .collect();
+        assert_eq!(quad_counts, [MAX_QUADS, 5, 1]);
+        assert_eq!(
+            renderer.get_stats(),
+            RenderStats {
+                draw_calls: 3,
+                quads: MAX_QUADS + 6,
+                translucent_quads: 1,
+                ..RenderStats::default()
+            }
         );
-        self.gl.vertex_attrib_pointer_with_i32(
-            texcoord_attrib_location,
-            2,
-            WebGlRenderingContext::FLOAT,
-            false,
-            (VERTEX_SIZE as i32) * FLOAT32_BYTES,
-            6 * FLOAT32_BYTES,
+    }
+
+    #[test]
+    fn rasterises_tinted_quads_in_depth_order() {
+        // get_viewport always scales to the same area, so a 4:3 framebuffer of
+        // 160x120 pixels covers 1600x1200 world units.
+        let mut renderer = software_renderer(160.0, 120.0);
+        let texture = TextureMap::new(1, 1, "white".to_string()).get_texture(0, 0);
+        renderer
+            .get_backend_mut()
+            .downcast_mut::<SoftwareBackend>()
+            .unwrap()
+            .add_texture("white", Image::filled(2, 2, [255, 255, 255, 255]));
+
+        renderer.draw_quad_with_depth_and_tint(
+            na::Point2::new(0.0, -100.0),
+            na::Vector2::new(200.0, 200.0),
+            &texture,
+            -10.0,
+            na::Vector4::new(1.0, 0.0, 0.0, 1.0),
+        );
+        renderer.draw_quad_with_depth_and_tint(
+            na::Point2::new(0.0, -100.0),
+            na::Vector2::new(100.0, 100.0),
+            &texture,
+            -5.0,
+            na::Vector4::new(0.0, 0.0, 1.0, 1.0),
         );
+        renderer.flush();
 
-        let viewport = self.get_viewport();
+        let image = software_backend(&renderer).get_image();
+        assert_eq!((image.width, image.height), (160, 120));
+        assert_eq!(image.pixel(80, 60), [0, 0, 255, 255]);
+        assert_eq!(image.pixel(71, 68), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(0, 0), [204, 255, 204, 255]);
+    }
 
-        let camera_pos_transform = if ui {
-            na::Translation3::new(0.0, 0.0, 0.0)
-        } else {
-            na::Translation3::new(
-                -self.camera.x,
-                -self.camera.y,
-                self.camera.y - viewport.y * 2.0,
+    #[test]
+    fn blends_translucent_quads_back_to_front() {
+        let mut renderer = software_renderer(160.0, 120.0);
+        let texture = TextureMap::new(1, 1, "white".to_string()).get_texture(0, 0);
+        let size = na::Vector2::new(200.0, 200.0);
+        let draw = |renderer: &mut Renderer, depth: f32, tint, blend| {
+            renderer.draw_quad_blended(
+                na::Point2::new(0.0, -100.0),
+                size,
+                &texture,
+                depth,
+                tint,
+                &QuadTransform::new(),
+                blend,
             )
         };
 
-        let orthographic_view = na::Orthographic3::new(
-            -viewport.x / 2.0,
-            viewport.x / 2.0,
-            -viewport.y / 2.0,
-            viewport.y / 2.0,
-            0.1,
-            viewport.y * 4.0,
+        // Drawn front to back, the translucent ones have to be sorted
+        draw(
+            &mut renderer,
+            -5.0,
+            na::Vector4::new(0.0, 0.0, 1.0, 0.5),
+            BlendMode::Alpha,
         );
-
-        self.gl.uniform_matrix4fv_with_f32_array(
-            Some(&viewport_uniform_location),
-            false,
-            orthographic_view.as_matrix().as_slice(),
+        draw(
+            &mut renderer,
+            -7.0,
+            na::Vector4::new(0.0, 1.0, 0.0, 1.0),
+            BlendMode::Additive,
         );
-        self.gl.uniform_matrix4fv_with_f32_array(
-            Some(&viewport_transform_location),
-            false,
-            camera_pos_transform.to_homogeneous().as_slice(),
+        draw(
+            &mut renderer,
+            -10.0,
+            na::Vector4::new(1.0, 0.0, 0.0, 1.0),
+            BlendMode::Alpha,
         );
-
-        self.gl.uniform2f(
-            Some(&fire_position_uniform_location),
-            self.fire_pos.x,
-            self.fire_pos.y,
+        renderer.draw_quad_blended(
+            na::Point2::new(-500.0, 300.0),
+            na::Vector2::new(100.0, 100.0),
+            &texture,
+            -1.0,
+            na::Vector4::new(0.5, 0.5, 0.5, 1.0),
+            &QuadTransform::new(),
+            BlendMode::Multiply,
+        );
+        renderer.flush();
+
+        let backend = software_backend(&renderer);
+        let draw_calls = backend.get_draw_calls();
+        assert_eq!(draw_calls.len(), 4);
+        assert!(!draw_calls[0].translucent);
+        assert_eq!(
+            draw_calls[1..]
+                .iter()
+                .map(|draw_call| draw_call.blend)
+                .collect::<Vec<_>>(),
+            [BlendMode::Additive, BlendMode::Alpha, BlendMode::Multiply]
         );
-        self.gl
-            .uniform1f(Some(&fire_heat_uniform_location), self.fire_heat);
+        let image = backend.get_image();
+        // Red, plus green, half covered by blue
+        assert_eq!(image.pixel(80, 65), [128, 128, 128, 255]);
+        // The background darkened by half
+        assert_eq!(image.pixel(30, 25), [102, 128, 102, 255]);
+    }
 
-        self.gl.enable_vertex_attrib_array(position_attrib_location);
-        self.gl.enable_vertex_attrib_array(color_attrib_location);
-        self.gl.enable_vertex_attrib_array(texcoord_attrib_location);
+    #[test]
+    fn rotates_and_flips_quads_around_their_pivot() {
+        let mut renderer = software_renderer(16.0, 12.0);
+        let texture = TextureMap::new(2, 1, "spritesheet".to_string()).get_texture(1, 0);
+        renderer.draw_quad_transformed(
+            na::Point2::new(10.0, 0.0),
+            na::Vector2::new(4.0, 2.0),
+            &texture,
+            0.0,
+            na::Vector4::new(1.0, 1.0, 1.0, 1.0),
+            &QuadTransform::new()
+                .with_pivot(na::Vector2::new(0.5, 0.5))
+                .with_rotation(std::f32::consts::FRAC_PI_2)
+                .flip_x(),
+        );
+        renderer.flush();
+
+        let draw_calls = software_backend(&renderer).get_draw_calls();
+        let corners: Vec<(f32, f32, f32, f32)> = draw_calls[0]
+            .vertices
+            .chunks_exact(VERTEX_SIZE)
+            .map(|vertex| (vertex[0].round(), vertex[1].round(), vertex[7], vertex[8]))
+            .collect();
+        // Flipped, the bottom left corner of the texture ends up on the right
+        // and turning a quarter moves it to the top
+        assert_eq!(
+            corners,
+            [
+                (11.0, 2.0, 0.5, 1.0),
+                (11.0, -2.0, 1.0, 1.0),
+                (9.0, 2.0, 0.5, 0.0),
+                (9.0, -2.0, 1.0, 0.0)
+            ]
+        );
+    }
 
-        self.gl.active_texture(WebGlRenderingContext::TEXTURE0);
+    #[test]
+    fn draws_the_placeholder_for_textures_not_loaded() {
+        let mut renderer = software_renderer(160.0, 120.0);
+        renderer
+            .get_assets_mut()
+            .set_placeholder(Image::filled(1, 1, [255, 0, 0, 255]));
+        renderer
+            .get_assets_mut()
+            .load_url("missing", "images/missing.png");
+        renderer.draw_quad(
+            na::Point2::new(0.0, -100.0),
+            na::Vector2::new(200.0, 200.0),
+            &TextureMap::new(1, 1, "missing".to_string()).get_texture(0, 0),
+        );
+        renderer.flush();
 
-        self.gl
-            .bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&texture));
-        self.gl.uniform1i(Some(&sampler_uniform_location), 0);
+        let backend = software_backend(&renderer);
+        assert_eq!(
+            backend.get_draw_calls()[0].texture_name,
+            crate::assets::PLACEHOLDER
+        );
+        assert_eq!(backend.get_image().pixel(80, 60), [255, 0, 0, 255]);
+    }
 
-        self.gl.draw_elements_with_i32(
-            WebGlRenderingContext::TRIANGLES,
-            (vertices.len() as i32) / (4 * VERTEX_SIZE as i32) * 6,
-            WebGlRenderingContext::UNSIGNED_SHORT,
-            0,
+    #[test]
+    fn maps_canvas_pixels_to_world_coordinates() {
+        let mut renderer = software_renderer(160.0, 120.0);
+        renderer.set_camera(na::Point2::new(300.0, 50.0));
+        assert_eq!(
+            renderer.screen_to_world(na::Point2::new(80.0, 60.0)),
+            na::Point2::new(300.0, 50.0)
+        );
+        assert_eq!(
+            renderer.screen_to_world(na::Point2::new(0.0, 0.0)),
+            na::Point2::new(-500.0, 650.0)
+        );
+        assert_eq!(
+            renderer.screen_to_world(na::Point2::new(160.0, 120.0)),
+            na::Point2::new(1100.0, -550.0)
         );
     }
 }
@@ -0,0 +1,302 @@
+extern crate nalgebra as na;
+
+use halfbrown::HashMap;
+
+use super::backend::{Backend, BlendMode, DrawCall};
+use super::{MAX_VERTICES, VERTEX_SIZE};
+
+const CLEAR_COLOR: [f32; 4] = [0.8, 1.0, 0.8, 1.0];
+
+/// Pixel x, pixel y (from the top) and depth in [0, 1].
+type WindowPoint = [f32; 3];
+
+/// An RGBA image with 8 bits per channel, stored row by row from the top.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, pixels: Vec<u8>) -> Image {
+        assert_eq!(pixels.len(), width * height * 4);
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Creates an image filled with a single colour.
+    pub fn filled(width: usize, height: usize, color: [u8; 4]) -> Image {
+        Image::new(width, height, color.repeat(width * height))
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * self.width + x) * 4;
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+}
+
+/// A draw call as seen by the `SoftwareBackend`, kept around for inspection in tests.
+#[derive(Clone, Debug)]
+pub struct RecordedDrawCall {
+    pub texture_name: String,
+    pub vertices: Vec<f32>,
+    pub blend: BlendMode,
+    pub translucent: bool,
+}
+
+impl RecordedDrawCall {
+    pub fn quad_count(&self) -> usize {
+        self.vertices.len() / (4 * VERTEX_SIZE)
+    }
+}
+
+/// A pure Rust backend which records every draw call and rasterises it into an
+/// in-memory framebuffer, so rendering can be tested without a browser.
+///
+/// The rasteriser follows the fixed parts of the WebGL pipeline (depth test,
+/// premultiplied blending, alpha discard and tinting) but does not emulate
+/// the fire lighting of the fragment shader.
+pub struct SoftwareBackend {
+    textures: HashMap<String, Image>,
+    draw_calls: Vec<RecordedDrawCall>,
+    frame_count: usize,
+
+    width: usize,
+    height: usize,
+    color: Vec<[f32; 4]>,
+    depth: Vec<f32>,
+}
+
+impl SoftwareBackend {
+    pub fn new() -> SoftwareBackend {
+        SoftwareBackend {
+            textures: HashMap::new(),
+            draw_calls: Vec::new(),
+            frame_count: 0,
+
+            width: 0,
+            height: 0,
+            color: Vec::new(),
+            depth: Vec::new(),
+        }
+    }
+
+    /// Registers the image used when drawing batches for `texture_name`.
+    /// Batches for unknown textures are drawn as if the texture was opaque white.
+    pub fn add_texture(&mut self, texture_name: &str, image: Image) {
+        self.textures.insert(texture_name.to_string(), image);
+    }
+
+    /// Draw calls of the last frame, in the order they were issued.
+    pub fn get_draw_calls(&self) -> &Vec<RecordedDrawCall> {
+        &self.draw_calls
+    }
+
+    /// Number of frames started since the backend was created.
+    pub fn get_frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    /// Returns the current contents of the framebuffer.
+    pub fn get_image(&self) -> Image {
+        let mut pixels = Vec::with_capacity(self.color.len() * 4);
+        for color in self.color.iter() {
+            for channel in color.iter() {
+                pixels.push((channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+            }
+        }
+        Image::new(self.width, self.height, pixels)
+    }
+
+    fn sample(&self, texture_name: &str, u: f32, v: f32) -> [f32; 4] {
+        match self.textures.get(texture_name) {
+            Some(image) => {
+                let x = ((u * image.width as f32).floor() as i64).rem_euclid(image.width as i64);
+                let y = ((v * image.height as f32).floor() as i64).rem_euclid(image.height as i64);
+                let texel = image.pixel(x as usize, y as usize);
+                [
+                    texel[0] as f32 / 255.0,
+                    texel[1] as f32 / 255.0,
+                    texel[2] as f32 / 255.0,
+                    texel[3] as f32 / 255.0,
+                ]
+            }
+            None => [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+
+    /// Transforms a vertex into window space.
+    ///
+    /// `matrix` is in column major order, multiplied out by hand as nalgebra is
+    /// painfully slow in unoptimised test builds.
+    fn to_window(&self, matrix: &[f32], vertex: &[f32]) -> WindowPoint {
+        let clip = |row: usize| {
+            matrix[row] * vertex[0]
+                + matrix[4 + row] * vertex[1]
+                + matrix[8 + row] * vertex[2]
+                + matrix[12 + row]
+        };
+        let w = clip(3);
+        [
+            (clip(0) / w + 1.0) / 2.0 * self.width as f32,
+            (1.0 - clip(1) / w) / 2.0 * self.height as f32,
+            (clip(2) / w + 1.0) / 2.0,
+        ]
+    }
+
+    fn rasterise_triangle(&mut self, draw_call: &DrawCall, corners: [(WindowPoint, &[f32]); 3]) {
+        let (p0, p1, p2) = (corners[0].0, corners[1].0, corners[2].0);
+        let area = edge(&p0, &p1, &p2);
+        if area == 0.0 {
+            return;
+        }
+
+        let min_x = p0[0].min(p1[0]).min(p2[0]).floor().max(0.0) as usize;
+        let min_y = p0[1].min(p1[1]).min(p2[1]).floor().max(0.0) as usize;
+        let max_x = (p0[0].max(p1[0]).max(p2[0]).ceil() as usize).min(self.width);
+        let max_y = (p0[1].max(p1[1]).max(p2[1]).ceil() as usize).min(self.height);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = [x as f32 + 0.5, y as f32 + 0.5, 0.0];
+                let w0 = edge(&p1, &p2, &p) / area;
+                let w1 = edge(&p2, &p0, &p) / area;
+                let w2 = edge(&p0, &p1, &p) / area;
+                if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                    continue;
+                }
+
+                let index = y * self.width + x;
+                let depth = w0 * p0[2] + w1 * p1[2] + w2 * p2[2];
+                if depth >= self.depth[index] || !(0.0..=1.0).contains(&depth) {
+                    continue;
+                }
+
+                let attribute = |offset: usize| {
+                    w0 * corners[0].1[offset]
+                        + w1 * corners[1].1[offset]
+                        + w2 * corners[2].1[offset]
+                };
+                let mut texel = self.sample(draw_call.texture_name, attribute(7), attribute(8));
+                texel[0] *= texel[3];
+                texel[1] *= texel[3];
+                texel[2] *= texel[3];
+                if texel[3] < draw_call.get_alpha_cutoff() {
+                    continue;
+                }
+                let tint_alpha = attribute(6);
+                let source = [
+                    texel[0] * attribute(3) * tint_alpha,
+                    texel[1] * attribute(4) * tint_alpha,
+                    texel[2] * attribute(5) * tint_alpha,
+                    texel[3] * tint_alpha,
+                ];
+
+                let destination = &mut self.color[index];
+                for channel in 0..4 {
+                    let behind = destination[channel] * (1.0 - source[3]);
+                    destination[channel] = match draw_call.blend {
+                        BlendMode::Alpha => source[channel] + behind,
+                        BlendMode::Additive => source[channel] + destination[channel],
+                        BlendMode::Multiply => source[channel] * destination[channel] + behind,
+                    };
+                }
+                if !draw_call.translucent {
+                    self.depth[index] = depth;
+                }
+            }
+        }
+    }
+}
+
+impl Default for SoftwareBackend {
+    fn default() -> Self {
+        SoftwareBackend::new()
+    }
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `c`.
+fn edge(a: &WindowPoint, b: &WindowPoint, c: &WindowPoint) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+impl Backend for SoftwareBackend {
+    fn load_shader(&mut self, _vertex: &str, _fragment: &str) -> i32 {
+        0
+    }
+
+    fn load_texture(&mut self, texture_name: &str, image: &Image) {
+        self.add_texture(texture_name, image.clone());
+    }
+
+    fn set_viewport(&mut self, viewport: na::Vector2<f32>) {
+        let width = viewport.x.max(0.0) as usize;
+        let height = viewport.y.max(0.0) as usize;
+        if width != self.width || height != self.height {
+            self.width = width;
+            self.height = height;
+            self.color = vec![CLEAR_COLOR; width * height];
+            self.depth = vec![1.0; width * height];
+        }
+    }
+
+    fn begin_frame(&mut self) {
+        self.frame_count += 1;
+        self.draw_calls.clear();
+        for color in self.color.iter_mut() {
+            *color = CLEAR_COLOR;
+        }
+        for depth in self.depth.iter_mut() {
+            *depth = 1.0;
+        }
+    }
+
+    fn draw(&mut self, draw_call: &DrawCall) {
+        // Like the index buffer of the WebGL backend
+        assert!(
+            draw_call.vertices.len() <= MAX_VERTICES * VERTEX_SIZE,
+            "more than MAX_QUADS quads in one draw call"
+        );
+        self.draw_calls.push(RecordedDrawCall {
+            texture_name: draw_call.texture_name.to_string(),
+            vertices: draw_call.vertices.to_vec(),
+            blend: draw_call.blend,
+            translucent: draw_call.translucent,
+        });
+
+        let matrix = draw_call.projection * draw_call.transform;
+        for quad in draw_call.vertices.chunks_exact(4 * VERTEX_SIZE) {
+            let vertex = |i: usize| &quad[i * VERTEX_SIZE..(i + 1) * VERTEX_SIZE];
+            let window: Vec<WindowPoint> = (0..4)
+                .map(|i| self.to_window(matrix.as_slice(), vertex(i)))
+                .collect();
+
+            // Same winding as the index buffer of the WebGL backend
+            for triangle in [[0, 1, 2], [2, 3, 1]].iter() {
+                self.rasterise_triangle(
+                    draw_call,
+                    [
+                        (window[triangle[0]], vertex(triangle[0])),
+                        (window[triangle[1]], vertex(triangle[1])),
+                        (window[triangle[2]], vertex(triangle[2])),
+                    ],
+                );
+            }
+        }
+    }
+
+    fn end_frame(&mut self) {
+        for color in self.color.iter_mut() {
+            color[3] = 1.0;
+        }
+    }
+}
@@ -0,0 +1,275 @@
+extern crate nalgebra as na;
+
+use halfbrown::HashMap;
+use web_sys::{WebGlBuffer, WebGlProgram, WebGlRenderingContext, WebGlTexture};
+
+use super::backend::{Backend, BlendMode, DrawCall};
+use super::glutil;
+use super::Image;
+use super::{FLOAT32_BYTES, MAX_INDICES, MAX_QUADS, MAX_VERTICES, VERTEX_SIZE};
+use crate::assets::PLACEHOLDER;
+
+/// Draws batches with WebGL in the browser.
+pub struct WebGlBackend {
+    /// Anything drawn with a texture that isn't here gets the placeholder.
+    textures: HashMap<String, WebGlTexture>,
+
+    pub gl: WebGlRenderingContext,
+    vertex_buffer: WebGlBuffer,
+    index_buffer: WebGlBuffer,
+
+    programs: HashMap<i32, WebGlProgram>,
+    selected_program: i32,
+}
+
+impl WebGlBackend {
+    pub fn new(gl: WebGlRenderingContext) -> WebGlBackend {
+        // Configure GL
+        gl.enable(WebGlRenderingContext::DEPTH_TEST);
+        gl.depth_func(WebGlRenderingContext::LESS);
+
+        gl.enable(WebGlRenderingContext::BLEND);
+
+        // Initialise indices
+        let mut indices: Vec<u16> = Vec::with_capacity(MAX_INDICES);
+        let mut offset = 0;
+        for _ in 0..MAX_QUADS {
+            indices.push(offset);
+            indices.push(offset + 1);
+            indices.push(offset + 2);
+
+            indices.push(offset + 2);
+            indices.push(offset + 3);
+            indices.push(offset + 1);
+
+            offset += 4;
+        }
+
+        // Initialise buffers
+        let vertex_buffer = gl
+            .create_buffer()
+            .ok_or("failed to create vertex buffer")
+            .unwrap();
+
+        let index_buffer = gl
+            .create_buffer()
+            .ok_or("failed to create index buffer")
+            .unwrap();
+
+        // Room for the biggest draw call, filled with buffer_sub_data every draw
+        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&vertex_buffer));
+        gl.buffer_data_with_i32(
+            WebGlRenderingContext::ARRAY_BUFFER,
+            (MAX_VERTICES * VERTEX_SIZE) as i32 * FLOAT32_BYTES,
+            WebGlRenderingContext::DYNAMIC_DRAW,
+        );
+
+        // The indices never change
+        gl.bind_buffer(
+            WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
+            Some(&index_buffer),
+        );
+        // danger zone: this is a live view to memory. No allocations in this block!
+        unsafe {
+            let index_array = js_sys::Uint16Array::view(indices.as_slice());
+
+            gl.buffer_data_with_array_buffer_view(
+                WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
+                &index_array,
+                WebGlRenderingContext::STATIC_DRAW,
+            );
+        }
+
+        WebGlBackend {
+            textures: HashMap::new(),
+
+            gl,
+            vertex_buffer,
+            index_buffer,
+
+            selected_program: 0,
+            programs: HashMap::new(),
+        }
+    }
+}
+
+impl Backend for WebGlBackend {
+    fn load_shader(&mut self, vertex: &str, fragment: &str) -> i32 {
+        let vert_shader =
+            glutil::compile_shader(&self.gl, WebGlRenderingContext::VERTEX_SHADER, vertex).unwrap();
+        let frag_shader =
+            glutil::compile_shader(&self.gl, WebGlRenderingContext::FRAGMENT_SHADER, fragment)
+                .unwrap();
+
+        let program = glutil::link_program(&self.gl, &vert_shader, &frag_shader).unwrap();
+        let key = self.programs.len() as i32;
+        self.programs.insert(key, program);
+
+        key
+    }
+
+    fn load_texture(&mut self, texture_name: &str, image: &Image) {
+        match glutil::upload_texture(&self.gl, image) {
+            Ok(texture) => {
+                self.textures.insert(texture_name.to_string(), texture);
+            }
+            Err(error) => log::error!("{}: {}", texture_name, error),
+        }
+    }
+
+    fn set_viewport(&mut self, viewport: na::Vector2<f32>) {
+        self.gl.viewport(0, 0, viewport.x as i32, viewport.y as i32);
+    }
+
+    fn begin_frame(&mut self) {
+        // Translucent batches leave depth writes off
+        self.gl.depth_mask(true);
+        self.gl.clear_color(0.8, 1.0, 0.8, 1.0);
+        self.gl.clear(
+            WebGlRenderingContext::COLOR_BUFFER_BIT | WebGlRenderingContext::DEPTH_BUFFER_BIT,
+        );
+    }
+
+    fn draw(&mut self, draw_call: &DrawCall) {
+        let texture = match self
+            .textures
+            .get(draw_call.texture_name)
+            .or_else(|| self.textures.get(PLACEHOLDER))
+        {
+            Some(texture) => texture,
+            None => return,
+        };
+        let vertices = draw_call.vertices;
+        assert!(
+            vertices.len() <= MAX_VERTICES * VERTEX_SIZE,
+            "more than MAX_QUADS quads in one draw call"
+        );
+
+        let program = self.programs.get(&self.selected_program).unwrap();
+        self.gl.use_program(Some(program));
+
+        // Colours are premultiplied by alpha
+        let (source_factor, destination_factor) = match draw_call.blend {
+            BlendMode::Alpha => (
+                WebGlRenderingContext::ONE,
+                WebGlRenderingContext::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendMode::Additive => (WebGlRenderingContext::ONE, WebGlRenderingContext::ONE),
+            BlendMode::Multiply => (
+                WebGlRenderingContext::DST_COLOR,
+                WebGlRenderingContext::ONE_MINUS_SRC_ALPHA,
+            ),
+        };
+        self.gl.blend_func(source_factor, destination_factor);
+        self.gl.depth_mask(!draw_call.translucent);
+
+        self.gl.bind_buffer(
+            WebGlRenderingContext::ARRAY_BUFFER,
+            Some(&self.vertex_buffer),
+        );
+
+        // danger zone: this is a live view to memory. No allocations in this block!
+        unsafe {
+            let vert_array = js_sys::Float32Array::view(vertices);
+
+            self.gl.buffer_sub_data_with_i32_and_array_buffer_view(
+                WebGlRenderingContext::ARRAY_BUFFER,
+                0,
+                &vert_array,
+            );
+        }
+
+        self.gl.bind_buffer(
+            WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
+            Some(&self.index_buffer),
+        );
+
+        let position_attrib_location = self.gl.get_attrib_location(program, "aPosition") as u32;
+        let color_attrib_location = self.gl.get_attrib_location(program, "aColor") as u32;
+        let texcoord_attrib_location = self.gl.get_attrib_location(program, "aTexCoord") as u32;
+        let sampler_uniform_location = self.gl.get_uniform_location(program, "uSampler").unwrap();
+        let viewport_uniform_location = self.gl.get_uniform_location(program, "uViewport").unwrap();
+        let viewport_transform_location =
+            self.gl.get_uniform_location(program, "uTransform").unwrap();
+        let fire_position_uniform_location =
+            self.gl.get_uniform_location(program, "uFirePos").unwrap();
+        let fire_heat_uniform_location =
+            self.gl.get_uniform_location(program, "uFireHeat").unwrap();
+        let alpha_cutoff_uniform_location = self
+            .gl
+            .get_uniform_location(program, "uAlphaCutoff")
+            .unwrap();
+
+        self.gl.vertex_attrib_pointer_with_i32(
+            position_attrib_location,
+            3,
+            WebGlRenderingContext::FLOAT,
+            false,
+            (VERTEX_SIZE as i32) * FLOAT32_BYTES,
+            0,
+        );
+        self.gl.vertex_attrib_pointer_with_i32(
+            color_attrib_location,
+            4,
+            WebGlRenderingContext::FLOAT,
+            false,
+            (VERTEX_SIZE as i32) * FLOAT32_BYTES,
+            3 * FLOAT32_BYTES,
+        );
+        self.gl.vertex_attrib_pointer_with_i32(
+            texcoord_attrib_location,
+            2,
+            WebGlRenderingContext::FLOAT,
+            false,
+            (VERTEX_SIZE as i32) * FLOAT32_BYTES,
+            7 * FLOAT32_BYTES,
+        );
+
+        self.gl.uniform_matrix4fv_with_f32_array(
+            Some(&viewport_uniform_location),
+            false,
+            draw_call.projection.as_slice(),
+        );
+        self.gl.uniform_matrix4fv_with_f32_array(
+            Some(&viewport_transform_location),
+            false,
+            draw_call.transform.as_slice(),
+        );
+
+        self.gl.uniform2f(
+            Some(&fire_position_uniform_location),
+            draw_call.fire_pos.x,
+            draw_call.fire_pos.y,
+        );
+        self.gl
+            .uniform1f(Some(&fire_heat_uniform_location), draw_call.fire_heat);
+        self.gl.uniform1f(
+            Some(&alpha_cutoff_uniform_location),
+            draw_call.get_alpha_cutoff(),
+        );
+
+        self.gl.enable_vertex_attrib_array(position_attrib_location);
+        self.gl.enable_vertex_attrib_array(color_attrib_location);
+        self.gl.enable_vertex_attrib_array(texcoord_attrib_location);
+
+        self.gl.active_texture(WebGlRenderingContext::TEXTURE0);
+
+        self.gl
+            .bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(texture));
+        self.gl.uniform1i(Some(&sampler_uniform_location), 0);
+
+        self.gl.draw_elements_with_i32(
+            WebGlRenderingContext::TRIANGLES,
+            (vertices.len() as i32) / (4 * VERTEX_SIZE as i32) * 6,
+            WebGlRenderingContext::UNSIGNED_SHORT,
+            0,
+        );
+    }
+
+    fn end_frame(&mut self) {
+        self.gl.clear_color(0.0, 0.0, 0.0, 1.0);
+        self.gl.color_mask(false, false, false, true);
+        self.gl.clear(WebGlRenderingContext::COLOR_BUFFER_BIT);
+        self.gl.color_mask(true, true, true, true);
+    }
+}
@@ -7,6 +7,7 @@ varying vec2 vPosition;
 uniform vec2 uFirePos;
 uniform float uFireHeat;
 uniform sampler2D uSampler;
+uniform float uAlphaCutoff;
 
 const float c = 800.0;
 
@@ -30,7 +31,7 @@ vec3 colorTemperatureToRGB(const in float temperature){
 void main() {
   vec4 texColor = texture2D(uSampler, vTexCoord);
   texColor.rgb *= texColor.a;
-  if (texColor.a < 0.5) discard;
+  if (texColor.a < uAlphaCutoff) discard;
 
   float r = length(vPosition - uFirePos);
   float r2 = (((max(0.0, r) - 48.0) + c) / c);
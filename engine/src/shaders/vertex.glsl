@@ -1,7 +1,7 @@
 precision highp float;
 
 attribute vec3 aPosition;
-attribute vec3 aColor;
+attribute vec4 aColor;
 attribute vec2 aTexCoord;
 
 varying vec4 vFragColor;
@@ -14,6 +14,6 @@ uniform mat4 uTransform;
 void main() {
   vPosition = aPosition.xy;
   gl_Position = uViewport * uTransform * vec4(aPosition, 1.0);
-  vFragColor = vec4(aColor, 1.0);
+  vFragColor = vec4(aColor.rgb * aColor.a, aColor.a);
   vTexCoord = aTexCoord;
 }
\ No newline at end of file
@@ -0,0 +1,203 @@
+//! A spatial hash of circles, so finding what's near a point only looks at the
+//! cells around it instead of every object in the world.
+use halfbrown::HashMap;
+
+use crate::entity::Entity;
+
+/// Cell size used by `Entities`, a few times the size of a typical collider.
+pub const DEFAULT_CELL_SIZE: f32 = 128.0;
+
+type Cell = (i32, i32);
+
+struct Entry {
+    pos: na::Point2<f32>,
+    range: f32,
+    min: Cell,
+    max: Cell,
+}
+
+/// Circles keyed by `Entity`, bucketed into square cells of `cell_size`.
+///
+/// A circle is stored in every cell its bounding box touches, so it's best to
+/// pick a cell size somewhat larger than most ranges.
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<Cell, Vec<Entity>>,
+    entries: HashMap<Entity, Entry>,
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> SpatialHash {
+        assert!(cell_size > 0.0, "cell size must be positive");
+        SpatialHash {
+            cell_size,
+            cells: HashMap::new(),
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn get_cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entries.contains_key(&entity)
+    }
+
+    fn cell(&self, x: f32, y: f32) -> Cell {
+        (
+            (x / self.cell_size).floor() as i32,
+            (y / self.cell_size).floor() as i32,
+        )
+    }
+
+    fn bounds(&self, pos: na::Point2<f32>, range: f32) -> (Cell, Cell) {
+        (
+            self.cell(pos.x - range, pos.y - range),
+            self.cell(pos.x + range, pos.y + range),
+        )
+    }
+
+    /// Adds a circle, or moves it if the entity is already in the hash. Moving
+    /// within the same cells doesn't touch the buckets.
+    pub fn insert(&mut self, entity: Entity, pos: na::Point2<f32>, range: f32) {
+        let (min, max) = self.bounds(pos, range);
+        if let Some(entry) = self.entries.get_mut(&entity) {
+            if entry.min == min && entry.max == max {
+                entry.pos = pos;
+                entry.range = range;
+                return;
+            }
+        }
+        self.remove(entity);
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                match self.cells.get_mut(&(x, y)) {
+                    Some(cell) => cell.push(entity),
+                    None => {
+                        self.cells.insert((x, y), vec![entity]);
+                    }
+                }
+            }
+        }
+        self.entries.insert(
+            entity,
+            Entry {
+                pos,
+                range,
+                min,
+                max,
+            },
+        );
+    }
+
+    /// Removes a circle, returns false if the entity wasn't in the hash.
+    pub fn remove(&mut self, entity: Entity) -> bool {
+        let entry = match self.entries.remove(&entity) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        for x in entry.min.0..=entry.max.0 {
+            for y in entry.min.1..=entry.max.1 {
+                if let Some(cell) = self.cells.get_mut(&(x, y)) {
+                    cell.retain(|other| *other != entity);
+                    if cell.is_empty() {
+                        self.cells.remove(&(x, y));
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Every circle overlapping the circle of `radius` around `pos`, ordered by
+    /// entity.
+    pub fn query(&self, pos: na::Point2<f32>, radius: f32) -> Vec<Entity> {
+        let (min, max) = self.bounds(pos, radius);
+        let mut found = Vec::new();
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                if let Some(cell) = self.cells.get(&(x, y)) {
+                    found.extend(cell.iter().filter(|entity| {
+                        let entry = &self.entries[*entity];
+                        na::distance_squared(&entry.pos, &pos) < (entry.range + radius).powi(2)
+                    }));
+                }
+            }
+        }
+        found.sort();
+        found.dedup();
+        found
+    }
+
+    /// Every circle overlapping the rectangle from `min` to `max`, ordered by
+    /// entity.
+    pub fn query_rect(&self, min: na::Point2<f32>, max: na::Point2<f32>) -> Vec<Entity> {
+        let (min_cell, max_cell) = (self.cell(min.x, min.y), self.cell(max.x, max.y));
+        let mut found = Vec::new();
+        for x in min_cell.0..=max_cell.0 {
+            for y in min_cell.1..=max_cell.1 {
+                if let Some(cell) = self.cells.get(&(x, y)) {
+                    found.extend(cell.iter().filter(|entity| {
+                        let entry = &self.entries[*entity];
+                        let closest = na::Point2::new(
+                            entry.pos.x.clamp(min.x, max.x),
+                            entry.pos.y.clamp(min.y, max.y),
+                        );
+                        na::distance_squared(&entry.pos, &closest) <= entry.range.powi(2)
+                    }));
+                }
+            }
+        }
+        found.sort();
+        found.dedup();
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::Entities;
+    use crate::GameObject;
+
+    #[test]
+    fn finds_overlapping_circles_as_they_move() {
+        let mut entities = Entities::new();
+        let a = entities.spawn(GameObject::new(na::Point2::new(0.0, 0.0)));
+        let b = entities.spawn(GameObject::new(na::Point2::new(0.0, 0.0)));
+        let c = entities.spawn(GameObject::new(na::Point2::new(0.0, 0.0)));
+
+        let mut hash = SpatialHash::new(10.0);
+        hash.insert(a, na::Point2::new(0.0, 0.0), 1.0);
+        hash.insert(b, na::Point2::new(25.0, 0.0), 30.0);
+        hash.insert(c, na::Point2::new(-15.0, -15.0), 2.0);
+        assert_eq!(hash.query(na::Point2::new(0.0, 0.0), 0.5), [a, b]);
+        assert_eq!(hash.query(na::Point2::new(-13.0, -13.0), 1.0), [c]);
+        assert!(hash.query(na::Point2::new(100.0, 100.0), 5.0).is_empty());
+
+        hash.insert(a, na::Point2::new(-14.0, -14.0), 1.0);
+        hash.insert(c, na::Point2::new(-14.5, -14.5), 2.0);
+        assert_eq!(hash.query(na::Point2::new(-13.0, -13.0), 1.0), [a, c]);
+        assert!(hash.remove(c));
+        assert!(!hash.remove(c));
+        assert_eq!(hash.query(na::Point2::new(-13.0, -13.0), 1.0), [a]);
+        assert_eq!(hash.len(), 2);
+        assert_eq!(
+            hash.query_rect(na::Point2::new(-20.0, -20.0), na::Point2::new(-14.5, -10.0)),
+            [a]
+        );
+        assert_eq!(
+            hash.query_rect(na::Point2::new(0.0, -5.0), na::Point2::new(1.0, 5.0)),
+            [b]
+        );
+    }
+}
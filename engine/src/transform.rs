@@ -0,0 +1,77 @@
+//! Translation, rotation and scale, for placing children relative to their
+//! parents (see `Entities::set_parent`).
+
+/// Scales, then rotates counterclockwise by `rotation` radians, then translates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: na::Vector2<f32>,
+    pub rotation: f32,
+    pub scale: na::Vector2<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform::new(na::Vector2::zeros())
+    }
+
+    pub fn new(translation: na::Vector2<f32>) -> Transform {
+        Transform {
+            translation,
+            rotation: 0.0,
+            scale: na::Vector2::new(1.0, 1.0),
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Transform {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: na::Vector2<f32>) -> Transform {
+        self.scale = scale;
+        self
+    }
+
+    /// Maps a point from this transform's space to the space around it.
+    pub fn apply(&self, point: na::Point2<f32>) -> na::Point2<f32> {
+        let scaled = point.coords.component_mul(&self.scale);
+        na::Point2::from(na::Rotation2::new(self.rotation) * scaled + self.translation)
+    }
+
+    /// Places `local`, given relative to this transform, in the space around
+    /// this transform.
+    ///
+    /// Rotated children of non-uniformly scaled parents would need a shear,
+    /// which can't be represented, so they keep their own axes.
+    pub fn combine(&self, local: &Transform) -> Transform {
+        Transform {
+            translation: self.apply(na::Point2::from(local.translation)).coords,
+            rotation: self.rotation + local.rotation,
+            scale: self.scale.component_mul(&local.scale),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combines_parent_and_child_transforms() {
+        let parent = Transform::new(na::Vector2::new(10.0, 0.0))
+            .with_rotation(std::f32::consts::FRAC_PI_2)
+            .with_scale(na::Vector2::new(2.0, 2.0));
+        let child = Transform::new(na::Vector2::new(3.0, 0.0)).with_rotation(0.5);
+        let world = parent.combine(&child);
+        assert!((world.translation - na::Vector2::new(10.0, 6.0)).norm() < 1e-5);
+        assert!((world.rotation - (std::f32::consts::FRAC_PI_2 + 0.5)).abs() < 1e-6);
+        assert_eq!(world.scale, na::Vector2::new(2.0, 2.0));
+        assert_eq!(Transform::identity().combine(&child), child);
+    }
+}
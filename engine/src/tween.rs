@@ -0,0 +1,402 @@
+//! Animates properties of a `GameObject` over time.
+//!
+//! A `Tween` moves a value from one end to another along an easing curve, after
+//! an optional delay, and can be followed by more tweens. The `Tweens` component
+//! holds the tweens running on a `GameObject`, `start` adds one. Like
+//! `animation::step`, the engine calls `step` after every update of a `World`
+//! that exposes its `Entities`.
+//!
+//! Times are in milliseconds.
+extern crate nalgebra as na;
+
+use std::f32::consts::PI;
+
+use crate::entity::{Commands, Entities, Entity};
+use crate::GameObject;
+
+/// How a tween gets from start to end, see <https://easings.net>.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Ease {
+    #[default]
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+    ExpoIn,
+    ExpoOut,
+    /// Overshoots the end a little and comes back.
+    BackOut,
+    BounceOut,
+}
+
+impl Ease {
+    /// How far along a tween is once `t` of its time has passed, both going
+    /// from 0 to 1.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Ease::Linear => t,
+            Ease::QuadIn => t * t,
+            Ease::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Ease::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (2.0 - 2.0 * t).powi(2) / 2.0
+                }
+            }
+            Ease::CubicIn => t.powi(3),
+            Ease::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Ease::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t.powi(3)
+                } else {
+                    1.0 - (2.0 - 2.0 * t).powi(3) / 2.0
+                }
+            }
+            Ease::SineIn => 1.0 - (t * PI / 2.0).cos(),
+            Ease::SineOut => (t * PI / 2.0).sin(),
+            Ease::SineInOut => (1.0 - (t * PI).cos()) / 2.0,
+            Ease::ExpoIn if t == 0.0 => 0.0,
+            Ease::ExpoIn => 2f32.powf(10.0 * t - 10.0),
+            Ease::ExpoOut if t == 1.0 => 1.0,
+            Ease::ExpoOut => 1.0 - 2f32.powf(-10.0 * t),
+            Ease::BackOut => {
+                let overshoot = 1.70158;
+                1.0 + (overshoot + 1.0) * (t - 1.0).powi(3) + overshoot * (t - 1.0).powi(2)
+            }
+            Ease::BounceOut => {
+                let (n, d) = (7.5625, 2.75);
+                if t < 1.0 / d {
+                    n * t * t
+                } else if t < 2.0 / d {
+                    let t = t - 1.5 / d;
+                    n * t * t + 0.75
+                } else if t < 2.5 / d {
+                    let t = t - 2.25 / d;
+                    n * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / d;
+                    n * t * t + 0.984375
+                }
+            }
+        }
+    }
+}
+
+/// Values a tween can move between.
+pub trait Lerp: Copy + 'static {
+    /// The value `t` of the way from `from` to `to`.
+    fn lerp(from: Self, to: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(from: f32, to: f32, t: f32) -> f32 {
+        from + (to - from) * t
+    }
+}
+
+impl Lerp for na::Vector2<f32> {
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(&to, t)
+    }
+}
+
+impl Lerp for na::Vector3<f32> {
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(&to, t)
+    }
+}
+
+impl Lerp for na::Vector4<f32> {
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(&to, t)
+    }
+}
+
+impl Lerp for na::Point2<f32> {
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        na::Point2::from(from.coords.lerp(&to.coords, t))
+    }
+}
+
+type Setter = Box<dyn FnMut(&mut GameObject, f32)>;
+type Callback = Box<dyn FnOnce(Entity, &mut GameObject, &mut Commands)>;
+
+/// Moves a value of a `GameObject` over time.
+pub struct Tween {
+    set: Setter,
+    duration: f64,
+    delay: f64,
+    ease: Ease,
+    elapsed: f64,
+    on_complete: Option<Callback>,
+    next: Option<Box<Tween>>,
+}
+
+impl Tween {
+    /// Goes from `from` to `to` in `duration` milliseconds, handing every value
+    /// on the way to `set`.
+    pub fn new<T, F>(from: T, to: T, duration: f64, mut set: F) -> Tween
+    where
+        T: Lerp,
+        F: FnMut(&mut GameObject, T) + 'static,
+    {
+        Tween::with_setter(
+            duration,
+            Box::new(move |game_object, t| set(game_object, T::lerp(from, to, t))),
+        )
+    }
+
+    /// Goes to `to` in `duration` milliseconds from whatever `get` gives once
+    /// the tween starts, handing every value on the way to `set`.
+    pub fn to<T, G, F>(to: T, duration: f64, get: G, mut set: F) -> Tween
+    where
+        T: Lerp,
+        G: Fn(&GameObject) -> T + 'static,
+        F: FnMut(&mut GameObject, T) + 'static,
+    {
+        let mut from = None;
+        Tween::with_setter(
+            duration,
+            Box::new(move |game_object, t| {
+                let from = *from.get_or_insert_with(|| get(game_object));
+                set(game_object, T::lerp(from, to, t))
+            }),
+        )
+    }
+
+    /// Moves a `GameObject` to `pos`.
+    pub fn move_to(pos: na::Point2<f32>, duration: f64) -> Tween {
+        Tween::to(
+            pos,
+            duration,
+            |game_object| game_object.pos,
+            |game_object, pos| game_object.pos = pos,
+        )
+    }
+
+    /// Scales a `GameObject` to `scale`.
+    pub fn scale_to(scale: na::Vector2<f32>, duration: f64) -> Tween {
+        Tween::to(
+            scale,
+            duration,
+            |game_object| game_object.scale,
+            |game_object, scale| game_object.scale = scale,
+        )
+    }
+
+    /// Turns a `GameObject` to `rotation`.
+    pub fn rotate_to(rotation: f32, duration: f64) -> Tween {
+        Tween::to(
+            rotation,
+            duration,
+            |game_object| game_object.rotation,
+            |game_object, rotation| game_object.rotation = rotation,
+        )
+    }
+
+    fn with_setter(duration: f64, set: Setter) -> Tween {
+        assert!(duration >= 0.0, "tweens can't take negative time");
+        Tween {
+            set,
+            duration,
+            delay: 0.0,
+            ease: Ease::Linear,
+            elapsed: 0.0,
+            on_complete: None,
+            next: None,
+        }
+    }
+
+    pub fn with_ease(mut self, ease: Ease) -> Tween {
+        self.ease = ease;
+        self
+    }
+
+    /// Waits `delay` milliseconds before starting, leaving the value alone in
+    /// the meantime.
+    pub fn with_delay(mut self, delay: f64) -> Tween {
+        self.delay = delay;
+        self
+    }
+
+    /// Calls `f` once the tween reaches its end, with a way to queue spawns and
+    /// despawns.
+    pub fn on_complete<F>(mut self, f: F) -> Tween
+    where
+        F: FnOnce(Entity, &mut GameObject, &mut Commands) + 'static,
+    {
+        self.on_complete = Some(Box::new(f));
+        self
+    }
+
+    /// Starts `tween` once this tween and the ones chained to it so far end.
+    pub fn then(mut self, tween: Tween) -> Tween {
+        let next = match self.next.take() {
+            Some(next) => next.then(tween),
+            None => tween,
+        };
+        self.next = Some(Box::new(next));
+        self
+    }
+
+    /// Moves on by `dt` milliseconds, returning how much time is left over
+    /// once the tween has ended.
+    fn advance(&mut self, game_object: &mut GameObject, dt: f64) -> Option<f64> {
+        self.elapsed += dt;
+        let time = self.elapsed - self.delay;
+        if time < 0.0 {
+            return None;
+        }
+        let progress = if self.duration > 0.0 {
+            (time / self.duration).min(1.0)
+        } else {
+            1.0
+        };
+        (self.set)(game_object, self.ease.apply(progress as f32));
+        if time < self.duration {
+            None
+        } else {
+            Some(time - self.duration)
+        }
+    }
+}
+
+/// The tweens running on a `GameObject`. Tweens that ended are dropped, and
+/// the component with them once none are left.
+#[derive(Default)]
+pub struct Tweens {
+    running: Vec<Tween>,
+}
+
+impl Tweens {
+    pub fn new() -> Tweens {
+        Tweens::default()
+    }
+
+    /// Runs `tween` alongside the others. Tweens are stepped in the order they
+    /// were added, so the latest wins if several set the same value.
+    pub fn add(&mut self, tween: Tween) {
+        self.running.push(tween);
+    }
+
+    pub fn len(&self) -> usize {
+        self.running.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.running.is_empty()
+    }
+
+    /// Stops every tween where it is, without calling completion callbacks.
+    pub fn clear(&mut self) {
+        self.running.clear();
+    }
+}
+
+/// Runs `tween` on `game_object`, adding a `Tweens` component if it has none.
+pub fn start(game_object: &mut GameObject, tween: Tween) {
+    match game_object.get_component_mut::<Tweens>() {
+        Some(tweens) => tweens.add(tween),
+        None => {
+            let mut tweens = Tweens::new();
+            tweens.add(tween);
+            game_object.add_component(tweens);
+        }
+    }
+}
+
+/// Moves every tween forward by `dt` milliseconds. Time left over when a tween
+/// ends goes to the one chained after it.
+pub fn step(entities: &mut Entities, dt: f64) {
+    entities.for_each_with_mut::<Tweens, _>(|entity, game_object, commands| {
+        // Taken out so tweens can change the rest of the object
+        let tweens = game_object.remove_component::<Tweens>().unwrap();
+        let mut running = Vec::with_capacity(tweens.running.len());
+        for mut tween in tweens.running {
+            let mut dt = dt;
+            while let Some(left_over) = tween.advance(game_object, dt) {
+                if let Some(on_complete) = tween.on_complete.take() {
+                    on_complete(entity, game_object, commands);
+                }
+                match tween.next.take() {
+                    Some(next) => {
+                        tween = *next;
+                        dt = left_over;
+                    }
+                    None => break,
+                }
+            }
+            if tween.elapsed - tween.delay < tween.duration {
+                running.push(tween);
+            }
+        }
+        // Callbacks can start tweens too
+        if let Some(started) = game_object.remove_component::<Tweens>() {
+            running.extend(started.running);
+        }
+        if !running.is_empty() {
+            game_object.add_component(Tweens { running });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chains_tweens_with_delays_and_callbacks() {
+        assert_eq!(Ease::QuadIn.apply(0.5), 0.25);
+        assert_eq!(Ease::CubicInOut.apply(0.5), 0.5);
+        for ease in [
+            Ease::SineInOut,
+            Ease::ExpoOut,
+            Ease::BackOut,
+            Ease::BounceOut,
+        ] {
+            assert!(ease.apply(0.0).abs() < 1e-6, "{:?}", ease);
+            assert!((ease.apply(1.0) - 1.0).abs() < 1e-6, "{:?}", ease);
+        }
+
+        let mut entities = Entities::new();
+        let mut game_object = GameObject::new(na::Point2::new(0.0, 0.0));
+        start(
+            &mut game_object,
+            Tween::move_to(na::Point2::new(10.0, 0.0), 100.0)
+                .then(
+                    Tween::scale_to(na::Vector2::new(3.0, 3.0), 100.0)
+                        .with_delay(50.0)
+                        .on_complete(|_, game_object, _| game_object.rotation = 1.0),
+                )
+                .then(
+                    Tween::new(0.0, 1.0, 0.0, |game_object, _| game_object.rotation = 2.0)
+                        .on_complete(|entity, _, commands| commands.despawn(entity)),
+                ),
+        );
+        let entity = entities.spawn(game_object);
+
+        step(&mut entities, 50.0);
+        assert_eq!(entities.get(entity).unwrap().pos, na::Point2::new(5.0, 0.0));
+        // The left over 25 ms count towards the delay of the scale tween
+        step(&mut entities, 75.0);
+        let game_object = entities.get(entity).unwrap();
+        assert_eq!(game_object.pos, na::Point2::new(10.0, 0.0));
+        assert_eq!(game_object.scale, na::Vector2::new(1.0, 1.0));
+        step(&mut entities, 75.0);
+        let game_object = entities.get(entity).unwrap();
+        assert_eq!(game_object.scale, na::Vector2::new(2.0, 2.0));
+        assert_eq!(game_object.rotation, 0.0);
+        assert!(game_object.has_component::<Tweens>());
+
+        step(&mut entities, 50.0);
+        assert!(!entities.contains(entity));
+    }
+}
@@ -6,9 +6,8 @@ extern crate poisson;
 extern crate rand;
 extern crate wee_alloc;
 
-use std::collections::HashMap;
 use std::convert::TryInto;
-use std::hash::BuildHasherDefault;
+use std::rc::Rc;
 
 use na::{Point2, Vector2};
 use noise::{NoiseFn, Perlin, Seedable};
@@ -18,14 +17,58 @@ use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
 use wasm_bindgen::prelude::*;
 
-use engine::key::{key_codes, KeyManager};
-use engine::renderer::{Renderer, Texture, TextureMap};
-use engine::{Collider, GameObject, Rend, World};
+use engine::animation::{Animator, Clip, Condition, Playback};
+use engine::assets::Assets;
+use engine::entity::{Commands, Entities, Entity};
+use engine::key::action::{ActionMap, Binding};
+use engine::key::{buttons, Code, KeyManager};
+use engine::particles::Emitter;
+use engine::physics::Body;
+use engine::renderer::{Atlas, BlendMode, QuadTransform, Renderer, TextureMap};
+use engine::transform::Transform;
+use engine::tween::{self, Ease, Tween};
+use engine::{Collider, GameObject, Phase, Rend, World};
 
 // Use `wee_alloc` as the global allocator.
+#[cfg(target_arch = "wasm32")]
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+/// How hot the fire burns, it goes out below 0.2.
+struct Heat(f32);
+
+/// Multiplies the colours of a `TexturedBox`.
+struct Tint(na::Vector3<f32>);
+
+/// Moves a `TexturedBox` forward or back from the depth its y gives it.
+struct DepthOffset(f32);
+
+struct Tree {
+    hit_count: u32,
+}
+
+/// Left behind by a chopped tree.
+struct Stump;
+
+/// A chopped tree falling over, removed once it hits the ground.
+struct Felled;
+
+/// Can be picked up and thrown into the fire.
+struct Log;
+
+/// Held by the player, chopping needs a short rest between hits.
+struct Axe {
+    last_hit: f64,
+}
+
+/// The player's body temperature, shown by the `Thermometer`.
+struct Temperature(f32);
+
+/// The logs the player carries, shown by the `Inventory`.
+struct Logs(u8);
+
+/// Draws `texture`, or the frame showing if the object has an `Animator`,
+/// anchored at the texture's pivot.
 struct TexturedBox {
     size: na::Vector2<f32>,
     texture: engine::renderer::Texture,
@@ -33,17 +76,40 @@ struct TexturedBox {
 
 impl Rend for TexturedBox {
     fn render(&self, renderer: &mut Renderer, game_object: &GameObject) {
-        renderer.draw_quad_with_tint(
-            game_object.pos,
+        let pos = game_object.get_interpolated_pos(renderer.get_alpha());
+        let texture = game_object
+            .get_component::<Animator>()
+            .map_or(&self.texture, |animator| animator.get_texture());
+        renderer.draw_quad_transformed(
+            pos,
             self.size,
-            &self.texture,
-            na::Vector3::new(
-                *game_object.props.get("tint_red").unwrap_or(&1.0),
-                *game_object.props.get("tint_green").unwrap_or(&1.0),
-                *game_object.props.get("tint_blue").unwrap_or(&1.0),
-            ),
+            texture,
+            -pos.y
+                + game_object
+                    .get_component::<DepthOffset>()
+                    .map_or(0.0, |offset| offset.0),
+            game_object
+                .get_component::<Tint>()
+                .map_or(na::Vector4::new(1.0, 1.0, 1.0, 1.0), |tint| {
+                    na::Vector4::new(tint.0.x, tint.0.y, tint.0.z, 1.0)
+                }),
+            &QuadTransform::new()
+                .with_pivot(texture.get_pivot())
+                .with_rotation(game_object.rotation)
+                .with_scale(game_object.scale),
         )
     }
+
+    fn get_range(&self, game_object: &GameObject) -> Option<f32> {
+        // From the pivot to the farthest corner, whichever way it's turned
+        let pivot = self.texture.get_pivot();
+        let corner = na::Vector2::new(
+            pivot.x.max(1.0 - pivot.x) * self.size.x,
+            pivot.y.max(1.0 - pivot.y) * self.size.y,
+        );
+        let scale = game_object.scale.x.abs().max(game_object.scale.y.abs());
+        Some(corner.norm() * scale)
+    }
 }
 
 struct Thermometer {
@@ -52,7 +118,6 @@ struct Thermometer {
     filling_pos: (f32, f32),
     texture_size: (f32, f32),
     texture_map: TextureMap,
-    temperature: f32,
 }
 
 impl Thermometer {
@@ -68,13 +133,13 @@ impl Thermometer {
             filling_pos,
             texture_size,
             texture_map,
-            temperature: 1.0,
         }
     }
 }
 
 impl Rend for Thermometer {
     fn render(&self, renderer: &mut Renderer, game_object: &GameObject) {
+        let temperature = game_object.get_component::<Temperature>().unwrap().0;
         renderer.draw_quad_with_depth(
             game_object.pos,
             self.size,
@@ -87,16 +152,16 @@ impl Rend for Thermometer {
             -0.11,
         );
         let mut filling_size = self.size.clone_owned();
-        filling_size.x *= self.temperature;
+        filling_size.x *= temperature;
         let mut filling_pos = game_object.pos.clone();
-        filling_pos.x -= self.size.x * 0.5 * (1.0 - self.temperature);
+        filling_pos.x -= self.size.x * 0.5 * (1.0 - temperature);
         renderer.draw_quad_with_depth(
             filling_pos,
             filling_size,
             &self.texture_map.get_texture_custom(
                 self.filling_pos.0,
                 self.filling_pos.1,
-                self.texture_size.0 * self.temperature,
+                self.texture_size.0 * temperature,
                 self.texture_size.1,
             ),
             -0.11,
@@ -109,7 +174,6 @@ struct Inventory {
     texture_pos: (f32, f32),
     texture_size: (f32, f32),
     texture_map: TextureMap,
-    amount: u8,
 }
 
 impl Inventory {
@@ -123,15 +187,15 @@ impl Inventory {
             texture_pos,
             texture_size,
             texture_map,
-            amount: 0,
         }
     }
 }
 
 impl Rend for Inventory {
     fn render(&self, renderer: &mut Renderer, game_object: &GameObject) {
+        let amount = game_object.get_component::<Logs>().unwrap().0;
         let mut size = self.size.clone_owned();
-        size.y *= self.amount as f32;
+        size.y *= amount as f32;
         renderer.draw_quad_with_depth(
             game_object.pos,
             size,
@@ -139,7 +203,7 @@ impl Rend for Inventory {
                 self.texture_pos.0,
                 self.texture_pos.1,
                 self.texture_size.0,
-                self.texture_size.1 * self.amount as f32,
+                self.texture_size.1 * amount as f32,
             ),
             -0.11,
         );
@@ -178,56 +242,36 @@ struct Cam {}
 
 impl Rend for Cam {
     fn render(&self, renderer: &mut Renderer, game_object: &GameObject) {
-        renderer.set_camera(game_object.pos);
+        renderer.set_camera(game_object.get_interpolated_pos(renderer.get_alpha()));
     }
 }
 
-struct Particle {
-    pos: na::Point2<f32>,
-}
+/// Lights up the surroundings of the fire.
+struct Fire;
 
-impl Particle {
-    fn new(x: f32, y: f32) -> Particle {
-        Particle {
-            pos: na::Point2::new(x, y),
-        }
+impl Rend for Fire {
+    fn render(&self, renderer: &mut Renderer, game_object: &GameObject) {
+        let heat = game_object.get_component::<Heat>().unwrap().0;
+        renderer.set_fire_heat(heat);
+        renderer.set_fire_pos(game_object.pos);
     }
 }
 
-struct Fire {
-    texture: Texture,
-
-    particles: Vec<Particle>,
+/// Draws the particles of the object's `Emitter` just in front of it.
+struct Particles {
+    /// How far from the object the particles get.
+    range: f32,
 }
 
-impl Fire {
-    fn new(texture: Texture) -> Fire {
-        Fire {
-            texture,
-            particles: vec![Particle::new(0.0, 5.0)],
+impl Rend for Particles {
+    fn render(&self, renderer: &mut Renderer, game_object: &GameObject) {
+        if let Some(emitter) = game_object.get_component::<Emitter>() {
+            emitter.render(renderer, game_object, -game_object.pos.y + 1.0);
         }
     }
-}
 
-impl Rend for Fire {
-    fn render(&self, renderer: &mut Renderer, game_object: &GameObject) {
-        let heat = *game_object.props.get("heat").unwrap();
-        renderer.set_fire_heat(heat);
-        renderer.set_fire_pos(game_object.pos);
-
-        for particle in self.particles.iter() {
-            let render_pos = na::Point2::new(
-                game_object.pos.x + particle.pos.x,
-                game_object.pos.y + particle.pos.y,
-            );
-            let size = ((heat - 0.25) / 0.75).sqrt() * 128.0;
-            renderer.draw_quad_with_depth(
-                render_pos,
-                na::Vector2::new(size, size),
-                &self.texture,
-                -game_object.pos.y + 1.0,
-            )
-        }
+    fn get_range(&self, _game_object: &GameObject) -> Option<f32> {
+        Some(self.range)
     }
 }
 
@@ -237,33 +281,164 @@ macro_rules! log {
         web_sys::console::log_1(&format!( $( $t )* ).into());
     }
 }
+mod actions {
+    pub const MOVE_UP: &str = "move_up";
+    pub const MOVE_DOWN: &str = "move_down";
+    pub const MOVE_LEFT: &str = "move_left";
+    pub const MOVE_RIGHT: &str = "move_right";
+    pub const CHOP: &str = "chop";
+    pub const INTERACT: &str = "interact";
+}
+
+/// WASD, arrow keys and vim keys for moving, space to chop and E to pick up and drop logs.
+fn default_controls() -> ActionMap {
+    let mut controls = ActionMap::new();
+    // Physical keys, so WASD stays in place on AZERTY and other layouts
+    for (action, codes) in [
+        (actions::MOVE_UP, [Code::KeyW, Code::ArrowUp, Code::KeyK]),
+        (
+            actions::MOVE_DOWN,
+            [Code::KeyS, Code::ArrowDown, Code::KeyJ],
+        ),
+        (
+            actions::MOVE_RIGHT,
+            [Code::KeyD, Code::ArrowRight, Code::KeyL],
+        ),
+        (
+            actions::MOVE_LEFT,
+            [Code::KeyA, Code::ArrowLeft, Code::KeyH],
+        ),
+    ]
+    .iter()
+    {
+        for code in codes.iter() {
+            controls.bind(action, Binding::Code(*code));
+        }
+    }
+    for (action, axis, positive, button) in [
+        (actions::MOVE_UP, 1, false, buttons::DPAD_UP),
+        (actions::MOVE_DOWN, 1, true, buttons::DPAD_DOWN),
+        (actions::MOVE_RIGHT, 0, true, buttons::DPAD_RIGHT),
+        (actions::MOVE_LEFT, 0, false, buttons::DPAD_LEFT),
+    ]
+    .iter()
+    {
+        controls.bind(
+            action,
+            Binding::Axis {
+                axis: *axis,
+                positive: *positive,
+            },
+        );
+        controls.bind(action, Binding::Button(*button));
+    }
+    controls.bind(actions::CHOP, Binding::Code(Code::Space));
+    controls.bind(actions::CHOP, Binding::Button(buttons::A));
+    controls.bind(actions::INTERACT, Binding::Code(Code::KeyE));
+    controls.bind(actions::INTERACT, Binding::Button(buttons::X));
+    controls
+}
+
 struct SomeWorld {
-    game_objects: HashMap<String, GameObject, BuildHasherDefault<hashers::fnv::FNV1aHasher32>>,
-    last_tick: f64,
+    spritesheet: Rc<Atlas>,
+    entities: Entities,
+    player: Entity,
+    /// A trigger attached to the player, overlapping what the player can reach.
+    reach: Entity,
+    fire: Entity,
+    /// Where the thermometer and inventory hang from, in screen space.
+    hud: Entity,
+    thermometer: Entity,
+    inventory: Entity,
+    controls: ActionMap,
     seconds: f64,
     death: f64,
 }
 
 const WORLD_EDGE: f64 = 10000.0;
+const PLAYER_RADIUS: f32 = 16.0;
+/// How close the player has to be to chop trees and handle logs.
+const PLAYER_REACH: f32 = 4.0;
+/// Per second, walking at full speed slows down to 10% in 205ms.
+const PLAYER_DRAG: f32 = std::f32::consts::LN_10 / 0.205;
+const PLAYER_FORCE: f32 = 5000.0;
+const PLAYER_MAX_SPEED: f32 = 375.0;
+const PLAYER_REST_SPEED: f32 = 50.0;
+/// Every log carried lowers the player's max speed this much.
+const LOG_SLOWDOWN: f32 = 100.0;
+/// How fast chopped trees fall over, in radians per second.
+const FALL_SPEED: f32 = 3.0;
+/// Flames emitted per second by a fire burning at full heat.
+const FLAME_RATE: f64 = 40.0;
+const WOOD_CHIPS_PER_HIT: usize = 6;
+/// Name of the camera following the fire once the game is lost.
+const DEATH_WATCH: &str = "deathwatch";
+
+mod layers {
+    /// Trees and the fire.
+    pub const SOLID: u32 = 1;
+    /// Logs lying around.
+    pub const ITEM: u32 = 2;
+    pub const PLAYER: u32 = 4;
+}
 
 impl SomeWorld {
-    fn new() -> SomeWorld {
-        let spritesheet = TextureMap::new(4, 4, "spritesheet".to_string());
+    fn new(controls: ActionMap) -> SomeWorld {
+        let spritesheet =
+            Rc::new(Atlas::from_json(include_str!("../www/images/spritesheet.json")).unwrap());
+        let mut entities = Entities::new();
 
-        let mut player = GameObject::new(na::Point2::new(-401.0, 1700.0));
+        let start = na::Point2::new(-401.0, 1700.0);
+        let mut player = GameObject::new(start);
         player.add_rend(Box::new(TexturedBox {
             size: na::Vector2::new(64.0, 128.0),
-            texture: spritesheet.get_texture_custom(3.0, 2.0, 1.0, 2.0),
+            texture: spritesheet.get("player").unwrap(),
         }));
         player.add_rend(Box::new(Cam {}));
+        // Logs lying around block the player just like trees and the fire
+        player.add_collider(
+            Collider::circle(PLAYER_RADIUS)
+                .with_layers(layers::PLAYER)
+                .with_mask(layers::SOLID | layers::ITEM),
+        );
+        player.add_component(Axe { last_hit: 0.0 });
+        player.add_component(
+            Body::new(1.0)
+                .with_drag(PLAYER_DRAG)
+                .with_max_speed(PLAYER_MAX_SPEED)
+                .with_rest_speed(PLAYER_REST_SPEED),
+        );
+        let player = entities.spawn(player);
+
+        // Touches whatever can be chopped, picked up or fed to the fire
+        let mut reach = GameObject::new(start);
+        reach.add_collider(
+            Collider::circle(PLAYER_RADIUS + PLAYER_REACH)
+                .as_trigger()
+                .with_layers(layers::PLAYER)
+                .with_mask(layers::SOLID | layers::ITEM),
+        );
+        let reach = entities.spawn(reach);
+        entities
+            .set_parent(reach, player, Transform::identity())
+            .unwrap();
+
         let mut fire = GameObject::new(na::Point2::new(-501.0, 1750.0));
-        fire.props.insert("heat".to_string(), 1.0);
-        fire.add_collider(Collider::new(40.0));
+        fire.add_component(Heat(1.0));
+        fire.add_collider(Collider::new(40.0).with_layers(layers::SOLID));
         fire.add_rend(Box::new(TexturedBox {
             size: na::Vector2::new(80.0, 80.0),
-            texture: spritesheet.get_texture(2, 0),
+            texture: spritesheet.get("fire_3").unwrap(),
         }));
-        fire.add_rend(Box::new(Fire::new(spritesheet.get_texture(0, 0))));
+        fire.add_rend(Box::new(Fire));
+        fire.add_rend(Box::new(Particles { range: 150.0 }));
+        fire.add_component(SomeWorld::flames(&spritesheet));
+        let mut animator = SomeWorld::fire_animator(&spritesheet);
+        animator.play("fire_3").unwrap();
+        fire.add_component(animator);
+        let fire = entities.spawn(fire);
+
+        let hud = entities.spawn(GameObject::new(na::Point2::new(0.0, 0.0)));
 
         let mut thermometer = GameObject::new(na::Point2::new(0.0, 150.0));
         thermometer.add_rend(Box::new(Thermometer::new(
@@ -272,6 +447,15 @@ impl SomeWorld {
             (2.0, 0.5),
             engine::renderer::TextureMap::new(4, 1, "ui".to_string()),
         )));
+        thermometer.add_component(Temperature(1.0));
+        let thermometer = entities.spawn(thermometer);
+        entities
+            .set_parent(
+                thermometer,
+                hud,
+                Transform::new(na::Vector2::new(0.0, 150.0)),
+            )
+            .unwrap();
 
         let mut inventory = GameObject::new(na::Point2::new(-50.0, 0.0));
         inventory.add_rend(Box::new(Inventory::new(
@@ -279,6 +463,11 @@ impl SomeWorld {
             (1.0, 1.0),
             engine::renderer::TextureMap::new(4, 1, "ui".to_string()),
         )));
+        inventory.add_component(Logs(0));
+        let inventory = entities.spawn(inventory);
+        entities
+            .set_parent(inventory, hud, Transform::new(na::Vector2::new(-50.0, 0.0)))
+            .unwrap();
 
         let mut grass = GameObject::new(na::Point2::new(0.0, 0.0));
         grass.add_rend(Box::new(Grass::new(engine::renderer::TextureMap::new(
@@ -286,14 +475,7 @@ impl SomeWorld {
             1,
             "grass".to_string(),
         ))));
-
-        let mut game_objects =
-            HashMap::with_hasher(BuildHasherDefault::<hashers::fnv::FNV1aHasher32>::default());
-        game_objects.insert("player".to_string(), player);
-        game_objects.insert("fire".to_string(), fire);
-        game_objects.insert("thermometer".to_string(), thermometer);
-        game_objects.insert("inventory".to_string(), inventory);
-        game_objects.insert("grass".to_string(), grass);
+        entities.spawn(grass);
 
         const TREE_COLLISION_RANGE: f32 = 17.0;
         let mut tree_i = 0;
@@ -317,353 +499,383 @@ impl SomeWorld {
             let mut tree =
                 // GameObject::new(Point2::new(world_coords.x as f32, world_coords.y as f32));
                 GameObject::new(Point2::new(*x, *y));
-            tree.add_collider(Collider::new(TREE_COLLISION_RANGE));
+            tree.add_collider(Collider::new(TREE_COLLISION_RANGE).with_layers(layers::SOLID));
             tree.add_rend(Box::new(TexturedBox {
                 size: na::Vector2::new(150.0, 150.0),
-                texture: spritesheet.get_texture(1, 0),
+                texture: spritesheet.get("tree").unwrap(),
             }));
-            tree.props.insert("tree".to_string(), tree_i as f32);
-            game_objects.insert(format!("tree{}", tree_i), tree);
+            tree.add_component(Tree { hit_count: 0 });
+            entities.spawn(tree);
             tree_i += 1;
         }
         // }
         log::debug!("Got trees: {}", tree_i);
         SomeWorld {
-            game_objects,
-            last_tick: 0.0,
+            spritesheet,
+            entities,
+            player,
+            reach,
+            fire,
+            hud,
+            thermometer,
+            inventory,
+            controls,
             seconds: 0.0,
             death: 0.0,
         }
     }
 
-    fn get_direction(key_manager: &KeyManager) -> na::Vector2<f32> {
-        let mut direction: na::Vector2<f32> = na::Vector2::<f32>::zeros();
-        if key_manager.key_pressed(key_codes::W)
-            || key_manager.key_pressed(key_codes::UP_ARROW)
-            || key_manager.key_pressed(key_codes::K)
-        {
-            direction.y += 1.0;
-        }
-        if key_manager.key_pressed(key_codes::S)
-            || key_manager.key_pressed(key_codes::DOWN_ARROW)
-            || key_manager.key_pressed(key_codes::J)
-        {
-            direction.y += -1.0;
-        }
-        if key_manager.key_pressed(key_codes::D)
-            || key_manager.key_pressed(key_codes::RIGHT_ARROW)
-            || key_manager.key_pressed(key_codes::L)
-        {
-            direction.x += 1.0;
-        }
-        if key_manager.key_pressed(key_codes::A)
-            || key_manager.key_pressed(key_codes::LEFT_ARROW)
-            || key_manager.key_pressed(key_codes::H)
-        {
-            direction.x += -1.0;
-        }
+    fn get_direction(&self, key_manager: &KeyManager) -> na::Vector2<f32> {
+        let direction = na::Vector2::new(
+            self.controls
+                .axis(actions::MOVE_LEFT, actions::MOVE_RIGHT, key_manager),
+            self.controls
+                .axis(actions::MOVE_DOWN, actions::MOVE_UP, key_manager),
+        );
+        // Keys push both axes all the way, a half tilted stick walks slower
         let norm = direction.norm();
-        if norm > 0.0 {
+        if norm > 1.0 {
             direction / norm
         } else {
             direction
         }
     }
 
-    fn cut_down_tree(
-        spritesheet: &TextureMap,
-        stumps: &mut HashMap<String, GameObject>,
-        tree_name: &String,
-        tree: &&mut GameObject,
+    fn chop(
+        &mut self,
+        entity: Entity,
+        key_manager: &KeyManager,
+        timestamp: f64,
+        spritesheet: &Atlas,
     ) {
+        let axe = self.entities.get_component_mut::<Axe>(self.player).unwrap();
+        if (timestamp - axe.last_hit) <= 250.0 || !self.controls.down(actions::CHOP, key_manager) {
+            return;
+        }
+        log::debug!("Whack!");
+        dfhh();
+        axe.last_hit = timestamp;
+
+        let game_object = self.entities.get_mut(entity).unwrap();
+        let tree = game_object.get_component_mut::<Tree>().unwrap();
+        tree.hit_count += 1;
+        if tree.hit_count >= 5 {
+            let pos = game_object.pos;
+            SomeWorld::cut_down_tree(spritesheet, &mut self.entities.commands(), entity, pos);
+        } else {
+            if !game_object.has_component::<Tint>() {
+                game_object.add_component(Tint(na::Vector3::repeat(1.0)));
+            }
+            let flash = Tween::new(
+                na::Vector3::new(1.0, 1.0, 10.0),
+                na::Vector3::repeat(1.0),
+                1000.0,
+                |game_object, tint| game_object.get_component_mut::<Tint>().unwrap().0 = tint,
+            );
+            tween::start(game_object, flash.with_ease(Ease::ExpoOut));
+
+            if !game_object.has_component::<Animator>() {
+                game_object.add_component(SomeWorld::tree_animator(spritesheet));
+            }
+            if !game_object.has_component::<Emitter>() {
+                let seed =
+                    (game_object.pos.x.to_bits() as u64) << 32 | game_object.pos.y.to_bits() as u64;
+                game_object.add_component(SomeWorld::wood_chips(spritesheet, seed));
+                game_object.add_rend(Box::new(Particles { range: 150.0 }));
+            }
+            let transform = game_object.get_transform();
+            let chips = game_object.get_component_mut::<Emitter>().unwrap();
+            chips.burst(&transform, WOOD_CHIPS_PER_HIT);
+            let animator = game_object.get_component_mut::<Animator>().unwrap();
+            animator.restart("hit").unwrap();
+        }
+    }
+
+    fn pick_up(&mut self, entity: Entity, key_manager: &KeyManager) {
+        let inventory = self
+            .entities
+            .get_component_mut::<Logs>(self.inventory)
+            .unwrap();
+        if self.controls.down(actions::INTERACT, key_manager) && inventory.0 < 3 {
+            duue();
+            inventory.0 += 1;
+            self.entities.commands().despawn(entity);
+        }
+    }
+
+    fn drop_off(&mut self, key_manager: &KeyManager) {
+        let inventory = self
+            .entities
+            .get_component_mut::<Logs>(self.inventory)
+            .unwrap();
+        if self.controls.down(actions::INTERACT, key_manager) && inventory.0 > 0 {
+            quipp();
+            inventory.0 -= 1;
+            let heat = self.entities.get_component_mut::<Heat>(self.fire).unwrap();
+            heat.0 = f32::min(1.0, heat.0 + 0.3);
+        }
+    }
+
+    /// Flames rising from the fire, emitted faster the hotter it burns.
+    fn flames(spritesheet: &Atlas) -> Emitter {
+        Emitter::new(spritesheet.get("flame").unwrap(), 46)
+            .with_rate(FLAME_RATE)
+            .with_lifetime(500.0, 900.0)
+            .with_offset(Vector2::new(-20.0, 0.0), Vector2::new(20.0, 15.0))
+            .with_velocity(Vector2::new(-15.0, 40.0), Vector2::new(15.0, 90.0))
+            .with_acceleration(Vector2::new(-10.0, 20.0), Vector2::new(10.0, 40.0))
+            .with_size(56.0, 8.0)
+            .with_color(
+                na::Vector4::new(1.0, 0.8, 0.4, 0.8),
+                na::Vector4::new(0.8, 0.2, 0.0, 0.0),
+            )
+    }
+
+    /// Bits of wood flying off a tree when it's hit.
+    fn wood_chips(spritesheet: &Atlas, seed: u64) -> Emitter {
+        Emitter::new(spritesheet.get("log").unwrap(), seed)
+            .with_lifetime(300.0, 500.0)
+            .with_offset(Vector2::new(-10.0, 30.0), Vector2::new(10.0, 60.0))
+            .with_velocity(Vector2::new(-120.0, 60.0), Vector2::new(120.0, 180.0))
+            .with_acceleration(Vector2::new(0.0, -600.0), Vector2::new(0.0, -600.0))
+            .with_size(14.0, 6.0)
+            .with_color(
+                na::Vector4::repeat(1.0),
+                na::Vector4::new(1.0, 1.0, 1.0, 0.0),
+            )
+            .with_blend(BlendMode::Alpha)
+    }
+
+    /// Flickers between the fire of each heat level and the one below it.
+    fn fire_animator(spritesheet: &Atlas) -> Animator {
+        let frame = |level: i32| spritesheet.get(&format!("fire_{}", level.max(0))).unwrap();
+        let clip = |level: i32| {
+            Clip::new(Playback::Loop)
+                .with_frame(frame(level), 400.0)
+                .with_frame(frame(level - 1), 80.0)
+        };
+        (1..4).fold(Animator::new("fire_0", clip(0)), |animator, level| {
+            animator.with_clip(&format!("fire_{}", level), clip(level))
+        })
+    }
+
+    /// Spawns a camera, named `DEATH_WATCH`, that glides from where the player
+    /// was to the fire.
+    fn spawn_death_watch(&mut self, player_pos: Point2<f32>, fire_pos: Point2<f32>) {
+        let mut death_watch = GameObject::new(player_pos);
+        death_watch.add_rend(Box::new(Cam {}));
+        tween::start(
+            &mut death_watch,
+            Tween::move_to(fire_pos, 400.0).with_ease(Ease::CubicOut),
+        );
+        let death_watch = self.entities.spawn(death_watch);
+        self.entities.set_name(death_watch, DEATH_WATCH).unwrap();
+    }
+
+    /// Shakes a tree for a moment when it's hit.
+    fn tree_animator(spritesheet: &Atlas) -> Animator {
+        Animator::new(
+            "standing",
+            Clip::new(Playback::Loop).with_frame(spritesheet.get("tree").unwrap(), 1000.0),
+        )
+        .with_clip(
+            "hit",
+            Clip::new(Playback::Once).with_frame(spritesheet.get("tree_hit").unwrap(), 150.0),
+        )
+        .with_transition("hit", "standing", Condition::Finished)
+    }
+
+    fn cut_down_tree(spritesheet: &Atlas, commands: &mut Commands, tree: Entity, pos: Point2<f32>) {
         // Chopping trees creates a stump and a log
-        let mut stump = GameObject::new(tree.pos.clone());
+        commands.despawn(tree);
+        let mut stump = GameObject::new(pos);
         stump.add_rend(Box::new(TexturedBox {
             size: na::Vector2::new(128.0, 128.0),
-            texture: spritesheet.get_texture(3, 0),
+            texture: spritesheet.get("stump").unwrap(),
+        }));
+        stump.add_component(Stump);
+        // Behind the felled tree, which falls from the same spot
+        stump.add_component(DepthOffset(-1.0));
+        commands.spawn(stump);
+        let mut felled = GameObject::new(pos);
+        felled.add_rend(Box::new(TexturedBox {
+            size: na::Vector2::new(150.0, 150.0),
+            texture: spritesheet.get("tree").unwrap(),
         }));
-        stump
-            .props
-            .insert("stump".to_string(), *tree.props.get("tree").unwrap());
-        stumps.insert(format!("stump{}", tree_name), stump);
-        let mut log = GameObject::new(&tree.pos + Vector2::new(32.0, 32.0));
-        log.add_collider(Collider::new(16.0));
+        felled.add_component(Felled);
+        commands.spawn(felled);
+        let mut log = GameObject::new(pos + Vector2::new(32.0, 32.0));
+        log.add_collider(Collider::new(16.0).with_layers(layers::ITEM));
         log.add_rend(Box::new(TexturedBox {
             size: na::Vector2::new(64.0, 64.0),
-            texture: spritesheet.get_texture(0, 2),
+            texture: spritesheet.get("log").unwrap(),
         }));
-        log.props
-            .insert("log".to_string(), *tree.props.get("tree").unwrap());
-        stumps.insert(format!("log{}", tree_name), log);
+        log.add_component(Log);
+        commands.spawn(log);
     }
 }
 
 impl engine::World for SomeWorld {
-    fn tick(&mut self, key_manager: &KeyManager, timestamp: f64) {
-        if (timestamp - self.last_tick) > 500.0 {
-            self.last_tick = timestamp;
-            return;
-        }
-        let deltatime = timestamp - self.last_tick;
-        self.seconds += deltatime / 1000.0;
-        let spritesheet = TextureMap::new(4, 4, "spritesheet".to_string());
-        let direction = SomeWorld::get_direction(key_manager);
-
-        let player_dead = !self.game_objects.contains_key("player");
-
-        let fire_pos = self.game_objects.get("fire").unwrap().pos;
-        match self.game_objects.get_mut("deathwatch") {
-            Some(death_watch) => {
-                if (na::distance(&death_watch.pos, &fire_pos)) > 1.0 {
-                    death_watch.pos = na::Point2::new(
-                        fire_pos.x * 0.2 + death_watch.pos.x * 0.8,
-                        fire_pos.y * 0.2 + death_watch.pos.y * 0.8,
-                    );
-                }
-            }
-            None => (),
-        }
+    fn update(&mut self, key_manager: &KeyManager, dt: f64) {
+        self.seconds += dt / 1000.0;
+        let timestamp = self.seconds * 1000.0;
+        let spritesheet = Rc::clone(&self.spritesheet);
+        let direction = self.get_direction(key_manager);
+
+        let player_dead = !self.entities.contains(self.player);
+
+        let fire_pos = self.entities.get(self.fire).unwrap().pos;
 
         {
-            let fire = self.game_objects.get_mut("fire").unwrap();
-            let mut heat = *fire.props.get("heat").unwrap();
+            let mut heat = self.entities.get_component::<Heat>(self.fire).unwrap().0;
             let mut mul = 0.4; // bigger value = light goes out slower
             if player_dead {
                 mul = 0.2;
             }
-            heat *= 1.0 - (timestamp - self.last_tick) as f32 / (100000.0 * mul);
+            heat *= 1.0 - dt as f32 / (100000.0 * mul);
             if heat < 0.2 {
                 exeunt(self.death);
             }
             if heat < 0.25 && !player_dead {
-                if !self.game_objects.contains_key("deathwatch") {
-                    let window = web_sys::window().unwrap();
-                    window
-                        .alert_with_message(
-                            "You let your fire die out and are now doomed to die as well.",
-                        )
-                        .unwrap();
+                if self.entities.find(DEATH_WATCH).is_none() {
+                    alert("You let your fire die out and are now doomed to die as well.");
                     self.death = self.seconds.round();
 
-                    let player_pos = self.game_objects.get("player").unwrap().pos;
-                    let mut death_watch = GameObject::new(player_pos);
-                    death_watch.add_rend(Box::new(Cam {}));
-                    self.game_objects
-                        .insert("deathwatch".to_string(), death_watch);
+                    let player_pos = self.entities.get(self.player).unwrap().pos;
+                    self.spawn_death_watch(player_pos, fire_pos);
                 }
-                let fire = self.game_objects.get_mut("fire").unwrap();
-                *fire.props.get_mut("heat").unwrap() = heat;
+                self.entities
+                    .get_component_mut::<Heat>(self.fire)
+                    .unwrap()
+                    .0 = heat;
                 return;
             }
             set_volume(f32::max(0.0, heat - 0.25));
-            *fire.props.get_mut("heat").unwrap() = heat;
-
-            // log::debug!("{:?}", fire.props);
 
-            let fire = self.game_objects.get_mut("fire").unwrap();
-            fire.rend[0] = Box::new(TexturedBox {
-                size: na::Vector2::new(80.0, 80.0),
-                texture: spritesheet.get_texture((heat * 4.0 - 1.0) as i32, 1),
-            });
+            let fire = self.entities.get_mut(self.fire).unwrap();
+            fire.get_component_mut::<Heat>().unwrap().0 = heat;
+            let level = ((heat * 4.0 - 1.0) as i32).clamp(0, 3);
+            fire.get_component_mut::<Animator>()
+                .unwrap()
+                .play(&format!("fire_{}", level))
+                .unwrap();
+            fire.get_component_mut::<Emitter>()
+                .unwrap()
+                .set_rate(FLAME_RATE * heat as f64);
         }
 
-        if self.game_objects.contains_key("player") {
-            let mut inventory = self.game_objects.get_mut("inventory").unwrap().rend[0]
-                .downcast_mut::<Inventory>()
+        if self.entities.contains(self.player) {
+            let inventory = self
+                .entities
+                .get_component::<Logs>(self.inventory)
                 .unwrap()
-                .amount;
-            let player = self.game_objects.get("player").unwrap();
-
-            let mut speed = player.speed.clone();
-            speed += direction * ((timestamp - self.last_tick) as f32 * 0.1);
-
-            let div = (timestamp - self.last_tick) / 205.0;
-            speed *= 0.1f32.powf(div as f32);
-
-            let norm = speed.norm();
-            let max_speed = 7.5 - inventory as f32 * 2.0;
-            if norm > max_speed {
-                speed *= max_speed / norm;
-            }
-
-            if norm < 1.0 {
-                speed *= 0.0;
+                .0;
+            let player = self.entities.get_mut(self.player).unwrap();
+            let player_pos = player.pos;
+            let body = player.get_component_mut::<Body>().unwrap();
+            // The engine moves the player after this update
+            body.apply_force(direction * PLAYER_FORCE);
+            body.set_max_speed(PLAYER_MAX_SPEED - inventory as f32 * LOG_SLOWDOWN);
+            // Face the way the player walks
+            if direction.x != 0.0 {
+                player.scale.x = player.scale.x.abs() * direction.x.signum();
             }
 
-            let player_pos = player.pos.clone();
-            let mut last_player_hit: f32 = *player.props.get("last_hit").unwrap_or(&0.0);
-            let mut stumps = HashMap::new();
-            self.game_objects.retain(|key, game_object| {
-                if let Some(collider) = game_object.get_collider() {
-                    if collider.collide(&game_object, &player_pos, &mut speed) {
-                        if (timestamp - last_player_hit as f64) > 250.0
-                            && key_manager.key_down(key_codes::SPACE)
-                            && game_object.props.contains_key("tree")
-                        {
-                            log::debug!("Whack!");
-                            dfhh();
-                            last_player_hit = timestamp as f32;
-                            let hit_count = game_object.props.get("hit_count").unwrap_or(&0.0);
-                            if hit_count + 1.0 >= 5.0 {
-                                SomeWorld::cut_down_tree(
-                                    &spritesheet,
-                                    &mut stumps,
-                                    key,
-                                    &game_object,
-                                );
-                                return false;
-                            } else {
-                                // log::debug!("{:?}", game_object.props);
-                                game_object
-                                    .props
-                                    .insert("hit_count".to_string(), hit_count + 1.0);
-                                // if !game_object.props.contains_key("tint_green") {
-                                //     game_object.props.insert("tint_green".to_string(), 1.0);
-                                // }
-                                // *game_object.props.get_mut("tint_green").unwrap() = 0.2;
-
-                                // if !game_object.props.contains_key("tint_red") {
-                                //     game_object.props.insert("tint_red".to_string(), 1.0);
-                                // }
-                                // *game_object.props.get_mut("tint_red").unwrap() = 0.2;
-
-                                if !game_object.props.contains_key("tint_blue") {
-                                    game_object.props.insert("tint_blue".to_string(), 1.0);
-                                }
-                                *game_object.props.get_mut("tint_blue").unwrap() = 10.0;
-
-                                game_object.rend.clear();
-                                game_object.add_rend(Box::new(TexturedBox {
-                                    size: na::Vector2::new(128.0, 128.0),
-                                    texture: spritesheet.get_texture(2, 0),
-                                }));
-                            }
-                        } else if key_manager.key_down(key_codes::E)
-                            && game_object.props.contains_key("log")
-                            && inventory < 3
-                        {
-                            //         // Picking up a log
-                            duue();
-                            inventory += 1;
-                            return false;
-                        }
-                    }
-                }
-                true
-            });
-            if !self
-                .game_objects
-                .get("player")
-                .unwrap()
-                .props
-                .contains_key("last_hit")
-            {
-                self.game_objects
-                    .get_mut("player")
-                    .unwrap()
-                    .props
-                    .insert("last_hit".to_string(), 0.0);
-            }
-            *self
-                .game_objects
-                .get_mut("player")
-                .unwrap()
-                .props
-                .get_mut("last_hit")
-                .unwrap() = last_player_hit;
-
-            self.game_objects.extend(stumps);
-            let fire = self.game_objects.get_mut("fire").unwrap();
-            let mut heat = *fire.props.get("heat").unwrap();
-            // Dropping off logs:
-            if inventory > 0 {
-                if fire
-                    .get_collider()
-                    .as_ref()
-                    .unwrap()
-                    .collide(&fire, &player_pos, &mut speed)
-                    && key_manager.key_down(key_codes::E)
-                {
-                    quipp();
-                    inventory -= 1;
-                    heat = f32::min(1.0, heat + 0.3);
+            // Whatever was within reach at the end of the last update
+            let within_reach: Vec<Entity> = self
+                .entities
+                .get_collision_events()
+                .iter()
+                .filter(|event| event.trigger && event.phase != Phase::Exit)
+                .filter_map(|event| event.other(self.reach))
+                .collect();
+            for entity in within_reach {
+                let game_object = match self.entities.get(entity) {
+                    Some(game_object) => game_object,
+                    None => continue,
+                };
+                if game_object.has_component::<Tree>() {
+                    self.chop(entity, key_manager, timestamp, &spritesheet);
+                } else if game_object.has_component::<Log>() {
+                    self.pick_up(entity, key_manager);
+                } else if entity == self.fire {
+                    self.drop_off(key_manager);
                 }
             }
-            heat *= 1.0 - (timestamp - self.last_tick) as f32 / 100000.0;
-            *fire.props.get_mut("heat").unwrap() = heat;
-            self.game_objects.get_mut("inventory").unwrap().rend[0]
-                .downcast_mut::<Inventory>()
+
+            let heat = &mut self
+                .entities
+                .get_component_mut::<Heat>(self.fire)
                 .unwrap()
-                .amount = inventory;
+                .0;
+            *heat *= 1.0 - dt as f32 / 100000.0;
+            let heat = *heat;
 
-            let player = self.game_objects.get("player").unwrap();
-            let fire = self.game_objects.get("fire").unwrap();
-            let conductivity = (timestamp - self.last_tick) as f32 / 8000.0;
+            let player = self.entities.get(self.player).unwrap();
+            let fire = self.entities.get(self.fire).unwrap();
+            let conductivity = dt as f32 / 8000.0;
             let c = 600.0; // smaller number == sharper drop-off
             let r2 = ((f32::max(0.0, (player.pos - fire.pos).norm() - 48.0) + c) / c).powi(2);
-            self.game_objects.get_mut("thermometer").unwrap().rend[0]
-                .downcast_mut::<Thermometer>()
-                .unwrap()
-                .temperature *= 1.0 - conductivity;
-            self.game_objects.get_mut("thermometer").unwrap().rend[0]
-                .downcast_mut::<Thermometer>()
-                .unwrap()
-                .temperature += 1.0 / r2 * heat * conductivity;
-
-            let player_temp = self.game_objects.get("thermometer").unwrap().rend[0]
-                .downcast_ref::<Thermometer>()
-                .unwrap()
-                .temperature;
-            let mut player = self.game_objects.get_mut("player").unwrap();
-            player.pos += speed * (timestamp - self.last_tick) as f32 * 0.05;
-            player.speed = speed;
-
-            let player_pos = player.pos;
-
+            let temperature = self
+                .entities
+                .get_component_mut::<Temperature>(self.thermometer)
+                .unwrap();
+            temperature.0 *= 1.0 - conductivity;
+            temperature.0 += 1.0 / r2 * heat * conductivity;
+
+            let player_temp = temperature.0;
             if player_temp <= 0.3 {
-                player.rend[0].downcast_mut::<TexturedBox>().unwrap().size =
-                    na::Vector2::new(0.5, 1.0) * 128.0 * ((player_temp - 0.25) / 0.05)
+                let player = self.entities.get_mut(self.player).unwrap();
+                let shrink = (player_temp - 0.25) / 0.05;
+                player.scale = na::Vector2::new(player.scale.x.signum() * shrink, shrink);
             }
             if player_temp < 0.25 {
-                let mut death_watch = GameObject::new(player_pos);
-                death_watch.add_rend(Box::new(Cam {}));
-                let window = web_sys::window().unwrap();
-                window.alert_with_message("You let yourself underheat and were vanquished by the cold, leaving your fire to decay to a smoulder.").unwrap();
+                alert("You let yourself underheat and were vanquished by the cold, leaving your fire to decay to a smoulder.");
                 self.death = self.seconds.round();
 
-                self.game_objects
-                    .insert("deathwatch".to_string(), death_watch);
-                self.game_objects.remove("player");
-                self.game_objects.remove("thermometer");
-                self.game_objects.remove("inventory");
+                self.spawn_death_watch(player_pos, fire_pos);
+                self.entities.despawn(self.player);
+                // Takes the thermometer and inventory with it
+                self.entities.despawn(self.hud);
             }
         }
-        let damp = 0.1f64.powf((timestamp - self.last_tick) / 1000.0) as f32;
-        for game_object in self.game_objects.values_mut() {
-            if game_object.props.contains_key("tint_red") {
-                let tint_red = game_object.props.get("tint_red").unwrap();
-                *game_object.props.get_mut("tint_red").unwrap() =
-                    damp * tint_red + (1.0 - damp) * 1.0
-            }
-            if game_object.props.contains_key("tint_blue") {
-                let tint_blue = game_object.props.get("tint_blue").unwrap();
-                *game_object.props.get_mut("tint_blue").unwrap() =
-                    damp * tint_blue + (1.0 - damp) * 1.0
-            }
-            if game_object.props.contains_key("tint_green") {
-                let tint_green = game_object.props.get("tint_green").unwrap();
-                *game_object.props.get_mut("tint_green").unwrap() =
-                    damp * tint_green + (1.0 - damp) * 1.0
+        let felled: Vec<Entity> = self
+            .entities
+            .query::<Felled>()
+            .map(|(entity, _, _)| entity)
+            .collect();
+        for entity in felled {
+            let tree = self.entities.get_mut(entity).unwrap();
+            tree.rotation -= FALL_SPEED * dt as f32 / 1000.0;
+            if tree.rotation <= -std::f32::consts::FRAC_PI_2 {
+                self.entities.commands().despawn(entity);
             }
         }
-        self.last_tick = timestamp;
     }
 
     fn get_game_objects(&self) -> Vec<&GameObject> {
-        self.game_objects.values().collect()
+        self.entities.get_game_objects()
+    }
+
+    fn get_game_objects_mut(&mut self) -> Vec<&mut GameObject> {
+        self.entities.get_game_objects_mut()
+    }
+
+    fn get_entities_mut(&mut self) -> Option<&mut Entities> {
+        Some(&mut self.entities)
+    }
+
+    fn load_assets(&mut self, assets: &mut Assets) {
+        assets.load_url(
+            self.spritesheet.get_texture_name(),
+            "images/spritesheet.png",
+        );
+        assets.load_url("grass", "images/tuustimaa.png");
+        assets.load_url("ui", "images/ui.png");
     }
 }
 
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(module = "/www/def.js")]
 extern "C" {
     fn set_volume(v: f32);
@@ -674,13 +886,83 @@ extern "C" {
     fn exeunt(time: f64);
 }
 
+#[cfg(target_arch = "wasm32")]
+fn alert(message: &str) {
+    let window = web_sys::window().unwrap();
+    window.alert_with_message(message).unwrap();
+}
+
+// The page hooks only exist in the browser, native builds get silent stand-ins
+// so the world can be simulated in tests.
+#[cfg(not(target_arch = "wasm32"))]
+mod page {
+    pub fn set_volume(_v: f32) {}
+    pub fn dfhh() {}
+    pub fn duue() {}
+    pub fn quipp() {}
+
+    pub fn exeunt(_time: f64) {}
+
+    pub fn alert(message: &str) {
+        log::info!("{}", message);
+    }
+}
+#[cfg(not(target_arch = "wasm32"))]
+use page::*;
+
+/// Players can rebind the controls by putting a profile (see
+/// `engine::key::action`) into the `controls` entry of local storage. Actions
+/// the profile leaves out keep their default bindings.
+fn load_controls() -> ActionMap {
+    let mut controls = default_controls();
+    let profile = web_sys::window()
+        .and_then(|window| window.local_storage().ok().flatten())
+        .and_then(|storage| storage.get_item("controls").ok().flatten());
+    match profile.map(|profile| ActionMap::from_profile(&profile)) {
+        Some(Ok(stored)) => controls.merge(&stored),
+        Some(Err(error)) => log::warn!("Ignoring stored controls: {}", error),
+        None => (),
+    }
+    controls
+}
+
 #[wasm_bindgen]
 pub fn run() {
     #[cfg(debug_assertions)]
     console_error_panic_hook::set_once();
     wasm_logger::init(wasm_logger::Config::default());
     log::info!("Game starting");
-    engine::start(Box::new(SomeWorld::new()) as Box<dyn World>).unwrap();
+    engine::start(Box::new(SomeWorld::new(load_controls())) as Box<dyn World>).unwrap();
 }
 
 mod trees;
+
+#[cfg(test)]
+mod tests {
+    use engine::driver::NativeDriver;
+    use engine::key::KeyManager;
+    use engine::renderer::{Renderer, SoftwareBackend};
+    use engine::Engine;
+
+    use super::*;
+
+    #[test]
+    fn simulates_some_world_natively() {
+        let mut engine = Engine::new(
+            Box::new(SomeWorld::new(default_controls())),
+            KeyManager::headless(),
+            Renderer::new(Box::new(SoftwareBackend::new())),
+        );
+        let mut driver = NativeDriver::new(1000.0 / 60.0, na::Vector2::new(80.0, 60.0));
+        driver.run(&mut engine, 600);
+
+        let world = engine.get_world();
+        assert!(world.get_game_objects().len() > trees::TREES.len());
+        let backend = engine
+            .get_renderer()
+            .get_backend()
+            .downcast_ref::<SoftwareBackend>()
+            .unwrap();
+        assert_eq!(backend.get_frame_count(), 600);
+    }
+}
@@ -34,8 +34,21 @@ module.exports = {
     },
     module: {
         rules: [
+            {
+                // Fetched by the game at a fixed URL, see `load_assets`
+                test: /images[\\/](spritesheet|tuustimaa|ui)\.png$/,
+                use: [
+                    {
+                        loader: "file-loader",
+                        options: {
+                            name: "images/[name].[ext]",
+                        },
+                    },
+                ],
+            },
             {
                 test: /\.(png|m4a|mp3)$/,
+                exclude: /images[\\/](spritesheet|tuustimaa|ui)\.png$/,
                 use: [
                     {
                         loader: "url-loader",
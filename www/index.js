@@ -1,7 +1,7 @@
-import spritesheet from "./images/spritesheet.png";
-import tuustid from "./images/tuustid.png";
-import ui from "./images/ui.png";
-import grass from "./images/tuustimaa.png";
+// Textures are fetched by the game itself, these only get them emitted
+import "./images/spritesheet.png";
+import "./images/ui.png";
+import "./images/tuustimaa.png";
 import character from "./images/character.png";
 import ludum46 from "./music/ludum46.m4a";
 import dfhh from "./music/dfhh.mp3";
@@ -78,32 +78,12 @@ function startGame() {
 }
 
 const startButton = document.getElementById("start_button");
-
-let loadCount = 0;
-let loadify = () => {
-    ++loadCount;
-    if (loadCount === 4) {
-        startButton.onclick = startGame;
-        startButton.disabled = false;
-    }
-};
+startButton.onclick = startGame;
+startButton.disabled = false;
 
 const img0 = document.getElementById("character");
 img0.src = character;
 
-const img = document.getElementById("spritesheet");
-img.src = spritesheet;
-img.onload = loadify;
-const img2 = document.getElementById("tuustid");
-img2.src = tuustid;
-img2.onload = loadify;
-const img3 = document.getElementById("ui");
-img3.src = ui;
-img3.onload = loadify;
-const img4 = document.getElementById("grass");
-img4.src = grass;
-img4.onload = loadify;
-
 export function set_volume(v) {
     return (audio.volume = v);
 }